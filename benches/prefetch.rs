@@ -0,0 +1,73 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use sculptor::{prefetch, FileIO, SerdeStr};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::PathBuf};
+
+#[derive(Serialize, Deserialize)]
+struct Conf {
+    entries: Vec<String>,
+}
+impl SerdeStr for Conf {
+    fn de_from_str(string: &str) -> Result<Self, io::Error> {
+        Ok(serde_json::from_str(string)?)
+    }
+    fn ser_to_string(&self) -> Result<String, io::Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Drops the files from the page cache where the OS allows it, so every load is cold
+fn evict(paths: &[PathBuf]) {
+    #[cfg(target_os = "linux")]
+    for path in paths {
+        use std::os::unix::io::AsRawFd;
+        let file = fs::File::open(path).unwrap();
+        file.sync_all().unwrap();
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = paths;
+}
+
+fn load_all(paths: &[PathBuf]) {
+    for path in paths {
+        FileIO::<Conf>::new(path.clone()).load().unwrap();
+    }
+}
+
+fn prefetch_startup(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join("sculptor_bench_prefetch");
+    fs::create_dir_all(&dir).unwrap();
+    let conf = Conf {
+        entries: (0..20_000).map(|i| format!("entry {}", i)).collect(),
+    };
+    let paths: Vec<_> = (0..12)
+        .map(|i| dir.join(format!("conf_{}.json", i)))
+        .collect();
+    for path in &paths {
+        FileIO::new(path.clone()).save(&conf).unwrap();
+    }
+    let mut group = c.benchmark_group("startup_loads");
+    group.bench_function("cold", |b| {
+        b.iter_batched(
+            || evict(&paths),
+            |()| load_all(&paths),
+            BatchSize::PerIteration,
+        )
+    });
+    group.bench_function("prefetched", |b| {
+        b.iter_batched(
+            || evict(&paths),
+            |()| {
+                prefetch(&paths);
+                load_all(&paths)
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, prefetch_startup);
+criterion_main!(benches);
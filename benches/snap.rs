@@ -0,0 +1,20 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use sculptor::snap_reader_with_capacity;
+use std::hint::black_box;
+
+fn snap_capacity(c: &mut Criterion) {
+    let data = vec![0xa5u8; 16 * 1024 * 1024];
+    let mut group = c.benchmark_group("snap_reader_with_capacity");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    for capacity in [8 * 1024, 64 * 1024, 1024 * 1024] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(capacity),
+            &capacity,
+            |b, &cap| b.iter(|| snap_reader_with_capacity(black_box(&data[..]), cap).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, snap_capacity);
+criterion_main!(benches);
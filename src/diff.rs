@@ -0,0 +1,126 @@
+use serde_json::Value;
+
+/// A difference between two JSON documents, at a dotted path such as `server.port`;
+/// array elements are addressed by index, and the empty path is the document itself
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonChange {
+    Added {
+        path: String,
+        value: Value,
+    },
+    Removed {
+        path: String,
+        value: Value,
+    },
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl JsonChange {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added { path, .. } | Self::Removed { path, .. } | Self::Changed { path, .. } => {
+                path
+            }
+        }
+    }
+}
+
+/// The changes turning `old` into `new`, depth first and in key order
+///
+/// Objects are compared key by key and arrays index by index, so inserting at the front
+/// of an array reports every later element as changed.
+pub fn diff_json(old: &Value, new: &Value) -> Vec<JsonChange> {
+    diff_from(Some(old), new)
+}
+
+/// [`diff_json`] where a missing `old` reports `new` as added at the empty path
+pub(crate) fn diff_from(old: Option<&Value>, new: &Value) -> Vec<JsonChange> {
+    let mut changes = Vec::new();
+    diff_at(String::new(), old, Some(new), &mut changes);
+    changes
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn diff_at(path: String, old: Option<&Value>, new: Option<&Value>, out: &mut Vec<JsonChange>) {
+    match (old, new) {
+        (Some(old), Some(new)) if old == new => {}
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                diff_at(child_path(&path, key), old.get(key), new.get(key), out);
+            }
+        }
+        (Some(Value::Array(old)), Some(Value::Array(new))) => {
+            for i in 0..old.len().max(new.len()) {
+                diff_at(
+                    child_path(&path, &i.to_string()),
+                    old.get(i),
+                    new.get(i),
+                    out,
+                );
+            }
+        }
+        (Some(old), Some(new)) => out.push(JsonChange::Changed {
+            path,
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        (Some(old), None) => out.push(JsonChange::Removed {
+            path,
+            value: old.clone(),
+        }),
+        (None, Some(new)) => out.push(JsonChange::Added {
+            path,
+            value: new.clone(),
+        }),
+        (None, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn diff_json_reports_each_kind() {
+        let old = json!({"name": "app", "server": {"port": 80}, "tags": ["a", "b"]});
+        let new = json!({"server": {"port": 443, "tls": true}, "tags": ["a"]});
+        assert_eq!(
+            diff_json(&old, &new),
+            [
+                JsonChange::Removed {
+                    path: "name".into(),
+                    value: json!("app")
+                },
+                JsonChange::Changed {
+                    path: "server.port".into(),
+                    old: json!(80),
+                    new: json!(443)
+                },
+                JsonChange::Added {
+                    path: "server.tls".into(),
+                    value: json!(true)
+                },
+                JsonChange::Removed {
+                    path: "tags.1".into(),
+                    value: json!("b")
+                },
+            ]
+        );
+        assert_eq!(diff_json(&old, &old), []);
+    }
+}
@@ -1,13 +1,37 @@
 use serde::{Deserialize, Serialize};
-use std::{fs, io, path::PathBuf};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 pub trait SerdeStr: Serialize + for<'de> Deserialize<'de> {
     fn de_from_str(string: &str) -> Result<Self, io::Error>
     where
         Self: Sized;
     fn ser_to_string(&self) -> Result<String, io::Error>;
+    /// Deserializes from a reader; buffers into a `String` unless the format can stream
+    ///
+    /// [`FileIO::load`] and [`FileIO::save`] go through this pair, the file's text having
+    /// been decoded first, so an override changes how every FileIO parses and writes `Self`.
+    fn de_from_reader<R: Read>(mut reader: R) -> Result<Self, io::Error>
+    where
+        Self: Sized,
+    {
+        let mut string = String::new();
+        reader.read_to_string(&mut string)?;
+        Self::de_from_str(&string)
+    }
+    /// Serializes into a writer; buffers into a `String` unless the format can stream
+    fn ser_to_writer<W: Write>(&self, mut writer: W) -> Result<(), io::Error> {
+        writer.write_all(self.ser_to_string()?.as_bytes())
+    }
 }
 
+#[cfg(feature = "json")]
 #[macro_export]
 macro_rules! impl_serde_str_json {
     ($($t:ty),*) => {
@@ -19,157 +43,5097 @@ macro_rules! impl_serde_str_json {
                 fn ser_to_string(&self) -> Result<String, std::io::Error> {
                     Ok(serde_json::to_string(self)?)
                 }
+                fn de_from_reader<R: std::io::Read>(reader: R) -> Result<Self, std::io::Error> {
+                    Ok(serde_json::from_reader(reader)?)
+                }
+                fn ser_to_writer<W: std::io::Write>(&self, writer: W) -> Result<(), std::io::Error> {
+                    Ok(serde_json::to_writer(writer, self)?)
+                }
             }
         )*
     };
 }
 
+/// Implements [`SerdeStr`] with TOML
+///
+/// Native TOML datetimes (`2024-01-01T00:00:00Z`) round-trip through fields of type
+/// `toml::value::Datetime`; `time`/`chrono` types serialize as strings instead.
+/// Parse errors keep the `toml` message, including line and column.
+#[cfg(feature = "toml")]
 #[macro_export]
 macro_rules! impl_serde_str_toml {
     ($($t:ty),*) => {
         $(
             impl $crate::SerdeStr for $t {
                 fn de_from_str(string: &str) -> Result<Self, std::io::Error> {
-                    Ok(toml::from_str(string).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?)
+                    toml::from_str(string).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
                 }
                 fn ser_to_string(&self) -> Result<String, std::io::Error> {
-                    Ok(toml::to_string(self).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?)
+                    toml::to_string(self).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
                 }
             }
         )*
     };
 }
 
-/// Easy access to the a file (configuration file, data file, etc.)
-/// Provides (safe?) load and save operations
-pub struct FileIO<T, S = ()> {
-    _content: std::marker::PhantomData<T>,
-    _serde: std::marker::PhantomData<S>,
-    pub path: PathBuf,
+/// The formats a file can be read in as an untyped [`Value`], detected by extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "toml")]
+    Toml,
 }
 
-impl<T> FileIO<T>
-where
-    T: SerdeStr,
-{
-    pub fn new(path: PathBuf) -> Self {
-        Self {
-            _content: std::marker::PhantomData,
-            _serde: std::marker::PhantomData,
-            path,
+#[cfg_attr(not(any(feature = "json", feature = "toml")), allow(unused_variables))]
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            #[cfg(feature = "json")]
+            "json" => Some(Self::Json),
+            #[cfg(feature = "toml")]
+            "toml" => Some(Self::Toml),
+            _ => None,
         }
     }
-    fn ensure_parent(&self) -> io::Result<()> {
-        let parent = self.path.parent().ok_or_else(|| {
-            io::Error::new(
+    pub fn parse_value(self, string: &str) -> io::Result<Value> {
+        match self {
+            #[cfg(feature = "json")]
+            Self::Json => Ok(serde_json::from_str(string)?),
+            #[cfg(feature = "toml")]
+            Self::Toml => {
+                toml::from_str(string).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+    pub fn value_to_string(self, value: &Value) -> io::Result<String> {
+        match self {
+            #[cfg(feature = "json")]
+            Self::Json => Ok(serde_json::to_string(value)?),
+            #[cfg(feature = "toml")]
+            Self::Toml => {
+                toml::to_string(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+}
+
+/// The top-level key a line of serialized TOML belongs to, if it starts one
+#[cfg(feature = "toml")]
+fn toml_top_level_key(line: &str, in_table: bool) -> Option<String> {
+    let unquote = |key: &str| key.trim().trim_matches('"').to_owned();
+    if let Some(header) = line.strip_prefix('[') {
+        let header = header.trim_start_matches('[');
+        let end = header.find([']', '.'])?;
+        return Some(unquote(&header[..end]));
+    }
+    if in_table || line.starts_with([' ', '\t', '#']) {
+        return None;
+    }
+    let (key, _) = line.split_once(" = ")?;
+    Some(unquote(key))
+}
+
+/// Puts `# doc` comment lines above the first line of each documented top-level key
+#[cfg(feature = "toml")]
+fn document_toml(text: &str, docs: &[(&str, &str)]) -> String {
+    let mut documented = String::with_capacity(text.len());
+    let mut done = std::collections::HashSet::new();
+    let mut in_table = false;
+    for line in text.lines() {
+        let key = toml_top_level_key(line, in_table);
+        in_table |= line.starts_with('[');
+        if let Some(key) = key {
+            if let Some((_, doc)) = docs.iter().find(|(k, _)| *k == key) {
+                if done.insert(key) {
+                    for doc_line in doc.lines() {
+                        documented += "# ";
+                        documented += doc_line;
+                        documented += "\n";
+                    }
+                }
+            }
+        }
+        documented += line;
+        documented += "\n";
+    }
+    documented
+}
+
+/// How [`merge_values_with`] combines two arrays
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// The overlay's array replaces the base's
+    Replace,
+    /// Elements are matched by the value of this field: matching ones are deep-merged,
+    /// others appended; arrays with any element that isn't an object holding the field
+    /// are replaced instead
+    MergeByKey(String),
+}
+
+/// Deep-merges `overlay` onto `base`: objects are merged key by key, anything else is replaced
+pub fn merge_values(base: &mut Value, overlay: Value) {
+    merge_values_with(base, overlay, &ArrayMerge::Replace)
+}
+
+/// [`merge_values`] combining arrays as `arrays` says, e.g. lists of servers keyed by name
+pub fn merge_values_with(base: &mut Value, overlay: Value, arrays: &ArrayMerge) {
+    match (base, overlay, arrays) {
+        (Value::Object(base), Value::Object(overlay), _) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(slot) => merge_values_with(slot, value, arrays),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(overlay), ArrayMerge::MergeByKey(key))
+            if base
+                .iter()
+                .chain(&overlay)
+                .all(|item| item.get(key).is_some()) =>
+        {
+            for item in overlay {
+                match base
+                    .iter_mut()
+                    .find(|existing| existing.get(key) == item.get(key))
+                {
+                    Some(existing) => merge_values_with(existing, item, arrays),
+                    None => base.push(item),
+                }
+            }
+        }
+        (base, overlay, _) => *base = overlay,
+    }
+}
+
+/// Converts a JSON value to an inline TOML value; TOML has no null, and its integers
+/// stop at `i64::MAX`
+#[cfg(feature = "toml_edit")]
+fn toml_edit_value(value: &Value) -> io::Result<toml_edit::Value> {
+    Ok(match value {
+        Value::Null => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "TOML cannot represent null",
+        ))?,
+        Value::Bool(b) => (*b).into(),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None if n.is_u64() => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is too large for a TOML integer", n),
+            ))?,
+            None => n.as_f64().unwrap_or(f64::NAN).into(),
+        },
+        Value::String(s) => s.as_str().into(),
+        Value::Array(items) => {
+            let items = items.iter().map(toml_edit_value);
+            items.collect::<io::Result<toml_edit::Array>>()?.into()
+        }
+        Value::Object(map) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (key, value) in map {
+                table.insert(key, toml_edit_value(value)?);
+            }
+            table.into()
+        }
+    })
+}
+
+/// Applies `overlay` to a TOML table in place, keeping the comments and layout
+/// of everything it doesn't replace; a null removes the key
+#[cfg(feature = "toml_edit")]
+fn patch_toml_table(
+    table: &mut dyn toml_edit::TableLike,
+    overlay: serde_json::Map<String, Value>,
+) -> io::Result<()> {
+    for (key, value) in overlay {
+        match (table.get_mut(&key), value) {
+            (_, Value::Null) => {
+                table.remove(&key);
+            }
+            (Some(item), Value::Object(map)) if item.is_table_like() => {
+                let nested = item.as_table_like_mut().expect("checked table-like");
+                patch_toml_table(nested, map)?;
+            }
+            (Some(toml_edit::Item::Value(old)), value) => {
+                let mut new = toml_edit_value(&value)?;
+                // keeps the trailing comment and spacing of the replaced value
+                *new.decor_mut() = old.decor().clone();
+                *old = new;
+            }
+            (Some(item), value) => *item = toml_edit::Item::Value(toml_edit_value(&value)?),
+            (None, value) => {
+                table.insert(&key, toml_edit::Item::Value(toml_edit_value(&value)?));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Interprets an environment string as the JSON type already found at its slot;
+/// without a prior value, booleans and numbers are recognized and anything else stays a string
+fn coerce_env(raw: &str, existing: Option<&Value>) -> Value {
+    let parsed = serde_json::from_str::<Value>(raw).ok();
+    match (existing, parsed) {
+        (Some(Value::String(_)), _) => Value::String(raw.to_owned()),
+        (Some(Value::Bool(_)), Some(v @ Value::Bool(_)))
+        | (Some(Value::Number(_)), Some(v @ Value::Number(_)))
+        | (None | Some(Value::Null), Some(v @ (Value::Bool(_) | Value::Number(_)))) => v,
+        _ => Value::String(raw.to_owned()),
+    }
+}
+
+/// Walks `keys` into `value`, creating objects along the way, and returns the final slot
+fn slot_mut<'v>(value: &'v mut Value, keys: &[&str]) -> io::Result<&'v mut Value> {
+    let mut slot = value;
+    for key in keys {
+        if slot.is_null() {
+            *slot = Value::Object(Default::default());
+        }
+        slot = match slot {
+            Value::Object(map) => map.entry(key.to_string()).or_insert(Value::Null),
+            _ => Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
-                "The path has no parent directory",
-            )
-        })?;
-        fs::create_dir_all(parent)?;
-        Ok(())
+                format!("cannot descend into non-table value at `{}`", key),
+            ))?,
+        };
     }
-    pub fn load(&self) -> io::Result<T> {
-        self.ensure_parent()?;
-        let string = fs::read_to_string(&self.path.canonicalize()?)?;
-        let conf = SerdeStr::de_from_str(&string)?;
-        Ok(conf)
+    Ok(slot)
+}
+
+/// Writes the secret `lookup` finds for each dotted key in `keys` onto `value` as a string
+#[cfg(feature = "keyring")]
+fn overlay_secrets(
+    value: &mut Value,
+    keys: &[&str],
+    lookup: impl Fn(&str) -> io::Result<String>,
+) -> io::Result<()> {
+    for dotted in keys {
+        let secret = lookup(dotted)?;
+        let path: Vec<&str> = dotted.split('.').collect();
+        *slot_mut(value, &path)? = Value::String(secret);
     }
-    pub fn save(&self, conf: &T) -> io::Result<()> {
-        self.ensure_parent()?;
-        let s = SerdeStr::ser_to_string(conf)?;
-        fs::write(&self.path, s)?;
-        Ok(())
+    Ok(())
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_secret(service: &str, key: &str) -> io::Result<String> {
+    let entry = keyring::Entry::new(service, key).map_err(io::Error::other)?;
+    entry.get_password().map_err(|e| match e {
+        keyring::Error::NoEntry => io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no keyring entry for `{}` in service `{}`", key, service),
+        ),
+        e => io::Error::other(format!(
+            "keyring lookup of `{}` in service `{}` failed: {}",
+            key, service, e
+        )),
+    })
+}
+
+/// The unescaped reference tokens of an RFC 6901 JSON Pointer, none for the root
+fn pointer_tokens(ptr: &str) -> io::Result<Vec<String>> {
+    if ptr.is_empty() {
+        return Ok(Vec::new());
     }
-    pub fn load_or_init(&self, init: impl Fn() -> T) -> io::Result<T> {
-        match self.load() {
-            Ok(conf) => Ok(conf),
-            Err(_) => {
-                let conf = init();
-                self.backup_and_save(&conf)?;
-                Ok(conf)
+    let rest = ptr.strip_prefix('/').ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("JSON pointer `{}` must start with `/`", ptr),
+        )
+    })?;
+    Ok(rest
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Walks `ptr` into `value` like `Value::pointer_mut`, but creating missing object members
+/// (and objects in place of nulls) along the way
+fn pointer_slot_mut<'v>(value: &'v mut Value, ptr: &str) -> io::Result<&'v mut Value> {
+    let mut slot = value;
+    for token in pointer_tokens(ptr)? {
+        if slot.is_null() {
+            *slot = Value::Object(Default::default());
+        }
+        slot = match slot {
+            Value::Object(map) => map.entry(token).or_insert(Value::Null),
+            Value::Array(items) => {
+                let len = items.len();
+                token
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| items.get_mut(i))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("`{}` is not an index of an array of {}", token, len),
+                        )
+                    })?
             }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot descend into a plain value at `{}`", token),
+            ))?,
+        };
+    }
+    Ok(slot)
+}
+
+/// Applies `<PREFIX>_A__B=value` pairs onto `value` as `a.b = value`
+fn overlay_env(
+    value: &mut Value,
+    prefix: &str,
+    vars: impl Iterator<Item = (String, String)>,
+) -> io::Result<()> {
+    let prefix = format!("{}_", prefix);
+    for (key, raw) in vars {
+        let Some(rest) = key.strip_prefix(&prefix) else {
+            continue;
+        };
+        let keys = rest.to_lowercase();
+        let keys: Vec<&str> = keys.split("__").collect();
+        let slot = slot_mut(value, &keys)?;
+        *slot = coerce_env(&raw, Some(&*slot).filter(|v| !v.is_null()));
+    }
+    Ok(())
+}
+
+/// Substitutes every `${NAME}` in `template` from `vars`, failing with `InvalidInput`
+/// on placeholders left unfilled or unterminated
+fn fill_template(
+    template: &str,
+    vars: &std::collections::HashMap<String, String>,
+) -> io::Result<String> {
+    let mut filled = String::with_capacity(template.len());
+    let mut missing = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("${") {
+        filled.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "unterminated `${` in template")
+        })?;
+        let name = &rest[start + 2..start + end];
+        match vars.get(name) {
+            Some(value) => filled.push_str(value),
+            None => missing.push(name),
         }
+        rest = &rest[start + end + 1..];
     }
-    pub fn backup_and_save(&self, conf: &T) -> io::Result<()> {
-        self.ensure_parent()?;
-        if self.path.exists() {
-            // back up the old file
-            let mut ext = self
-                .path
-                .extension()
-                .unwrap_or_default()
-                .to_owned()
-                .into_string()
-                .unwrap_or_default();
-            ext += ".";
-            ext += &time::OffsetDateTime::now_utc().unix_timestamp().to_string();
-            ext += ".bak";
-            let backup_path = self.path.with_extension(ext);
-            fs::rename(&self.path, &backup_path)?;
+    filled.push_str(rest);
+    if !missing.is_empty() {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unfilled template placeholders: {}", missing.join(", ")),
+        ))?;
+    }
+    Ok(filled)
+}
+
+/// Flattens `value` into `NAME=value` pairs the way [`overlay_env`] reads them back
+fn flatten_env(name: String, value: &Value, out: &mut Vec<(String, String)>) -> io::Result<()> {
+    match value {
+        Value::Null => {}
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten_env(format!("{}__{}", name, key.to_uppercase()), value, out)?;
+            }
         }
-        self.save(conf)?;
-        Ok(())
+        Value::String(string) => out.push((name, string.clone())),
+        Value::Array(_) => out.push((name, serde_json::to_string(value)?)),
+        Value::Bool(_) | Value::Number(_) => out.push((name, value.to_string())),
     }
-    pub fn edit(&self) -> io::Result<()> {
-        let editor = std::env::var("EDITOR")
-            .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "$EDITOR envvar not set"))?;
-        let status = std::process::Command::new(editor)
-            .arg(&self.path)
-            .status()?;
-        if !status.success() {
+    Ok(())
+}
+
+/// Follows a dotted path like `server.port` through objects, and arrays by index
+fn lookup<'v>(value: &'v Value, dotted: &str) -> io::Result<&'v Value> {
+    let mut slot = value;
+    for key in dotted.split('.') {
+        let next = match slot {
+            Value::Object(map) => map.get(key),
+            Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        };
+        slot = next.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no value at `{}`", dotted))
+        })?;
+    }
+    Ok(slot)
+}
+
+const BOM: char = '\u{feff}';
+
+/// Reads a file as text; with the `gzip` feature, content starting with the gzip magic
+/// bytes is decompressed first, whatever the extension
+///
+/// A leading byte order mark, as Windows editors may write, is dropped since parsers
+/// reject it.
+fn read_text(path: &Path) -> io::Result<String> {
+    read_text_bounded(path, u64::MAX)
+}
+
+/// The text between a leading `---` line and the next one, if `text` opens with such a block
+fn front_matter(text: &str) -> Option<&str> {
+    let is_fence = |line: &str| line.trim_end() == "---";
+    let (first, rest) = text.split_once('\n')?;
+    if !is_fence(first) {
+        return None;
+    }
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if is_fence(line) {
+            return Some(&rest[..offset]);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// [`read_text`] failing with `InvalidData` rather than holding more than `max_bytes`,
+/// checked against the file's size before reading and again after decompressing
+fn read_text_bounded(path: &Path, max_bytes: u64) -> io::Result<String> {
+    let too_large = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "config too large: {} exceeds {} bytes",
+                path.display(),
+                max_bytes
+            ),
+        )
+    };
+    let file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    if size > max_bytes {
+        Err(too_large())?;
+    }
+    let mut bytes = Vec::with_capacity(size as usize);
+    // the file may have grown since
+    file.take(max_bytes.saturating_add(1))
+        .read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > max_bytes {
+        Err(too_large())?;
+    }
+    #[cfg(feature = "gzip")]
+    let bytes = if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut plain = Vec::new();
+        flate2::read::GzDecoder::new(&bytes[..])
+            .take(max_bytes.saturating_add(1))
+            .read_to_end(&mut plain)?;
+        if plain.len() as u64 > max_bytes {
+            Err(too_large())?;
+        }
+        plain
+    } else {
+        bytes
+    };
+    let mut string = String::from_utf8(bytes).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} is not valid UTF-8 text (at byte {}); is this a binary file?",
+                path.display(),
+                e.utf8_error().valid_up_to()
+            ),
+        )
+    })?;
+    if string.starts_with(BOM) {
+        string.drain(..BOM.len_utf8());
+    }
+    Ok(string)
+}
+
+fn format_of(path: &Path) -> io::Result<Format> {
+    Format::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown config format for {}", path.display()),
+        )
+    })
+}
+
+/// Parses `path` and merges it over the files its `include_key` entry names, resolved
+/// against its own directory; `stack` holds the files currently being included
+fn load_included(path: &Path, include_key: &str, stack: &mut Vec<PathBuf>) -> io::Result<Value> {
+    let path = path.canonicalize()?;
+    if stack.contains(&path) {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("include cycle through {}", path.display()),
+        ))?;
+    }
+    let mut value = format_of(&path)?
+        .parse_value(&read_text(&path)?)
+        .map_err(|e| io::Error::new(e.kind(), format!("in {}: {}", path.display(), e)))?;
+    let includes = match value
+        .as_object_mut()
+        .and_then(|map| map.remove(include_key))
+    {
+        None => return Ok(value),
+        Some(Value::String(include)) => vec![include],
+        Some(Value::Array(includes)) => includes
+            .into_iter()
+            .map(|include| match include {
+                Value::String(include) => Ok(include),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("`{}` entries must be paths", include_key),
+                )),
+            })
+            .collect::<io::Result<_>>()?,
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("`{}` must be a path or a list of paths", include_key),
+        ))?,
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    stack.push(path.clone());
+    let mut merged = Value::Object(Default::default());
+    for include in includes {
+        merge_values(
+            &mut merged,
+            load_included(&dir.join(include), include_key, stack)?,
+        );
+    }
+    stack.pop();
+    merge_values(&mut merged, value);
+    Ok(merged)
+}
+
+/// The values [`FileIO::load_stream`] reads, one at a time
+enum JsonStream<U> {
+    Values(
+        serde_json::StreamDeserializer<'static, serde_json::de::IoRead<io::BufReader<fs::File>>, U>,
+    ),
+    /// Past the opening `[`, splitting elements at top-level commas
+    Elements {
+        reader: io::BufReader<fs::File>,
+        first: bool,
+        done: bool,
+    },
+}
+
+/// Collects the bytes of the next array element, or `None` at the closing `]` of `[]`
+///
+/// Sets `done` once the closing `]` is reached, after checking that only whitespace follows.
+fn next_element<R: io::BufRead>(
+    reader: &mut R,
+    first: bool,
+    done: &mut bool,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut element = Vec::new();
+    let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+    'scan: loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
             Err(io::Error::new(
-                io::ErrorKind::Other,
-                "failed to edit config file and exit gracefully",
+                io::ErrorKind::UnexpectedEof,
+                "unterminated JSON array",
+            ))?
+        }
+        for (i, &byte) in buf.iter().enumerate() {
+            if in_string {
+                match byte {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match byte {
+                b'"' => in_string = true,
+                b'[' | b'{' => depth += 1,
+                b',' | b']' if depth == 0 => {
+                    element.extend_from_slice(&buf[..i]);
+                    reader.consume(i + 1);
+                    if byte == b',' {
+                        return Ok(Some(element));
+                    }
+                    break 'scan;
+                }
+                b']' | b'}' => {
+                    depth = depth.checked_sub(1).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "unbalanced `}` in JSON array")
+                    })?
+                }
+                _ => {}
+            }
+        }
+        let len = buf.len();
+        element.extend_from_slice(buf);
+        reader.consume(len);
+    }
+    *done = true;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        if !buf.iter().all(u8::is_ascii_whitespace) {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "trailing characters after JSON array",
             ))?;
         }
-        Ok(())
+        let len = buf.len();
+        reader.consume(len);
+    }
+    if first && element.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
     }
+    Ok(Some(element))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl<U: serde::de::DeserializeOwned> Iterator for JsonStream<U> {
+    type Item = io::Result<U>;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Values(values) => Some(values.next()?.map_err(io::Error::from)),
+            Self::Elements { done: true, .. } => None,
+            Self::Elements {
+                reader,
+                first,
+                done,
+            } => {
+                let element = match next_element(reader, std::mem::take(first), done) {
+                    Ok(Some(element)) => element,
+                    Ok(None) => return None,
+                    Err(e) => {
+                        *done = true;
+                        return Some(Err(e));
+                    }
+                };
+                Some(serde_json::from_slice(&element).map_err(io::Error::from))
+            }
+        }
+    }
+}
 
-    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
-    struct Conf {
-        pub name: String,
+/// Rebuilds every object in `value` with its keys in sorted order, which serde_json's
+/// `preserve_order` feature would otherwise leave in insertion order
+fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        Value::Object(map) => {
+            let mut entries: Vec<_> = std::mem::take(map).into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, mut value) in entries {
+                sort_keys(&mut value);
+                map.insert(key, value);
+            }
+        }
+        _ => {}
     }
-    impl SerdeStr for Conf {
-        fn de_from_str(string: &str) -> Result<Self, io::Error> {
-            let json = serde_json::from_str(&string)?;
-            Ok(json)
+}
+
+fn to_value<T: Serialize>(value: &T) -> io::Result<Value> {
+    Ok(serde_json::to_value(value)?)
+}
+
+fn from_value<T: for<'de> Deserialize<'de>>(value: Value) -> io::Result<T> {
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Conventional exit codes from BSD's `sysexits.h`, as returned by [`exit_code`]
+pub mod sysexits {
+    pub const EX_USAGE: i32 = 64;
+    pub const EX_DATAERR: i32 = 65;
+    pub const EX_NOINPUT: i32 = 66;
+    pub const EX_IOERR: i32 = 74;
+    pub const EX_TEMPFAIL: i32 = 75;
+    pub const EX_NOPERM: i32 = 77;
+}
+
+/// The exit code a CLI should end with after `err` from a [`FileIO`] operation
+///
+/// By kind: `NotFound` is `EX_NOINPUT`, `InvalidData` and `UnexpectedEof` (parse errors)
+/// are `EX_DATAERR`, `PermissionDenied` is `EX_NOPERM`, `InvalidInput` (e.g. an unknown
+/// format or a directory path) is `EX_USAGE`, `WouldBlock` (a held lock) is `EX_TEMPFAIL`,
+/// and anything else is `EX_IOERR`.
+pub fn exit_code(err: &io::Error) -> i32 {
+    use sysexits::*;
+    match err.kind() {
+        _ if err.is_not_found() => EX_NOINPUT,
+        _ if err.is_parse_error() => EX_DATAERR,
+        io::ErrorKind::PermissionDenied => EX_NOPERM,
+        io::ErrorKind::InvalidInput => EX_USAGE,
+        io::ErrorKind::WouldBlock => EX_TEMPFAIL,
+        _ => EX_IOERR,
+    }
+}
+
+/// Predicates over the `io::Error`s [`FileIO`] returns, mirroring [`exit_code`]
+///
+/// The errors already chain: a parse error wraps the parser's own, reachable through
+/// `get_ref` and `downcast`, so `?` into `anyhow` or a `thiserror` enum keeps the cause.
+pub trait FileIoErrorExt {
+    /// The file (or a file it names) doesn't exist
+    fn is_not_found(&self) -> bool;
+    /// The file exists but its content couldn't be parsed or is otherwise invalid
+    fn is_parse_error(&self) -> bool;
+}
+
+impl FileIoErrorExt for io::Error {
+    fn is_not_found(&self) -> bool {
+        self.kind() == io::ErrorKind::NotFound
+    }
+    fn is_parse_error(&self) -> bool {
+        matches!(
+            self.kind(),
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+        )
+    }
+}
+
+/// The editor [`FileIO::edit`] launches: `$VISUAL`, then `$EDITOR`, then `vi`
+/// (`notepad` on Windows); empty variables are skipped
+///
+/// This is a command line rather than a program name, e.g. `code -w`, which
+/// [`editor_command`] hands to the shell to split.
+pub fn resolve_editor() -> io::Result<std::ffi::OsString> {
+    resolve_editor_from(|var| std::env::var_os(var))
+}
+
+/// The command editing `path` in [`resolve_editor`]'s editor, run through the shell as
+/// Git does, so the editor's own arguments and quoting work as the user wrote them
+///
+/// That is `sh -c '<editor> "$@"' <editor> <path>`, or `cmd /C <editor> <path>` on Windows.
+pub fn editor_command(path: &Path) -> io::Result<std::process::Command> {
+    editor_command_from(|var| std::env::var_os(var), path)
+}
+
+fn editor_command_from(
+    var: impl Fn(&str) -> Option<std::ffi::OsString>,
+    path: &Path,
+) -> io::Result<std::process::Command> {
+    let editor = resolve_editor_from(var)?;
+    #[cfg(windows)]
+    let command = {
+        use std::os::windows::process::CommandExt;
+        let mut command = std::process::Command::new("cmd");
+        command.arg("/C").raw_arg(&editor).arg(path);
+        command
+    };
+    #[cfg(not(windows))]
+    let command = {
+        let mut script = editor.clone();
+        script.push(r#" "$@""#);
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c").arg(script).arg(&editor).arg(path);
+        command
+    };
+    Ok(command)
+}
+
+fn resolve_editor_from(
+    var: impl Fn(&str) -> Option<std::ffi::OsString>,
+) -> io::Result<std::ffi::OsString> {
+    let fallback = if cfg!(windows) { "notepad" } else { "vi" };
+    let editor = ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(var)
+        .find(|editor| !editor.is_empty())
+        .unwrap_or_else(|| fallback.into());
+    Ok(editor)
+}
+
+/// How often [`FileIO::load_stable`] tries to parse the file, and how long it waits in between
+const STABLE_ATTEMPTS: usize = 3;
+const STABLE_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// How long [`FileIO::save`] waits before retrying, see [`FileIO::retry_saves`]
+const SAVE_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(20);
+
+/// Whether a failed save may succeed if simply tried again
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::ResourceBusy
+    )
+}
+
+/// Runs `op` until it succeeds, fails without looking like a torn read, or runs out of attempts
+fn retry_torn<R>(mut op: impl FnMut() -> io::Result<R>) -> io::Result<R> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e)
+                if attempt < STABLE_ATTEMPTS
+                    && matches!(
+                        e.kind(),
+                        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                    ) =>
+            {
+                attempt += 1;
+                std::thread::sleep(STABLE_RETRY_DELAY);
+            }
+            res => return res,
         }
-        fn ser_to_string(&self) -> Result<String, io::Error> {
-            let s = serde_json::to_string(self)?;
-            Ok(s)
+    }
+}
+
+/// Splits a backup's file name, `<base>.<timestamp>.bak` or `.bak.gz`, into the name of
+/// the file it was made of and its unix timestamp; the inverse of [`format_backup_name`]
+pub fn parse_backup_name(name: &std::ffi::OsStr) -> Option<(String, i64)> {
+    let name = name.to_str()?;
+    let stem = name
+        .strip_suffix(".bak")
+        .or_else(|| name.strip_suffix(".bak.gz"))?;
+    let (base, timestamp) = stem.rsplit_once('.')?;
+    Some((base.to_owned(), timestamp.parse().ok()?))
+}
+
+/// The name of an uncompressed backup of the file named `base` taken at unix time
+/// `timestamp`, as made by [`FileIO::backup_and_save`] for files with an extension
+pub fn format_backup_name(base: &str, timestamp: i64) -> String {
+    format!("{}.{}.bak", base, timestamp)
+}
+
+/// Deletes all but the newest `keep_per_file` backups of every file in `dir`, returning
+/// the bytes freed; backups are grouped by the name of the file they were made of
+pub fn prune_backups(dir: &Path, keep_per_file: usize) -> io::Result<u64> {
+    let mut groups: BTreeMap<String, Vec<(i64, PathBuf)>> = Default::default();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if let Some((base, timestamp)) = parse_backup_name(&file_name) {
+            let group = groups.entry(base).or_default();
+            group.push((timestamp, entry.path()));
+        }
+    }
+    let mut freed = 0;
+    for mut backups in groups.into_values() {
+        backups.sort_by(|a, b| b.cmp(a));
+        for (_, path) in backups.into_iter().skip(keep_per_file) {
+            freed += fs::metadata(&path)?.len();
+            fs::remove_file(&path)?;
         }
     }
+    Ok(freed)
+}
 
-    #[test]
-    fn file_io_str() {
-        let path = PathBuf::from("test_file_io_str.json");
-        let conf = Conf {
-            name: "test".to_string(),
-        };
-        let s = serde_json::to_string(&conf).unwrap();
-        fs::write(&path, s).unwrap();
-        let file_io = FileIO::<Conf>::new(path.clone());
-        let loaded_conf = file_io.load();
-        fs::remove_file(&path).unwrap();
-        assert_eq!(loaded_conf.unwrap(), conf);
+/// Reads `paths` in parallel and discards the bytes, so that the loads that follow, e.g.
+/// at startup, hit the OS page cache instead of waiting on the disk one file at a time
+///
+/// Returns the bytes read from each path, in order; a failure only affects its own path,
+/// and is best ignored as the load will report it again.
+#[cfg(feature = "prefetch")]
+pub fn prefetch(paths: &[PathBuf]) -> Vec<io::Result<u64>> {
+    use rayon::prelude::*;
+    paths
+        .par_iter()
+        .map(|path| {
+            let mut file = fs::File::open(path)?;
+            let mut buf = vec![0; 64 * 1024];
+            let mut total = 0;
+            loop {
+                match file.read(&mut buf) {
+                    Ok(0) => return Ok(total),
+                    Ok(n) => total += n as u64,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => Err(e)?,
+                }
+            }
+        })
+        .collect()
+}
+
+/// Leading bytes of every file written by [`FileIO::save_snapshot`]
+#[cfg(feature = "sha_snap")]
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"SCSN";
+#[cfg(feature = "sha_snap")]
+const SNAPSHOT_VERSION: u8 = 1;
+#[cfg(feature = "sha_snap")]
+const SNAPSHOT_HEADER_LEN: usize = SNAPSHOT_MAGIC.len() + 1 + 64;
+
+/// The tracing span of one [`FileIO`] operation, which the bytes it moved are recorded
+/// on; empty without the `tracing` feature
+struct Trace {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl Trace {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn bytes(&self, bytes: usize) {
+        #[cfg(feature = "tracing")]
+        self.span.record("bytes", bytes);
     }
+}
 
-    #[test]
-    fn file_io_save() {
-        let path = PathBuf::from("test_file_io_save.json");
-        let conf = Conf {
-            name: "test".to_string(),
+/// An in-flight temp file, removed on drop (including unwinding) unless persisted
+struct TempFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl TempFile {
+    /// A new temp file in `dir` named after `prefix`, see [`crate::temp::create_unique`]
+    fn create(dir: &Path, prefix: &str) -> io::Result<(Self, fs::File)> {
+        let (file, path) = crate::temp::create_unique(dir, prefix)?;
+        let temp = Self {
+            path,
+            persisted: false,
         };
-        let file_io = FileIO::<Conf>::new(path.clone());
-        file_io.save(&conf).unwrap();
-        let loaded_conf = file_io.load();
-        fs::remove_file(&path).unwrap();
+        Ok((temp, file))
+    }
+    /// Renames the temp file over `target`, or copies it there across file systems
+    fn persist(mut self, target: &Path) -> io::Result<()> {
+        match fs::rename(&self.path, target) {
+            // leaves `persisted` unset, so the copied-from file is still removed on drop
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                fs::copy(&self.path, target)?;
+            }
+            res => {
+                res?;
+                self.persisted = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Fsyncs the directory holding `path` so that a rename into it is persisted
+fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    // SAFETY: both paths are valid NUL-terminated strings that outlive the call
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// A backup written by [`FileIO::backup_and_save`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub timestamp: time::OffsetDateTime,
+    pub size: u64,
+}
+
+/// How a file was found when [`FileIO::load_with_meta`] loaded it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadMeta {
+    /// `None` for extensions without a [`Format`], which `T` may still parse
+    pub format: Option<Format>,
+    /// Size on disk, so compressed for gzipped files
+    pub size: u64,
+    pub modified: std::time::SystemTime,
+}
+
+/// Returned by [`FileIO::save_reversible`]: dropping it keeps the new content, while
+/// [`SaveGuard::rollback`] puts the previous content back
+#[must_use = "dropping the guard commits the save right away"]
+pub struct SaveGuard<T: SerdeStr> {
+    file_io: FileIO<T>,
+    /// `None` if there was no file before
+    previous: Option<Vec<u8>>,
+}
+
+impl<T: SerdeStr> SaveGuard<T> {
+    /// Restores the content from before the save atomically, or removes the file if the
+    /// save created it
+    pub fn rollback(self) -> io::Result<()> {
+        match &self.previous {
+            Some(previous) => (self.file_io).traced("restore", |trace| {
+                self.file_io.write_atomic(trace, previous, false)
+            }),
+            None => fs::remove_file(&self.file_io.path),
+        }
+    }
+}
+
+/// Returned by [`FileIO::edit_guard`]: derefs to the loaded content and saves it
+/// atomically on drop, unless [`EditGuard::cancel`]ed
+///
+/// Dropping can only log a failed save; [`EditGuard::commit`] reports it instead. A guard
+/// dropped while unwinding from a panic discards the edit rather than saving it.
+#[must_use = "dropping the guard saves right away"]
+pub struct EditGuard<T: SerdeStr> {
+    file_io: FileIO<T>,
+    /// `None` once committed or cancelled
+    conf: Option<T>,
+}
+
+impl<T: SerdeStr> EditGuard<T> {
+    /// Saves the content now, reporting failures that dropping would only log
+    pub fn commit(mut self) -> io::Result<()> {
+        match self.conf.take() {
+            Some(conf) => self.file_io.save_atomic(&conf),
+            None => Ok(()),
+        }
+    }
+    /// Discards the changes, leaving the file as it was
+    pub fn cancel(mut self) {
+        self.conf = None;
+    }
+}
+
+impl<T: SerdeStr> std::ops::Deref for EditGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.conf
+            .as_ref()
+            .expect("only taken when consuming the guard")
+    }
+}
+
+impl<T: SerdeStr> std::ops::DerefMut for EditGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.conf
+            .as_mut()
+            .expect("only taken when consuming the guard")
+    }
+}
+
+impl<T: SerdeStr> Drop for EditGuard<T> {
+    fn drop(&mut self) {
+        // an edit interrupted by a panic may be half-done, so it's discarded
+        if std::thread::panicking() {
+            return;
+        }
+        if let Some(conf) = self.conf.take() {
+            if let Err(e) = self.file_io.save_atomic(&conf) {
+                log::warn!("failed to save {}: {}", self.file_io.path.display(), e);
+            }
+        }
+    }
+}
+
+/// What a [`FileIO`] just did, as reported to its observer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileIoEvent {
+    Loaded { path: PathBuf, bytes: usize },
+    Saved { path: PathBuf, bytes: usize },
+    BackedUp { from: PathBuf, to: PathBuf },
+}
+
+/// A step of [`FileIO::load_migrated`]: the version it upgrades to, and the upgrade
+pub type Migration = (u32, fn(Value) -> Value);
+
+pub type FileIoObserver = Arc<dyn Fn(FileIoEvent) + Send + Sync>;
+
+/// The line endings a [`FileIO`] writes, see [`FileIO::line_ending`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n` everywhere
+    #[default]
+    Lf,
+    /// `\r\n` everywhere
+    CrLf,
+    /// Whatever the file's first line ends with, and the serializer's own for a new file
+    Preserve,
+}
+
+impl LineEnding {
+    fn apply(self, s: String, path: &Path) -> io::Result<String> {
+        let crlf = match self {
+            Self::Lf => false,
+            Self::CrLf => true,
+            Self::Preserve => match fs::read(path) {
+                Ok(existing) => {
+                    let first = existing.split(|&b| b == b'\n').next().unwrap_or_default();
+                    existing.len() > first.len() && first.ends_with(b"\r")
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(s),
+                Err(e) => Err(e)?,
+            },
+        };
+        let lf = s.replace("\r\n", "\n");
+        Ok(if crlf { lf.replace('\n', "\r\n") } else { lf })
+    }
+}
+
+/// Easy access to the a file (configuration file, data file, etc.)
+/// Provides (safe?) load and save operations
+pub struct FileIO<T, S = ()> {
+    _content: std::marker::PhantomData<T>,
+    _serde: std::marker::PhantomData<S>,
+    pub path: PathBuf,
+    observer: Option<FileIoObserver>,
+    temp_dir: Option<PathBuf>,
+    backup_only_changes: bool,
+    line_ending: LineEnding,
+    save_options: SaveOptions,
+    #[cfg(feature = "gzip")]
+    compress_backups: bool,
+}
+
+/// What [`FileIO::save`] and the other whole-file writes, such as [`FileIO::save_value`],
+/// [`FileIO::set_path`] or [`FileIO::patch`], do beyond writing the file, set through the
+/// builder methods; in-place writers like `push_to_array_tail` and `save_gzip` ignore
+/// them, and [`FileIO::push_to_array`] or [`FileIO::save_durable`] keep their own guarantees
+#[derive(Clone, Copy, Debug, Default)]
+struct SaveOptions {
+    atomic: bool,
+    durable: bool,
+    #[cfg(feature = "lock")]
+    locked: bool,
+    retries: usize,
+}
+
+impl<T, S> Clone for FileIO<T, S> {
+    fn clone(&self) -> Self {
+        Self {
+            _content: std::marker::PhantomData,
+            _serde: std::marker::PhantomData,
+            path: self.path.clone(),
+            observer: self.observer.clone(),
+            temp_dir: self.temp_dir.clone(),
+            backup_only_changes: self.backup_only_changes,
+            line_ending: self.line_ending,
+            save_options: self.save_options,
+            #[cfg(feature = "gzip")]
+            compress_backups: self.compress_backups,
+        }
+    }
+}
+
+impl<T: SerdeStr> From<PathBuf> for FileIO<T> {
+    fn from(path: PathBuf) -> Self {
+        Self::new(path)
+    }
+}
+
+impl<T: SerdeStr> From<&Path> for FileIO<T> {
+    fn from(path: &Path) -> Self {
+        Self::new(path.to_path_buf())
+    }
+}
+
+impl<T: SerdeStr> From<String> for FileIO<T> {
+    fn from(path: String) -> Self {
+        Self::new(path.into())
+    }
+}
+
+impl<T: SerdeStr> From<&str> for FileIO<T> {
+    fn from(path: &str) -> Self {
+        Self::new(path.into())
+    }
+}
+
+impl<T> FileIO<T>
+where
+    T: SerdeStr,
+{
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            _content: std::marker::PhantomData,
+            _serde: std::marker::PhantomData,
+            path,
+            observer: None,
+            temp_dir: None,
+            backup_only_changes: false,
+            line_ending: LineEnding::default(),
+            save_options: SaveOptions::default(),
+            #[cfg(feature = "gzip")]
+            compress_backups: false,
+        }
+    }
+    /// Reports every load, save and backup to `observer`
+    ///
+    /// Reporting is best-effort: a panicking observer never fails the operation.
+    pub fn on_event(mut self, observer: FileIoObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+    /// Stages atomic saves in `dir` instead of next to the file, e.g. when the file lives
+    /// on a slow or read-mostly mount
+    ///
+    /// Staged files are named uniquely per save, so files of the same name, or threads
+    /// saving the same file, can share `dir`. If `dir` is on another file system, the
+    /// final rename fails and the staged file is copied over the target instead, so
+    /// readers may then observe a half-written file.
+    pub fn with_temp_dir(mut self, dir: PathBuf) -> Self {
+        self.temp_dir = Some(dir);
+        self
+    }
+    /// Makes [`FileIO::backup_and_save`] a no-op, neither backing up nor writing, when
+    /// the serialized content equals the file's, so repeated saves don't pile up backups
+    pub fn backup_only_changes(mut self) -> Self {
+        self.backup_only_changes = true;
+        self
+    }
+    /// Normalizes the line endings of everything saved from `T`, LF unless set otherwise,
+    /// so that saves from different platforms don't churn diffs
+    pub fn line_ending(mut self, ending: LineEnding) -> Self {
+        self.line_ending = ending;
+        self
+    }
+    /// Makes [`FileIO::save`] write through a temp file renamed over the target, as
+    /// [`FileIO::save_atomic`] does
+    ///
+    /// Like the other save options, this applies to every method rewriting the whole file,
+    /// such as [`FileIO::save_value`], [`FileIO::save_if_changed`], [`FileIO::set_path`]
+    /// or [`FileIO::patch`], not just [`FileIO::save`].
+    pub fn atomic_saves(mut self) -> Self {
+        self.save_options.atomic = true;
+        self
+    }
+    /// Makes [`FileIO::save`] fsync the file before returning, and the parent directory
+    /// too for [atomic saves](FileIO::atomic_saves)
+    pub fn durable_saves(mut self) -> Self {
+        self.save_options.durable = true;
+        self
+    }
+    /// Makes [`FileIO::save`] hold the [`FileIO::lock`] while writing, so saving while
+    /// already holding the lock waits forever
+    #[cfg(feature = "lock")]
+    pub fn locked_saves(mut self) -> Self {
+        self.save_options.locked = true;
+        self
+    }
+    /// Makes [`FileIO::save`] try again up to `retries` times after transient failures,
+    /// such as `Interrupted`, `TimedOut` or `ResourceBusy`
+    pub fn retry_saves(mut self, retries: usize) -> Self {
+        self.save_options.retries = retries;
+        self
+    }
+    /// `T` serialized as it is saved, with the line endings normalized
+    fn serialize(&self, conf: &T) -> io::Result<String> {
+        let mut bytes = Vec::new();
+        conf.ser_to_writer(&mut bytes)?;
+        let string =
+            String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.line_ending.apply(string, &self.path)
+    }
+    /// Gzips the backups made by [`FileIO::backup_and_save`] into `<name>.<ts>.bak.gz`,
+    /// while the file itself stays plain for editing
+    #[cfg(feature = "gzip")]
+    pub fn compress_backups(mut self) -> Self {
+        self.compress_backups = true;
+        self
+    }
+    fn emit(&self, event: FileIoEvent) {
+        if let Some(observer) = &self.observer {
+            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| observer(event)));
+        }
+    }
+    fn emit_loaded(&self, trace: &Trace, bytes: usize) {
+        trace.bytes(bytes);
+        self.emit(FileIoEvent::Loaded {
+            path: self.path.clone(),
+            bytes,
+        });
+    }
+    fn emit_saved(&self, trace: &Trace, bytes: usize) {
+        trace.bytes(bytes);
+        self.emit(FileIoEvent::Saved {
+            path: self.path.clone(),
+            bytes,
+        });
+    }
+    /// Runs `op` in a `file_io` tracing span recording `op`, the path, and once done the
+    /// bytes `run` reports through its [`Trace`] and the duration
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn traced<R>(
+        &self,
+        op: &'static str,
+        run: impl FnOnce(&Trace) -> io::Result<R>,
+    ) -> io::Result<R> {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::field::Empty;
+            let path = self.path.display();
+            let trace = Trace {
+                span: tracing::info_span!(
+                    "file_io",
+                    op,
+                    %path,
+                    bytes = Empty,
+                    duration_us = Empty
+                ),
+            };
+            let _entered = trace.span.enter();
+            let start = std::time::Instant::now();
+            let res = run(&trace);
+            (trace.span).record("duration_us", start.elapsed().as_micros() as u64);
+            res
+        }
+        #[cfg(not(feature = "tracing"))]
+        run(&Trace {})
+    }
+    /// Creates the parent directory, after checking the path isn't a directory itself
+    pub(crate) fn ensure_parent(&self) -> io::Result<()> {
+        self.ensure_not_dir()?;
+        let parent = self.path.parent().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The path has no parent directory",
+            )
+        })?;
+        fs::create_dir_all(parent)?;
+        Ok(())
+    }
+    fn ensure_not_dir(&self) -> io::Result<()> {
+        if self.path.is_dir() {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("path is a directory: {}", self.path.display()),
+            ))?;
+        }
+        Ok(())
+    }
+    fn format(&self) -> io::Result<Format> {
+        format_of(&self.path)
+    }
+    /// Parses the file by its extension's [`Format`] into an untyped `Value`, for tools
+    /// that inspect or edit configs without knowing `T`
+    pub fn load_value(&self) -> io::Result<Value> {
+        self.traced("load", |trace| {
+            let format = self.format()?;
+            let string = read_text(&self.path)?;
+            trace.bytes(string.len());
+            format.parse_value(&string)
+        })
+    }
+    /// Checks that the file is well-formed in its extension's [`Format`] without building a
+    /// `T`, e.g. for a fast lint step; whether it fits `T` is left to [`FileIO::load`]
+    pub fn check_syntax(&self) -> io::Result<()> {
+        self.load_value().map(|_| ())
+    }
+    /// Loads the file in the first of `formats` it parses in, whatever its extension, e.g.
+    /// for importing user-provided files of unknown format, returning which one it was
+    ///
+    /// Goes through [`Value`] rather than `T`'s [`SerdeStr`]. If no format fits, fails with
+    /// `InvalidData` listing each format's error.
+    pub fn load_try_formats(&self, formats: &[Format]) -> io::Result<(T, Format)> {
+        self.traced("load", |trace| {
+            let string = read_text(&self.path)?;
+            trace.bytes(string.len());
+            let mut errors = Vec::with_capacity(formats.len());
+            for &format in formats {
+                match format.parse_value(&string).and_then(from_value) {
+                    Ok(conf) => return Ok((conf, format)),
+                    Err(e) => errors.push(format!("as {:?}: {}", format, e)),
+                }
+            }
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} parses in none of the formats tried ({})",
+                    self.path.display(),
+                    errors.join("; ")
+                ),
+            ))
+        })
+    }
+    /// Writes an untyped `Value` in the file's [`Format`], bypassing `T` entirely
+    ///
+    /// Values the format can't hold, such as a `null` in TOML, fail with the format's error.
+    pub fn save_value(&self, value: &Value) -> io::Result<()> {
+        self.traced("save", |trace| {
+            let format = self.format()?;
+            self.ensure_parent()?;
+            let s = format.value_to_string(value)?;
+            self.write_saved(trace, s.as_bytes())?;
+            Ok(())
+        })
+    }
+    /// Loads the file; a leading UTF-8 byte order mark is skipped
+    pub fn load(&self) -> io::Result<T> {
+        let (conf, _) = self.load_raw()?;
+        Ok(conf)
+    }
+    /// Loads the file over an existing value, e.g. to reuse a large config's allocations
+    ///
+    /// `.json` files go through serde's in-place deserialization, which only reuses
+    /// memory when serde's `deserialize_in_place` feature is on and otherwise assigns a
+    /// fresh value; if parsing fails midway, `target` may be partially updated. Other
+    /// formats always load a fresh value and assign it, leaving `target` intact on errors.
+    #[cfg_attr(not(feature = "json"), allow(unused_variables))]
+    pub fn load_into(&self, target: &mut T) -> io::Result<()> {
+        self.traced("load", |trace| {
+            #[cfg(feature = "json")]
+            if Format::from_path(&self.path) == Some(Format::Json) {
+                self.ensure_parent()?;
+                let string = read_text(&self.path)?;
+                let mut de = serde_json::Deserializer::from_str(&string);
+                T::deserialize_in_place(&mut de, target)?;
+                de.end()?;
+                self.emit_loaded(trace, string.len());
+                return Ok(());
+            }
+            *target = self.load()?;
+            Ok(())
+        })
+    }
+    /// Reads the file as a stream of JSON values, deserialized one at a time so memory stays
+    /// bounded by the largest one, whatever the extension
+    ///
+    /// A file starting with `[` yields the elements of that top-level array; otherwise it
+    /// holds values separated by whitespace, like NDJSON. An element that fails to
+    /// deserialize is reported without ending the stream, unlike malformed JSON.
+    pub fn load_stream<U: serde::de::DeserializeOwned>(
+        &self,
+    ) -> io::Result<impl Iterator<Item = io::Result<U>>> {
+        self.traced("load", |_| {
+            use io::BufRead;
+            self.ensure_parent()?;
+            let mut reader = io::BufReader::new(fs::File::open(&self.path)?);
+            let is_array = loop {
+                let buf = reader.fill_buf()?;
+                match buf.iter().position(|byte| !byte.is_ascii_whitespace()) {
+                    Some(start) => {
+                        let is_array = buf[start] == b'[';
+                        reader.consume(start + is_array as usize);
+                        break is_array;
+                    }
+                    None if buf.is_empty() => break false,
+                    None => {
+                        let len = buf.len();
+                        reader.consume(len);
+                    }
+                }
+            };
+            Ok(if is_array {
+                JsonStream::Elements {
+                    reader,
+                    first: true,
+                    done: false,
+                }
+            } else {
+                JsonStream::Values(serde_json::Deserializer::from_reader(reader).into_iter())
+            })
+        })
+    }
+    /// Loads the file along with its detected format, size and modification time
+    pub fn load_with_meta(&self) -> io::Result<(T, LoadMeta)> {
+        let conf = self.load()?;
+        let metadata = fs::metadata(&self.path)?;
+        let meta = LoadMeta {
+            format: Format::from_path(&self.path),
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        };
+        Ok((conf, meta))
+    }
+    /// [`FileIO::load`] that retries a few times, milliseconds apart, when parsing fails,
+    /// assuming a concurrent writer left the file half-written, e.g. while being watched
+    ///
+    /// A file that stays corrupt still fails, only later.
+    pub fn load_stable(&self) -> io::Result<T> {
+        retry_torn(|| self.load())
+    }
+    /// Loads the file only if it was modified strictly after `since`, e.g. the
+    /// [`LoadMeta::modified`] of the last load, returning `Ok(None)` otherwise
+    pub fn load_if_newer(&self, since: std::time::SystemTime) -> io::Result<Option<T>> {
+        if fs::metadata(&self.path)?.modified()? <= since {
+            return Ok(None);
+        }
+        self.load().map(Some)
+    }
+    /// Loads the file, returning it only if it serializes differently from `current`, the
+    /// comparison [`FileIO::save_if_changed`] makes, so watch handlers can skip no-op edits
+    pub fn reload(&self, current: &T) -> io::Result<Option<T>> {
+        let new = self.load()?;
+        let changed = SerdeStr::ser_to_string(&new)? != SerdeStr::ser_to_string(current)?;
+        Ok(changed.then_some(new))
+    }
+    /// Loads the file, returning `Ok(None)` only if it doesn't exist
+    ///
+    /// Unlike [`FileIO::load_or_init`], a present but unreadable or corrupt file is an error,
+    /// so callers can avoid clobbering it.
+    pub fn try_load(&self) -> io::Result<Option<T>> {
+        match self.load() {
+            Ok(conf) => Ok(Some(conf)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    /// [`FileIO::load`] refusing files over `max_bytes` with `InvalidData`, before reading
+    /// them, so an untrusted oversized file can't exhaust memory
+    ///
+    /// Gzip content must stay within the limit once decompressed too.
+    pub fn load_bounded(&self, max_bytes: u64) -> io::Result<T> {
+        self.traced("load", |trace| {
+            self.ensure_parent()?;
+            let string = read_text_bounded(&self.path, max_bytes)?;
+            let conf = T::de_from_reader(string.as_bytes())?;
+            self.emit_loaded(trace, string.len());
+            Ok(conf)
+        })
+    }
+    /// Loads `T` from the front matter of e.g. a Markdown file: the block between a first
+    /// line of `---` and the next `---` line, parsed with `T`'s own format, ignoring the body
+    ///
+    /// A file not starting with a front-matter block fails with `InvalidData`.
+    pub fn load_front_matter(&self) -> io::Result<T> {
+        self.traced("load", |trace| {
+            let text = read_text(&self.path)?;
+            trace.bytes(text.len());
+            let block = front_matter(&text).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("no `---` front matter in {}", self.path.display()),
+                )
+            })?;
+            SerdeStr::de_from_str(block)
+        })
+    }
+    /// Loads the content along with the text it was parsed from
+    ///
+    /// That text is decoded as for [`FileIO::load`]: gzip files come back decompressed and
+    /// a leading byte order mark is dropped, so it can differ from the bytes on disk; use
+    /// `fs::read` for those.
+    pub fn load_raw(&self) -> io::Result<(T, String)> {
+        self.traced("load", |trace| {
+            self.ensure_parent()?;
+            let string = read_text(&self.path.canonicalize()?)?;
+            let conf = T::de_from_reader(string.as_bytes())?;
+            self.emit_loaded(trace, string.len());
+            Ok((conf, string))
+        })
+    }
+    /// [`FileIO::load`] without creating the parent directory, for readers that must
+    /// leave the file system as they found it
+    #[cfg(feature = "lock")]
+    pub(crate) fn load_read_only(&self) -> io::Result<T> {
+        self.traced("load", |trace| {
+            self.ensure_not_dir()?;
+            let string = read_text(&self.path)?;
+            let conf = T::de_from_reader(string.as_bytes())?;
+            self.emit_loaded(trace, string.len());
+            Ok(conf)
+        })
+    }
+    /// Saves the content; no save ever writes a byte order mark
+    ///
+    /// With every builder option enabled, each attempt goes: take the lock, write the temp
+    /// file, fsync it, rename it over the target, fsync the directory, release the lock.
+    /// A failed attempt releases the lock before any retry, and an attempt failing before
+    /// the rename leaves the target untouched.
+    pub fn save(&self, conf: &T) -> io::Result<()> {
+        self.save_reporting(conf).map(|_| ())
+    }
+    /// [`FileIO::save`] that also returns the number of bytes written
+    pub fn save_reporting(&self, conf: &T) -> io::Result<usize> {
+        self.traced("save", |trace| {
+            self.ensure_parent()?;
+            let s = self.serialize(conf)?;
+            self.write_saved(trace, s.as_bytes())?;
+            Ok(s.len())
+        })
+    }
+    /// Writes `bytes` as the whole file under its [`SaveOptions`], retrying transient
+    /// failures; every method rewriting the whole file goes through here
+    fn write_saved(&self, trace: &Trace, bytes: &[u8]) -> io::Result<()> {
+        let mut retries = self.save_options.retries;
+        loop {
+            match self.write_with_options(trace, bytes) {
+                Err(e) if retries > 0 && is_transient(&e) => {
+                    log::warn!("retrying save of {}: {}", self.path.display(), e);
+                    retries -= 1;
+                    std::thread::sleep(SAVE_RETRY_DELAY);
+                }
+                res => return res,
+            }
+        }
+    }
+    /// One attempt of [`FileIO::save`] under its [`SaveOptions`]
+    fn write_with_options(&self, trace: &Trace, bytes: &[u8]) -> io::Result<()> {
+        let options = self.save_options;
+        #[cfg(feature = "lock")]
+        let _lock = options.locked.then(|| self.lock()).transpose()?;
+        if options.atomic {
+            return self.write_atomic(trace, bytes, options.durable);
+        }
+        let mut file = fs::File::create(&self.path)?;
+        file.write_all(bytes)?;
+        if options.durable {
+            file.sync_all()?;
+        }
+        self.emit_saved(trace, bytes.len());
+        Ok(())
+    }
+    /// Saves only if the serialized content differs from the file's, returning whether it
+    /// wrote; spares the file's modification time and watchers on no-op saves
+    pub fn save_if_changed(&self, conf: &T) -> io::Result<bool> {
+        self.traced("save", |trace| {
+            let s = self.serialize(conf)?;
+            match fs::read(&self.path) {
+                Ok(existing) if existing == s.as_bytes() => return Ok(false),
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e)?,
+                _ => {}
+            }
+            self.ensure_parent()?;
+            self.write_saved(trace, s.as_bytes())?;
+            Ok(true)
+        })
+    }
+    /// Saves only if `conf` differs from the file's content as a value, returning what
+    /// changed, see [`diff_json`](crate::diff_json); `None` means nothing was written
+    ///
+    /// A missing file is reported as the whole of `conf` added at the empty path.
+    pub fn save_if_changed_diff(&self, conf: &T) -> io::Result<Option<Vec<crate::JsonChange>>> {
+        let old = match self.try_load()? {
+            Some(old) => Some(to_value(&old)?),
+            None => None,
+        };
+        let changes = crate::diff::diff_from(old.as_ref(), &to_value(conf)?);
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        self.save(conf)?;
+        Ok(Some(changes))
+    }
+    /// How the file has drifted from `expected`, compared as values: each change goes from
+    /// `expected` as old to the file's content as new, and none means they're in sync
+    ///
+    /// Fields the file has but `T` doesn't know are dropped on load and never reported.
+    pub fn drift(&self, expected: &T) -> io::Result<Vec<crate::JsonChange>> {
+        let current = to_value(&self.load()?)?;
+        Ok(crate::diff_json(&to_value(expected)?, &current))
+    }
+    /// Saves gzip-compressed; every load recognizes the result by its magic bytes
+    #[cfg(feature = "gzip")]
+    pub fn save_gzip(&self, conf: &T) -> io::Result<()> {
+        self.traced("save", |trace| {
+            self.ensure_parent()?;
+            let file = fs::File::create(&self.path)?;
+            let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            let s = self.serialize(conf)?;
+            encoder.write_all(s.as_bytes())?;
+            encoder.finish()?;
+            self.emit_saved(trace, fs::metadata(&self.path)?.len() as usize);
+            Ok(())
+        })
+    }
+    /// Loads a file stored in `encoding` rather than UTF-8, e.g. a legacy Windows-1252 one
+    ///
+    /// A byte order mark is not sniffed, and bytes that are malformed in `encoding` fail
+    /// with `InvalidData` instead of being replaced.
+    #[cfg(feature = "encoding")]
+    pub fn load_with_encoding(&self, encoding: &'static encoding_rs::Encoding) -> io::Result<T> {
+        self.traced("load", |trace| {
+            self.ensure_parent()?;
+            let bytes = fs::read(&self.path)?;
+            let string = encoding
+                .decode_without_bom_handling_and_without_replacement(&bytes)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} is not valid {}", self.path.display(), encoding.name()),
+                    )
+                })?;
+            let conf = SerdeStr::de_from_str(&string)?;
+            self.emit_loaded(trace, bytes.len());
+            Ok(conf)
+        })
+    }
+    /// Saves re-encoded into `encoding`, the counterpart of [`FileIO::load_with_encoding`]
+    ///
+    /// Characters `encoding` can't represent fail with `InvalidData` rather than being
+    /// written as HTML character references. UTF-16 encodings save as UTF-8, as
+    /// `encoding_rs` doesn't encode into them.
+    #[cfg(feature = "encoding")]
+    pub fn save_with_encoding(
+        &self,
+        conf: &T,
+        encoding: &'static encoding_rs::Encoding,
+    ) -> io::Result<()> {
+        self.traced("save", |trace| {
+            let s = self.serialize(conf)?;
+            let (bytes, _, unmappable) = encoding.encode(&s);
+            if unmappable {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("content can't be represented in {}", encoding.name()),
+                ))?;
+            }
+            self.ensure_parent()?;
+            self.write_saved(trace, &bytes)?;
+            Ok(())
+        })
+    }
+    /// Saves TOML with a `# <doc>` comment above each top-level key found in `docs`
+    ///
+    /// Best-effort and top-level only: nested keys aren't documented, and the file
+    /// must be a `.toml` one. Handy for self-documenting defaults in `load_or_init`.
+    #[cfg(feature = "toml")]
+    pub fn save_documented(&self, conf: &T, docs: &[(&str, &str)]) -> io::Result<()> {
+        self.traced("save", |trace| {
+            if self.format()? != Format::Toml {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "documented saves are only supported for TOML",
+                ))?;
+            }
+            self.ensure_parent()?;
+            let s = document_toml(&SerdeStr::ser_to_string(conf)?, docs);
+            let s = self.line_ending.apply(s, &self.path)?;
+            self.write_saved(trace, s.as_bytes())?;
+            Ok(())
+        })
+    }
+    /// Serializes in the file's format with the keys of every map sorted, at any depth,
+    /// so equal data always yields identical bytes, e.g. for committing or hashing
+    ///
+    /// Goes through [`Value`] rather than `T`'s [`SerdeStr`], so the format comes from
+    /// the extension.
+    pub fn to_string_stable(&self, conf: &T) -> io::Result<String> {
+        let mut value = to_value(conf)?;
+        sort_keys(&mut value);
+        self.format()?.value_to_string(&value)
+    }
+    /// Saves [`FileIO::to_string_stable`]'s output
+    pub fn save_stable(&self, conf: &T) -> io::Result<()> {
+        self.traced("save", |trace| {
+            let s = self.to_string_stable(conf)?;
+            self.ensure_parent()?;
+            self.write_saved(trace, s.as_bytes())?;
+            Ok(())
+        })
+    }
+    /// Scaffolds the file from `template`, with every `${NAME}` placeholder filled from
+    /// `vars`, once the result is checked to deserialize into `T`
+    ///
+    /// The text is written as filled, keeping the template's comments and layout, and
+    /// atomically. Unfilled placeholders fail with `InvalidInput`, a result that doesn't
+    /// parse as `T` with the parse error, and in both cases nothing is written.
+    pub fn save_from_template(
+        &self,
+        template: &str,
+        vars: &std::collections::HashMap<String, String>,
+    ) -> io::Result<()> {
+        self.traced("save", |trace| {
+            let filled = fill_template(template, vars)?;
+            T::de_from_str(&filled)?;
+            self.ensure_parent()?;
+            self.write_atomic(trace, filled.as_bytes(), false)
+        })
+    }
+    /// Saves and fsyncs the file before returning, so the content survives a power loss
+    ///
+    /// Noticeably slower than [`FileIO::save`], as every call waits for the device.
+    pub fn save_durable(&self, conf: &T) -> io::Result<()> {
+        self.traced("save", |trace| {
+            self.ensure_parent()?;
+            let s = self.serialize(conf)?;
+            let mut file = fs::File::create(&self.path)?;
+            file.write_all(s.as_bytes())?;
+            file.sync_all()?;
+            self.emit_saved(trace, s.len());
+            Ok(())
+        })
+    }
+    /// Loads the file for editing through the returned guard, which saves it back
+    /// atomically once dropped, so a load-mutate-save can't forget the save
+    pub fn edit_guard(&self) -> io::Result<EditGuard<T>> {
+        Ok(EditGuard {
+            file_io: self.clone(),
+            conf: Some(self.load()?),
+        })
+    }
+    /// [`FileIO::save_atomic`] that first captures the current content, so that the save
+    /// can be undone through the returned guard, e.g. if a later step fails
+    pub fn save_reversible(&self, conf: &T) -> io::Result<SaveGuard<T>> {
+        self.ensure_parent()?;
+        let previous = match fs::read(&self.path) {
+            Ok(previous) => Some(previous),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => Err(e)?,
+        };
+        self.save_atomic(conf)?;
+        Ok(SaveGuard {
+            file_io: self.clone(),
+            previous,
+        })
+    }
+    /// Saves into a temp file next to the target and renames it over the target,
+    /// so readers never observe a half-written file
+    pub fn save_atomic(&self, conf: &T) -> io::Result<()> {
+        self.traced("save", |trace| {
+            self.ensure_parent()?;
+            let s = self.serialize(conf)?;
+            self.write_atomic(trace, s.as_bytes(), false)
+        })
+    }
+    /// [`FileIO::save_atomic`] that also fsyncs the temp file and the parent directory,
+    /// so the rename itself is durable; pays the cost of two syncs per call
+    pub fn save_atomic_durable(&self, conf: &T) -> io::Result<()> {
+        self.traced("save", |trace| {
+            self.ensure_parent()?;
+            let s = self.serialize(conf)?;
+            self.write_atomic(trace, s.as_bytes(), true)
+        })
+    }
+    /// The name temp files for this file start with, see [`crate::temp::create_unique`]
+    fn temp_prefix(&self) -> std::borrow::Cow<'_, str> {
+        self.path.file_name().unwrap_or_default().to_string_lossy()
+    }
+    /// A new temp file for atomic saves to write before renaming, next to the file or in
+    /// [`FileIO::with_temp_dir`]'s directory
+    fn staging_file(&self) -> io::Result<(TempFile, fs::File)> {
+        let beside = self.path.parent().unwrap_or(Path::new(""));
+        let dir = self.temp_dir.as_deref().unwrap_or(beside);
+        TempFile::create(dir, &self.temp_prefix())
+    }
+    fn write_atomic(&self, trace: &Trace, bytes: &[u8], durable: bool) -> io::Result<()> {
+        let (temp, mut file) = self.staging_file()?;
+        // the temp file has default permissions, so carry over a chmod'ed target's mode
+        #[cfg(unix)]
+        if let Ok(existing) = fs::metadata(&self.path) {
+            file.set_permissions(existing.permissions())?;
+        }
+        file.write_all(bytes)?;
+        if durable {
+            file.sync_all()?;
+        }
+        drop(file);
+        temp.persist(&self.path)?;
+        if durable {
+            sync_parent(&self.path)?;
+        }
+        self.emit_saved(trace, bytes.len());
+        Ok(())
+    }
+    /// Loads a possibly partial file, filling absent fields from `T::default()`
+    ///
+    /// The format is picked from the file extension; keys present on disk win.
+    pub fn load_with_defaults(&self) -> io::Result<T>
+    where
+        T: Default,
+    {
+        let mut value = to_value(&T::default())?;
+        merge_values(&mut value, self.load_value()?);
+        from_value(value)
+    }
+    /// Resolves the configuration from every layer, each deep-merged over the previous:
+    ///
+    /// 1. `T::default()`
+    /// 2. the file, if it exists
+    /// 3. `<PREFIX>_...` environment variables, as in [`FileIO::load_with_env_overlay`]
+    /// 4. `cli`, e.g. flags collected into a [`Value`]
+    pub fn resolve(&self, prefix: &str, cli: Option<Value>) -> io::Result<T>
+    where
+        T: Default,
+    {
+        let mut value = to_value(&T::default())?;
+        match self.load_value() {
+            Ok(file) => merge_values(&mut value, file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => Err(e)?,
+        }
+        overlay_env(&mut value, prefix, std::env::vars())?;
+        if let Some(cli) = cli {
+            merge_values(&mut value, cli);
+        }
+        from_value(value)
+    }
+    /// Loads the file and lets environment variables override it
+    ///
+    /// `<PREFIX>_SERVER__PORT=9090` sets `server.port`: the rest of the name is lowercased
+    /// and `__` separates nesting levels. Values take the type of the field they replace.
+    pub fn load_with_env_overlay(&self, prefix: &str) -> io::Result<T> {
+        let mut value = self.load_value()?;
+        overlay_env(&mut value, prefix, std::env::vars())?;
+        from_value(value)
+    }
+    /// Loads the file with the dotted `keys` filled in from the OS keyring, so that
+    /// tokens never sit in the plaintext file
+    ///
+    /// Each key is looked up as the user of an entry in `service` and injected as a string
+    /// before deserializing into `T`; a key without an entry fails with `NotFound`.
+    #[cfg(feature = "keyring")]
+    pub fn load_with_keyring(&self, keys: &[&str], service: &str) -> io::Result<T> {
+        self.load_with_secrets(keys, |key| keyring_secret(service, key))
+    }
+    #[cfg(feature = "keyring")]
+    fn load_with_secrets(
+        &self,
+        keys: &[&str],
+        lookup: impl Fn(&str) -> io::Result<String>,
+    ) -> io::Result<T> {
+        let mut value = self.load_value()?;
+        overlay_secrets(&mut value, keys, lookup)?;
+        from_value(value)
+    }
+    /// Flattens the file into env pairs for a subprocess, the inverse of
+    /// [`FileIO::load_with_env_overlay`]: `server.port = 9090` becomes
+    /// `("<PREFIX>_SERVER__PORT", "9090")`
+    ///
+    /// Strings are passed as-is and other scalars in their JSON form; nulls are left out,
+    /// and arrays are written as JSON, which the overlay reads back as a plain string.
+    pub fn to_env(&self, prefix: &str) -> io::Result<Vec<(String, String)>> {
+        let value = self.load_value()?;
+        let mut pairs = Vec::new();
+        match &value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    let name = format!("{}_{}", prefix, key.to_uppercase());
+                    flatten_env(name, value, &mut pairs)?;
+                }
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a table", self.path.display()),
+            ))?,
+        }
+        Ok(pairs)
+    }
+    /// Deserializes only the value at a dotted path such as `server.port`
+    ///
+    /// The rest of the file isn't checked against `T`; a missing path is `NotFound`.
+    pub fn get_path<V: for<'de> Deserialize<'de>>(&self, dotted: &str) -> io::Result<V> {
+        let value = self.load_value()?;
+        from_value(lookup(&value, dotted)?.clone())
+    }
+    /// [`FileIO::get_path`] addressed by an RFC 6901 JSON Pointer such as `/server/port`,
+    /// where keys may contain dots (`/a.b`), `/` escaped as `~1` and `~` as `~0`
+    pub fn get_pointer<V: for<'de> Deserialize<'de>>(&self, ptr: &str) -> io::Result<V> {
+        let value = self.load_value()?;
+        pointer_tokens(ptr)?;
+        let found = value.pointer(ptr).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no value at `{}`", ptr))
+        })?;
+        from_value(found.clone())
+    }
+    /// [`FileIO::set_path`] addressed by a JSON Pointer, see [`FileIO::get_pointer`];
+    /// missing objects are created, while array elements must already exist
+    pub fn set_pointer<V: Serialize>(&self, ptr: &str, value: V) -> io::Result<()> {
+        self.traced("save", |trace| {
+            let format = self.format()?;
+            let mut root = match self.load_value() {
+                Ok(root) => root,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Value::Object(Default::default()),
+                Err(e) => Err(e)?,
+            };
+            *pointer_slot_mut(&mut root, ptr)? = to_value(&value)?;
+            self.ensure_parent()?;
+            let s = format.value_to_string(&root)?;
+            self.write_saved(trace, s.as_bytes())?;
+            Ok(())
+        })
+    }
+    /// Loads the `profiles.<profile>` table alone as `T`, for files holding several named
+    /// profiles such as `[profiles.dev]` and `[profiles.prod]`
+    ///
+    /// `profile` is one key, even if it contains dots; a missing one is `NotFound`.
+    pub fn load_profile(&self, profile: &str) -> io::Result<T> {
+        let mut value = self.load_value()?;
+        let section = value
+            .get_mut("profiles")
+            .and_then(|profiles| profiles.get_mut(profile))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no profile `{}` in {}", profile, self.path.display()),
+                )
+            })?;
+        from_value(section.take())
+    }
+    /// Loads every entry of the `profiles` table as `T`, keyed by profile name, such as for
+    /// listing all profiles; a file without profiles gives an empty map
+    ///
+    /// One malformed profile fails the whole load with an `InvalidData` error naming it,
+    /// rather than being skipped, so a typo never silently hides a profile.
+    pub fn load_all_profiles(&self) -> io::Result<BTreeMap<String, T>> {
+        let mut value = self.load_value()?;
+        let profiles = match value.get_mut("profiles").map(Value::take) {
+            None => return Ok(BTreeMap::new()),
+            Some(Value::Object(profiles)) => profiles,
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("`profiles` in {} is not a table", self.path.display()),
+            ))?,
+        };
+        profiles
+            .into_iter()
+            .map(|(name, profile)| {
+                let conf = from_value(profile).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("profile `{}` in {}: {}", name, self.path.display(), e),
+                    )
+                })?;
+                Ok((name, conf))
+            })
+            .collect()
+    }
+    /// Sets the value at a dotted path such as `server.port`, creating the file and
+    /// intermediate tables as needed, and writes the file back without going through `T`
+    ///
+    /// Replacing a table with a non-table value is refused, as is descending into a non-table.
+    pub fn set_path<V: Serialize>(&self, dotted: &str, value: V) -> io::Result<()> {
+        self.traced("save", |trace| {
+            let format = self.format()?;
+            let mut root = match self.load_value() {
+                Ok(root) => root,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Value::Object(Default::default()),
+                Err(e) => Err(e)?,
+            };
+            let keys: Vec<&str> = dotted.split('.').collect();
+            let slot = slot_mut(&mut root, &keys)?;
+            let value = to_value(&value)?;
+            if slot.is_object() && !value.is_object() {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("`{}` is a table and cannot be set to a plain value", dotted),
+                ))?;
+            }
+            *slot = value;
+            self.ensure_parent()?;
+            let s = format.value_to_string(&root)?;
+            self.write_saved(trace, s.as_bytes())?;
+            Ok(())
+        })
+    }
+    /// Loads the file after running every migration newer than its stored version
+    ///
+    /// The version is the integer at `version_field` (absent means 0); migrations run in
+    /// version order, each stamping its version, and an upgraded file is saved back.
+    /// A file newer than the last migration is refused rather than misread.
+    pub fn load_migrated(&self, version_field: &str, migrations: &[Migration]) -> io::Result<T> {
+        self.traced("load", |trace| {
+            let mut value = self.load_value()?;
+            let stored = value
+                .get(version_field)
+                .and_then(Value::as_u64)
+                .unwrap_or(0);
+            let mut steps = migrations.to_vec();
+            steps.sort_by_key(|(version, _)| *version);
+            let current = steps.last().map_or(0, |(version, _)| *version as u64);
+            if stored > current {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} is at version {}, newer than the supported {}",
+                        self.path.display(),
+                        stored,
+                        current
+                    ),
+                ))?;
+            }
+            let pending: Vec<_> = steps
+                .into_iter()
+                .filter(|(v, _)| *v as u64 > stored)
+                .collect();
+            if pending.is_empty() {
+                return from_value(value);
+            }
+            for (version, migrate) in pending {
+                value = migrate(value);
+                if let Value::Object(map) = &mut value {
+                    map.insert(version_field.to_owned(), version.into());
+                }
+            }
+            let conf = from_value(value.clone())?;
+            let s = self.format()?.value_to_string(&value)?;
+            self.write_saved(trace, s.as_bytes())?;
+            Ok(conf)
+        })
+    }
+    /// Loads the file and deep-merges each of `overrides` over it in order, e.g.
+    /// `config.toml` then `config.prod.toml`; missing overrides are skipped
+    ///
+    /// Every file is read in the format of its own extension, see [`merge_values`].
+    pub fn load_layered(&self, overrides: &[PathBuf]) -> io::Result<T> {
+        self.load_layered_with(overrides, &ArrayMerge::Replace)
+    }
+    /// [`FileIO::load_layered`] merging arrays as `arrays` says, see [`merge_values_with`]
+    pub fn load_layered_with(&self, overrides: &[PathBuf], arrays: &ArrayMerge) -> io::Result<T> {
+        self.traced("load", |_| {
+            let mut value = self.load_value()?;
+            for path in overrides {
+                let string = match read_text(path) {
+                    Ok(string) => string,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => Err(e)?,
+                };
+                let layer = format_of(path)?.parse_value(&string).map_err(|e| {
+                    io::Error::new(e.kind(), format!("in {}: {}", path.display(), e))
+                })?;
+                merge_values_with(&mut value, layer, arrays);
+            }
+            from_value(value)
+        })
+    }
+    /// Loads the file after merging in the files listed under its `include_key`, a path
+    /// or list of paths relative to the including file; includes may nest
+    ///
+    /// Later includes are merged over earlier ones and the including file over all of them;
+    /// the key itself is dropped before deserializing, and include cycles are rejected.
+    pub fn load_with_includes(&self, include_key: &str) -> io::Result<T> {
+        self.traced("load", |_| {
+            self.ensure_parent()?;
+            from_value(load_included(&self.path, include_key, &mut Vec::new())?)
+        })
+    }
+    /// Loads the file if it is present and parseable, otherwise parses `default_str`
+    /// (e.g. an `include_str!`ed default) without touching the file system
+    pub fn load_or_embedded(&self, default_str: &str) -> io::Result<T> {
+        self.traced("load", |_| {
+            match read_text(&self.path).and_then(|string| T::de_from_str(&string)) {
+                Ok(conf) => Ok(conf),
+                Err(_) => T::de_from_str(default_str),
+            }
+        })
+    }
+    pub fn load_or_init(&self, init: impl Fn() -> T) -> io::Result<T> {
+        match self.load() {
+            Ok(conf) => Ok(conf),
+            Err(_) => {
+                let conf = init();
+                self.backup_and_save(&conf)?;
+                Ok(conf)
+            }
+        }
+    }
+    fn backup_path_at(&self, timestamp: i64) -> PathBuf {
+        let mut ext = self
+            .path
+            .extension()
+            .unwrap_or_default()
+            .to_owned()
+            .into_string()
+            .unwrap_or_default();
+        ext += ".";
+        ext += &timestamp.to_string();
+        ext += ".bak";
+        #[cfg(feature = "gzip")]
+        if self.compress_backups {
+            ext += ".gz";
+        }
+        self.path.with_extension(ext)
+    }
+    /// The path [`FileIO::backup_and_save`] would move the current file to right now,
+    /// or `None` if there is no file to back up
+    pub fn backup_path(&self) -> Option<PathBuf> {
+        if !self.path.exists() {
+            return None;
+        }
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        Some(self.backup_path_at(now))
+    }
+    /// Restores the defaults, e.g. for a "reset settings" action, keeping the current
+    /// file recoverable through [`FileIO::backup_and_save`]
+    pub fn reset_to_default(&self) -> io::Result<()>
+    where
+        T: Default,
+    {
+        self.backup_and_save(&T::default())
+    }
+    pub fn backup_and_save(&self, conf: &T) -> io::Result<()> {
+        self.backup_and_save_at(conf, time::OffsetDateTime::now_utc())
+    }
+    /// [`FileIO::backup_and_save`] naming the backup after `now` instead of the real clock
+    pub fn backup_and_save_at(&self, conf: &T, now: time::OffsetDateTime) -> io::Result<()> {
+        self.traced("backup", |_| {
+            self.ensure_parent()?;
+            if self.backup_only_changes {
+                match fs::read(&self.path) {
+                    Ok(existing) if existing == self.serialize(conf)?.as_bytes() => {
+                        return Ok(());
+                    }
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e)?,
+                    _ => {}
+                }
+            }
+            if self.path.exists() {
+                let backup_path = self.backup_path_at(now.unix_timestamp());
+                self.move_to_backup(&backup_path)?;
+                self.emit(FileIoEvent::BackedUp {
+                    from: self.path.clone(),
+                    to: backup_path,
+                });
+            }
+            self.save(conf)?;
+            Ok(())
+        })
+    }
+    /// Moves the file to `backup_path`, compressing it on the way if configured
+    ///
+    /// A compressed backup is staged in a temp file and renamed into place, so a failure
+    /// never leaves a truncated `.gz` behind.
+    fn move_to_backup(&self, backup_path: &Path) -> io::Result<()> {
+        #[cfg(feature = "gzip")]
+        if self.compress_backups {
+            let plain = fs::read(&self.path)?;
+            let dir = match backup_path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            let prefix = backup_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let (temp, file) = TempFile::create(dir, &prefix)?;
+            let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            encoder.write_all(&plain)?;
+            drop(encoder.finish()?);
+            temp.persist(backup_path)?;
+            return fs::remove_file(&self.path);
+        }
+        fs::rename(&self.path, backup_path)
+    }
+    /// Backup timestamps and paths found next to the file, unordered
+    fn backup_entries(&self) -> io::Result<Vec<(i64, PathBuf)>> {
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => Err(e)?,
+        };
+        let mut found = Vec::new();
+        for entry in entries {
+            let file_name = entry?.file_name();
+            let backup = parse_backup_name(&file_name);
+            if let Some((_, timestamp)) = backup.filter(|(base, _)| *base == name) {
+                found.push((timestamp, self.path.with_file_name(file_name)));
+            }
+        }
+        Ok(found)
+    }
+    /// The backups of this file, newest first
+    ///
+    /// Names are listed up front to order them, while each backup's metadata is only
+    /// read as the iterator reaches it. Files not named like a backup are skipped.
+    pub fn backups(&self) -> impl Iterator<Item = io::Result<BackupInfo>> {
+        let (error, mut found) = match self.backup_entries() {
+            Ok(found) => (None, found),
+            Err(e) => (Some(e), Vec::new()),
+        };
+        found.sort_by(|a, b| b.cmp(a));
+        error
+            .map(Err)
+            .into_iter()
+            .chain(found.into_iter().map(|(timestamp, path)| {
+                let timestamp = time::OffsetDateTime::from_unix_timestamp(timestamp)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let size = fs::metadata(&path)?.len();
+                Ok(BackupInfo {
+                    path,
+                    timestamp,
+                    size,
+                })
+            }))
+    }
+    /// Renames the file to `new_path` along with its backups, which keep their timestamps,
+    /// then points `self` at `new_path`
+    ///
+    /// Fails with `AlreadyExists` rather than replacing an existing file or backup at
+    /// `new_path`. The backups move first and the file last; if any rename fails, those
+    /// already made are undone, so the file and its backups stay together.
+    pub fn move_to(&mut self, new_path: &Path) -> io::Result<()> {
+        let old_name = self.path.file_name().unwrap_or_default().len();
+        let new_name = new_path.file_name().unwrap_or_default().to_owned();
+        let mut renames = Vec::new();
+        for (_, backup) in self.backup_entries()? {
+            // `<old name>.<timestamp>.bak[.gz]`, so the suffix after the old name is ASCII
+            let name = backup.file_name().unwrap_or_default().as_encoded_bytes();
+            let suffix = String::from_utf8_lossy(&name[old_name..]);
+            let mut target = new_name.clone();
+            target.push(&*suffix);
+            renames.push((backup, new_path.with_file_name(target)));
+        }
+        renames.push((self.path.clone(), new_path.to_path_buf()));
+        if let Some((_, taken)) = renames.iter().find(|(_, to)| to.exists()) {
+            Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", taken.display()),
+            ))?;
+        }
+        FileIO::<T>::new(new_path.to_path_buf()).ensure_parent()?;
+        for (done, (from, to)) in renames.iter().enumerate() {
+            if let Err(e) = fs::rename(from, to) {
+                for (from, to) in renames[..done].iter().rev() {
+                    let _ = fs::rename(to, from);
+                }
+                Err(e)?;
+            }
+        }
+        self.path = new_path.to_path_buf();
+        Ok(())
+    }
+    /// Puts the content of the newest backup back in place, atomically, decompressing
+    /// `.bak.gz` backups; the backup itself is kept, and `NotFound` means there is none
+    pub fn restore_latest_backup(&self) -> io::Result<()> {
+        self.traced("restore", |trace| {
+            let latest = self.backup_entries()?.into_iter().max().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no backups of {}", self.path.display()),
+                )
+            })?;
+            let (_, path) = latest;
+            // byte for byte, so backups in any encoding restore
+            let bytes = fs::read(&path)?;
+            #[cfg(feature = "gzip")]
+            let bytes = if bytes.starts_with(&[0x1f, 0x8b]) {
+                let mut plain = Vec::new();
+                flate2::read::GzDecoder::new(&bytes[..]).read_to_end(&mut plain)?;
+                plain
+            } else {
+                bytes
+            };
+            self.write_atomic(trace, &bytes, false)
+        })
+    }
+    /// The [`snap`](crate::ShaSnap::snap) digest of the file's raw bytes, streamed without
+    /// parsing them, e.g. to detect changes across runs; `NotFound` if the file is missing
+    #[cfg(feature = "sha_snap")]
+    pub fn checksum(&self) -> io::Result<String> {
+        crate::snap_file(&self.path)
+    }
+    /// A digest of the file's content as a `T`, hashed as [`snap_jcs`](crate::snap_jcs), so
+    /// that format, whitespace and key order don't matter while changes to the data do
+    ///
+    /// Unlike [`FileIO::checksum`], the same data as JSON and as TOML fingerprints equally.
+    /// The file is parsed by its extension's [`Format`] and passed through `T`, so fields
+    /// `T` doesn't know are ignored and missing defaults are filled in.
+    #[cfg(feature = "serde_snap")]
+    pub fn value_fingerprint(&self) -> io::Result<String> {
+        let conf: T = from_value(self.load_value()?)?;
+        crate::snap_jcs(&conf)
+    }
+    /// [`FileIO::value_fingerprint`] of only the values at `paths`, dotted as in
+    /// [`FileIO::get_path`], e.g. to tell whether the fields needing a restart changed
+    ///
+    /// The values are hashed in the order of `paths`, straight from the file without
+    /// going through `T`. A missing path is framed as absent, distinct from any value,
+    /// `null` included.
+    #[cfg(feature = "serde_snap")]
+    pub fn value_fingerprint_paths(&self, paths: &[&str]) -> io::Result<String> {
+        use crate::ShaSnap;
+        let value = self.load_value()?;
+        let mut parts = Vec::with_capacity(paths.len());
+        for dotted in paths {
+            let part = match lookup(&value, dotted) {
+                Ok(found) => format!("+{}", crate::snap_jcs(found)?),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => "-".to_owned(),
+                Err(e) => Err(e)?,
+            };
+            parts.push(part);
+        }
+        let parts: Vec<&[u8]> = parts.iter().map(|part| part.as_bytes()).collect();
+        Ok(crate::frame(&parts).snap())
+    }
+    /// One digest over the file and each of `members` in order, e.g. its includes and
+    /// secrets sidecar, so tampering with any part of a logical config is detected
+    ///
+    /// Each file's [`checksum`](FileIO::checksum) is framed into the result, so the order
+    /// of `members` matters; any missing file is `NotFound`.
+    #[cfg(feature = "sha_snap")]
+    pub fn verify_bundle(&self, members: &[PathBuf]) -> io::Result<String> {
+        use crate::ShaSnap;
+        let checksums = std::iter::once(&self.path)
+            .chain(members)
+            .map(|path| crate::snap_file(path))
+            .collect::<io::Result<Vec<_>>>()?;
+        let parts: Vec<&[u8]> = checksums.iter().map(|c| c.as_bytes()).collect();
+        Ok(crate::frame(&parts).snap())
+    }
+    /// Writes the [`FileIO::verify_bundle`] digest to `manifest`, returning it
+    #[cfg(feature = "sha_snap")]
+    pub fn save_bundle_manifest(&self, members: &[PathBuf], manifest: &Path) -> io::Result<String> {
+        let digest = self.verify_bundle(members)?;
+        fs::write(manifest, &digest)?;
+        Ok(digest)
+    }
+    /// Whether the bundle still matches the digest stored by
+    /// [`FileIO::save_bundle_manifest`]; a missing manifest is `NotFound`
+    #[cfg(feature = "sha_snap")]
+    pub fn check_bundle_manifest(&self, members: &[PathBuf], manifest: &Path) -> io::Result<bool> {
+        let stored = fs::read_to_string(manifest)?;
+        Ok(self.verify_bundle(members)? == stored.trim())
+    }
+    /// Records the file's [`FileIO::checksum`] under `P`'s state directory (its data
+    /// directory where there is none), returning whether it changed since the last call,
+    /// e.g. to skip re-processing an unchanged config across runs
+    ///
+    /// The first call for a file returns `true`. Records are keyed by the canonical path.
+    #[cfg(all(feature = "sha_snap", feature = "project_info"))]
+    pub fn remember_hash<P: crate::ProjectInfo>(&self) -> io::Result<bool> {
+        use crate::ShaSnap;
+        let checksum = self.checksum()?;
+        let key = self
+            .path
+            .canonicalize()?
+            .as_os_str()
+            .as_encoded_bytes()
+            .snap();
+        let dir = P::state_dir().unwrap_or_else(P::data_dir).join("hashes");
+        let record = dir.join(&key[..32]);
+        match fs::read_to_string(&record) {
+            Ok(stored) if stored == checksum => return Ok(false),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e)?,
+            _ => {}
+        }
+        fs::create_dir_all(&dir)?;
+        fs::write(&record, &checksum)?;
+        Ok(true)
+    }
+    /// Copies the file to `<state dir>/snapshots/<file name>/<name>` under `P` (its data
+    /// directory where there is none), a named restore point beyond the timestamped
+    /// backups, atomically replacing any earlier snapshot of that name
+    ///
+    /// Snapshots are kept per file name, so files of `P` with different names can reuse
+    /// snapshot names. A `name` that isn't a plain file name fails with `InvalidInput`,
+    /// and a missing file with `NotFound`.
+    #[cfg(feature = "project_info")]
+    pub fn snapshot<P: crate::ProjectInfo>(&self, name: &str) -> io::Result<()> {
+        self.traced("snapshot", |trace| {
+            let path = snapshot_path::<P>(&self.path, name)?;
+            let bytes = fs::read(&self.path)?;
+            trace.bytes(bytes.len());
+            let dir = path.parent().expect("snapshots have a parent");
+            fs::create_dir_all(dir)?;
+            // staged next to the file's snapshot directory, so listings never see it
+            let staging = dir.parent().expect("snapshots have a parent");
+            let prefix = dir.file_name().unwrap_or_default().to_string_lossy();
+            let (temp, mut file) = TempFile::create(staging, &format!("{}.{}", prefix, name))?;
+            file.write_all(&bytes)?;
+            drop(file);
+            temp.persist(&path)
+        })
+    }
+    /// Puts the [`FileIO::snapshot`] named `name` back in place, atomically; the snapshot
+    /// itself is kept, and `NotFound` means there is none
+    #[cfg(feature = "project_info")]
+    pub fn restore_snapshot<P: crate::ProjectInfo>(&self, name: &str) -> io::Result<()> {
+        self.traced("restore", |trace| {
+            let bytes = fs::read(snapshot_path::<P>(&self.path, name)?)?;
+            self.ensure_parent()?;
+            self.write_atomic(trace, &bytes, false)
+        })
+    }
+    /// The names of this file's snapshots under `P`, sorted
+    #[cfg(feature = "project_info")]
+    pub fn list_snapshots<P: crate::ProjectInfo>(&self) -> io::Result<Vec<String>> {
+        let dir = snapshot_dir::<P>(&self.path)?;
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => Err(e)?,
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            names.extend(entry?.file_name().into_string());
+        }
+        names.sort();
+        Ok(names)
+    }
+    /// A stable identifier for this content at this location: the framed canonical path
+    /// and content digest, hashed; `NotFound` if the file is missing
+    #[cfg(feature = "sha_snap")]
+    pub fn content_id(&self) -> io::Result<String> {
+        let path = self.path.canonicalize()?;
+        let digest = crate::snap_reader(fs::File::open(&path)?)?;
+        let mut hasher = crate::FramedHasher::new();
+        hasher.update(path.as_os_str().as_encoded_bytes());
+        hasher.update(digest);
+        Ok(hasher.finalize())
+    }
+    /// Saves atomically as a snapshot: [`SNAPSHOT_MAGIC`], a version byte and the
+    /// SHA-512 of the payload, followed by the serialized payload itself
+    #[cfg(feature = "sha_snap")]
+    pub fn save_snapshot(&self, conf: &T) -> io::Result<()> {
+        self.traced("save", |trace| {
+            use sha2::Digest as _;
+            self.ensure_parent()?;
+            let payload = self.serialize(conf)?;
+            let mut bytes = Vec::with_capacity(SNAPSHOT_HEADER_LEN + payload.len());
+            bytes.extend_from_slice(SNAPSHOT_MAGIC);
+            bytes.push(SNAPSHOT_VERSION);
+            bytes.extend_from_slice(&sha2::Sha512::digest(payload.as_bytes()));
+            bytes.extend_from_slice(payload.as_bytes());
+            self.write_atomic(trace, &bytes, false)
+        })
+    }
+    /// Streams `reader` into the file through an atomic temp file while hashing it, e.g. a
+    /// download, and only renames it into place if its [`snap`](crate::ShaSnap::snap)
+    /// digest is `expected`
+    ///
+    /// On a mismatch the temp file is removed, the file is left as it was, and the error
+    /// is `InvalidData`. The content isn't parsed as `T`.
+    #[cfg(feature = "sha_snap")]
+    pub fn save_verified_from_reader<R: Read>(
+        &self,
+        mut reader: R,
+        expected: &str,
+    ) -> io::Result<()> {
+        self.traced("save", |trace| {
+            self.ensure_parent()?;
+            let (temp, file) = self.staging_file()?;
+            let mut writer = crate::HashingWriter::new(file);
+            let bytes = io::copy(&mut reader, &mut writer)?;
+            let (file, digest) = writer.into_parts();
+            drop(file);
+            if !digest.eq_ignore_ascii_case(expected.trim()) {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "digest mismatch for {}: expected {}, got {}",
+                        self.path.display(),
+                        expected,
+                        digest
+                    ),
+                ))?;
+            }
+            temp.persist(&self.path)?;
+            self.emit_saved(trace, bytes as usize);
+            Ok(())
+        })
+    }
+    /// Loads a file written by [`FileIO::save_snapshot`], failing with `InvalidData`
+    /// if the header is malformed or the payload doesn't match its embedded hash
+    #[cfg(feature = "sha_snap")]
+    pub fn load_snapshot(&self) -> io::Result<T> {
+        self.traced("load", |trace| {
+            use sha2::Digest as _;
+            self.ensure_parent()?;
+            let bytes = fs::read(&self.path)?;
+            let invalid = |msg: &str| {
+                let msg = format!("{}: {}", self.path.display(), msg);
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            };
+            if bytes.len() < SNAPSHOT_HEADER_LEN || !bytes.starts_with(SNAPSHOT_MAGIC) {
+                Err(invalid("not a snapshot"))?;
+            }
+            let (header, payload) = bytes.split_at(SNAPSHOT_HEADER_LEN);
+            if header[SNAPSHOT_MAGIC.len()] != SNAPSHOT_VERSION {
+                Err(invalid("unsupported snapshot version"))?;
+            }
+            if header[SNAPSHOT_MAGIC.len() + 1..] != sha2::Sha512::digest(payload)[..] {
+                Err(invalid("snapshot payload doesn't match its hash"))?;
+            }
+            let string = std::str::from_utf8(payload).map_err(|e| invalid(&e.to_string()))?;
+            let conf = SerdeStr::de_from_str(string)?;
+            self.emit_loaded(trace, bytes.len());
+            Ok(conf)
+        })
+    }
+    /// Creates the file empty if it is missing, otherwise only bumps its modification time
+    pub fn touch(&self) -> io::Result<()> {
+        self.ensure_parent()?;
+        let file = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        file.set_modified(std::time::SystemTime::now())
+    }
+    /// Exchanges the contents of this file and `other`, e.g. to activate a staged config
+    ///
+    /// On Linux this is a single atomic `renameat2(RENAME_EXCHANGE)`, so neither path is ever
+    /// missing. Elsewhere, or on file systems without support, it falls back to three renames
+    /// through a temp file, during which `self.path` briefly does not exist; a failed
+    /// rename undoes the ones before it, leaving both files as they were.
+    pub fn swap_with(&self, other: &Path) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        match exchange(&self.path, other) {
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) => {}
+            res => return res,
+        }
+        let beside = self.path.parent().unwrap_or(Path::new(""));
+        let (file, temp) = crate::temp::create_unique(beside, &self.temp_prefix())?;
+        drop(file);
+        if let Err(e) = fs::rename(&self.path, &temp) {
+            let _ = fs::remove_file(&temp);
+            Err(e)?;
+        }
+        if let Err(e) = fs::rename(other, &self.path) {
+            fs::rename(&temp, &self.path)?;
+            Err(e)?;
+        }
+        if let Err(e) = fs::rename(&temp, other) {
+            // puts both files back where they were
+            fs::rename(&self.path, other)?;
+            fs::rename(&temp, &self.path)?;
+            Err(e)?;
+        }
+        Ok(())
+    }
+    /// Appends `value` to the array the file holds, saving atomically; a missing or blank
+    /// file starts out as `[]`
+    ///
+    /// This is a read-modify-write of the whole file: concurrent pushes from several
+    /// processes can lose entries unless serialized, e.g. with `FileIO::lock`.
+    pub fn push_to_array(&self, value: &impl Serialize) -> io::Result<()> {
+        self.traced("save", |trace| {
+            let format = self.format()?;
+            let mut array = match read_text(&self.path) {
+                Ok(string) if string.trim().is_empty() => Value::Array(Vec::new()),
+                Ok(string) => format.parse_value(&string)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Value::Array(Vec::new()),
+                Err(e) => Err(e)?,
+            };
+            let Value::Array(items) = &mut array else {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} does not hold an array", self.path.display()),
+                ))?
+            };
+            items.push(to_value(value)?);
+            self.ensure_parent()?;
+            self.write_atomic(trace, format.value_to_string(&array)?.as_bytes(), false)
+        })
+    }
+    /// [`FileIO::push_to_array`] for large `.json` ledgers: rewrites only the closing `]`
+    /// in place instead of the whole file
+    ///
+    /// Not atomic: a crash mid-write can leave the array unterminated, and concurrent
+    /// pushes must be serialized just the same. Falls back to [`FileIO::push_to_array`]
+    /// for other formats, and for files that are missing, blank or don't end in `]`.
+    #[cfg(feature = "json")]
+    pub fn push_to_array_tail(&self, value: &impl Serialize) -> io::Result<()> {
+        self.traced("save", |trace| {
+            use io::{Seek, SeekFrom};
+            const TAIL: u64 = 4096;
+            if self.format()? != Format::Json {
+                return self.push_to_array(value);
+            }
+            let mut file = match fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&self.path)
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return self.push_to_array(value),
+                Err(e) => Err(e)?,
+            };
+            let len = file.metadata()?.len();
+            let start = len.saturating_sub(TAIL);
+            let mut tail = Vec::new();
+            file.seek(SeekFrom::Start(start))?;
+            file.read_to_end(&mut tail)?;
+            let mut rev = tail
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|(_, byte)| !byte.is_ascii_whitespace());
+            let (close, before) = match (rev.next(), rev.next()) {
+                (Some((close, b']')), Some((_, before))) => (close, *before),
+                _ => return self.push_to_array(value),
+            };
+            let mut appended = if before == b'[' {
+                Vec::new()
+            } else {
+                vec![b',']
+            };
+            serde_json::to_writer(&mut appended, value)?;
+            appended.push(b']');
+            let close = start + close as u64;
+            file.set_len(close)?;
+            file.seek(SeekFrom::Start(close))?;
+            file.write_all(&appended)?;
+            self.emit_saved(trace, appended.len());
+            Ok(())
+        })
+    }
+    /// Deep-merges `overlay` (see [`merge_values`]) into the file, checking that the
+    /// result still deserializes into `T` before writing
+    ///
+    /// With the `toml_edit` feature, `.toml` files are edited in place so that comments,
+    /// ordering and formatting outside the replaced values survive; there a null removes
+    /// the key, and new tables are written inline. Other files are rewritten by `T`.
+    #[cfg_attr(not(feature = "toml_edit"), allow(unused_variables))]
+    pub fn patch(&self, overlay: Value) -> io::Result<()> {
+        self.traced("save", |trace| {
+            #[cfg(feature = "toml_edit")]
+            if self.format()? == Format::Toml {
+                let Value::Object(overlay) = overlay else {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "a TOML patch must be a table",
+                    ))?
+                };
+                self.ensure_parent()?;
+                let mut doc: toml_edit::DocumentMut = fs::read_to_string(&self.path)?
+                    .parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                patch_toml_table(doc.as_table_mut(), overlay)?;
+                let s = doc.to_string();
+                T::de_from_str(&s)?;
+                self.write_saved(trace, s.as_bytes())?;
+                return Ok(());
+            }
+            let mut value = self.load_value()?;
+            merge_values(&mut value, overlay);
+            self.save(&from_value(value)?)
+        })
+    }
+    /// Opens the file in the editor picked by [`resolve_editor`] and waits for it, see
+    /// [`editor_command`]
+    pub fn edit(&self) -> io::Result<()> {
+        let status = editor_command(&self.path)?.status()?;
+        if !status.success() {
+            Err(io::Error::other(
+                "failed to edit config file and exit gracefully",
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> FileIO<T>
+where
+    T: Serialize + for<'de> Deserialize<'de>,
+{
+    /// Rewrites `from` into `to`, each in the format of its extension, validating through `T`
+    pub fn convert(from: &Path, to: &Path) -> io::Result<()> {
+        let value = format_of(from)?.parse_value(&read_text(from)?)?;
+        let conf: T = from_value(value)?;
+        let string = format_of(to)?.value_to_string(&to_value(&conf)?)?;
+        fs::write(to, string)
+    }
+}
+
+/// Where [`FileIO::snapshot`] keeps the snapshots of `P`'s files named like `file`
+#[cfg(feature = "project_info")]
+fn snapshot_dir<P: crate::ProjectInfo>(file: &Path) -> io::Result<PathBuf> {
+    let name = file.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has no file name to snapshot", file.display()),
+        )
+    })?;
+    let dir = P::state_dir().unwrap_or_else(P::data_dir);
+    Ok(dir.join("snapshots").join(name))
+}
+
+/// Where [`FileIO::snapshot`] keeps the snapshot `name` of `file`
+#[cfg(feature = "project_info")]
+fn snapshot_path<P: crate::ProjectInfo>(file: &Path, name: &str) -> io::Result<PathBuf> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("`{}` is not a valid snapshot name", name),
+        ))?;
+    }
+    Ok(snapshot_dir::<P>(file)?.join(name))
+}
+
+/// Removes the directory of a [`FileIO::temp`] file, and everything in it, once dropped
+#[cfg(feature = "test-util")]
+#[derive(Debug)]
+pub struct TempGuard {
+    dir: PathBuf,
+}
+
+#[cfg(feature = "test-util")]
+impl Drop for TempGuard {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Tells apart the directories [`FileIO::temp`] creates within this process
+#[cfg(feature = "test-util")]
+static TEMP_DIR_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[cfg(feature = "test-util")]
+impl<T: SerdeStr> FileIO<T> {
+    /// A not yet existing file in a fresh directory under the system temp directory, for
+    /// tests; the guard removes the directory, including backups and locks made next to
+    /// the file, once dropped
+    ///
+    /// The file has no extension, so [`Format`]-based methods need
+    /// [`FileIO::temp_with_extension`]. Panics if the directory can't be created.
+    pub fn temp() -> (Self, TempGuard) {
+        Self::temp_with_extension("")
+    }
+    /// [`FileIO::temp`] for a file named with `extension`, such as `json`
+    pub fn temp_with_extension(extension: &str) -> (Self, TempGuard) {
+        let dir = loop {
+            let n = TEMP_DIR_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let name = format!("sculptor-{}-{}", std::process::id(), n);
+            let dir = std::env::temp_dir().join(name);
+            match fs::create_dir(&dir) {
+                Ok(()) => break dir,
+                // left over by an earlier process with the same pid
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => panic!("failed to create {}: {}", dir.display(), e),
+            }
+        };
+        let path = dir.join("file").with_extension(extension);
+        (Self::new(path), TempGuard { dir })
+    }
+}
+
+/// Where a [`SourceIO`] reads and writes its text, e.g. a file, an HTTP endpoint or
+/// an embedded default; sources are read-only unless they implement `write`
+pub trait Source {
+    fn read(&self) -> io::Result<String>;
+    fn write(&self, string: &str) -> io::Result<()> {
+        let _ = string;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this config source is read-only",
+        ))
+    }
+}
+
+/// The file system source, with the same directory checks, line endings and events as
+/// [`FileIO::load`] and [`FileIO::save`]
+impl<T: SerdeStr> Source for FileIO<T> {
+    fn read(&self) -> io::Result<String> {
+        self.traced("load", |trace| {
+            self.ensure_not_dir()?;
+            let string = read_text(&self.path)?;
+            self.emit_loaded(trace, string.len());
+            Ok(string)
+        })
+    }
+    fn write(&self, string: &str) -> io::Result<()> {
+        self.traced("save", |trace| {
+            self.ensure_parent()?;
+            let s = self.line_ending.apply(string.to_owned(), &self.path)?;
+            self.write_saved(trace, s.as_bytes())?;
+            Ok(())
+        })
+    }
+}
+
+/// The serde layer of [`FileIO`] over any [`Source`]
+pub struct SourceIO<T, Src> {
+    _content: std::marker::PhantomData<T>,
+    source: Src,
+}
+
+impl<T, Src> SourceIO<T, Src>
+where
+    T: SerdeStr,
+    Src: Source,
+{
+    pub fn new(source: Src) -> Self {
+        Self {
+            _content: std::marker::PhantomData,
+            source,
+        }
+    }
+    pub fn source(&self) -> &Src {
+        &self.source
+    }
+    pub fn load(&self) -> io::Result<T> {
+        T::de_from_str(&self.source.read()?)
+    }
+    pub fn save(&self, conf: &T) -> io::Result<()> {
+        self.source.write(&conf.ser_to_string()?)
+    }
+}
+
+/// A base directory that [`FileIO`]s can be created under
+#[derive(Clone, Debug)]
+pub struct FileIORoot {
+    pub base: PathBuf,
+}
+
+impl FileIORoot {
+    pub fn new(base: PathBuf) -> Self {
+        Self { base }
+    }
+    /// A [`FileIO`] for `relative` resolved under the base directory
+    pub fn file<T: SerdeStr>(&self, relative: impl AsRef<Path>) -> FileIO<T> {
+        FileIO::new(self.base.join(relative))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether any temp file staged for `path` is still next to it
+    fn temps_left(path: &Path) -> bool {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        fs::read_dir(dir.unwrap_or(Path::new(".")))
+            .unwrap()
+            .any(|entry| {
+                let entry = entry.unwrap().file_name().into_string().unwrap();
+                entry.starts_with(&format!("{}.", name)) && entry.ends_with(".tmp")
+            })
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default)]
+    struct Conf {
+        pub name: String,
+    }
+    impl SerdeStr for Conf {
+        fn de_from_str(string: &str) -> Result<Self, io::Error> {
+            let json = serde_json::from_str(string)?;
+            Ok(json)
+        }
+        fn ser_to_string(&self) -> Result<String, io::Error> {
+            let s = serde_json::to_string(self)?;
+            Ok(s)
+        }
+    }
+
+    #[test]
+    fn file_io_str() {
+        let path = PathBuf::from("test_file_io_str.json");
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let s = serde_json::to_string(&conf).unwrap();
+        fs::write(&path, s).unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let loaded_conf = file_io.load();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded_conf.unwrap(), conf);
+    }
+
+    #[test]
+    fn file_io_save() {
+        let path = PathBuf::from("test_file_io_save.json");
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io.save(&conf).unwrap();
+        let loaded_conf = file_io.load();
+        fs::remove_file(&path).unwrap();
         assert_eq!(loaded_conf.unwrap(), conf);
     }
+
+    #[test]
+    fn file_io_save_reporting() {
+        let path = PathBuf::from("test_file_io_save_reporting.json");
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let written = FileIO::<Conf>::new(path.clone())
+            .save_reporting(&conf)
+            .unwrap();
+        let on_disk = fs::metadata(&path).unwrap().len();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written as u64, on_disk);
+    }
+
+    #[test]
+    fn file_io_directory_path() {
+        let path = PathBuf::from("test_file_io_directory_path.json");
+        fs::create_dir_all(&path).unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let loaded = file_io.load();
+        let saved = file_io.save(&Conf {
+            name: "test".to_string(),
+        });
+        fs::remove_dir(&path).unwrap();
+        for err in [loaded.unwrap_err(), saved.unwrap_err()] {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().contains("path is a directory"));
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    #[test]
+    fn file_io_load_with_keyring() {
+        // never touch the real keyring of whoever runs the tests
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+        let path = PathBuf::from("test_file_io_load_with_keyring.json");
+        fs::write(&path, r#"{"name":"placeholder"}"#).unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let mock = std::collections::HashMap::from([("name", "s3cret")]);
+        let injected = file_io.load_with_secrets(&["name"], |key| {
+            Ok(mock.get(key).ok_or(io::ErrorKind::NotFound)?.to_string())
+        });
+        let missing = file_io.load_with_keyring(&["name"], "sculptor-test-missing");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(injected.unwrap().name, "s3cret");
+        let missing = missing.unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        assert!(missing.to_string().contains("sculptor-test-missing"));
+    }
+
+    #[cfg(feature = "prefetch")]
+    #[test]
+    fn file_io_prefetch_then_load() {
+        let paths: Vec<_> = (0..3)
+            .map(|i| PathBuf::from(format!("test_file_io_prefetch_{}.json", i)))
+            .collect();
+        for path in &paths {
+            FileIO::<Conf>::new(path.clone())
+                .save(&Conf { name: "p".into() })
+                .unwrap();
+        }
+        let missing = PathBuf::from("test_file_io_prefetch_missing.json");
+        let read = prefetch(&[paths[0].clone(), missing, paths[2].clone()]);
+        let loaded: Vec<_> = paths
+            .iter()
+            .map(|path| FileIO::<Conf>::new(path.clone()).load())
+            .collect();
+        for path in &paths {
+            fs::remove_file(path).unwrap();
+        }
+        assert_eq!(*read[0].as_ref().unwrap(), r#"{"name":"p"}"#.len() as u64);
+        assert_eq!(
+            read[1].as_ref().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(read[2].is_ok());
+        assert!(loaded.into_iter().all(|conf| conf.unwrap().name == "p"));
+    }
+
+    #[test]
+    fn file_io_load_skips_bom() {
+        let path = PathBuf::from("test_file_io_load_skips_bom.json");
+        fs::write(&path, b"\xef\xbb\xbf{\"name\":\"bom\"}").unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let loaded = file_io.load();
+        file_io.save(&Conf { name: "bom".into() }).unwrap();
+        let saved = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().name, "bom");
+        assert!(saved.starts_with(b"{"));
+    }
+
+    #[test]
+    fn file_io_exit_codes() {
+        use sysexits::*;
+        let path = PathBuf::from("test_file_io_exit_codes.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let missing = file_io.load().unwrap_err();
+        fs::write(&path, "{\"name\": 1}").unwrap();
+        let malformed = file_io.load().unwrap_err();
+        fs::write(&path, "{\"name\":").unwrap();
+        let truncated = file_io.load().unwrap_err();
+        fs::remove_file(&path).unwrap();
+        let directory = FileIO::<Conf>::new(PathBuf::from("src"))
+            .load()
+            .unwrap_err();
+        assert_eq!(exit_code(&missing), EX_NOINPUT);
+        assert_eq!(exit_code(&malformed), EX_DATAERR);
+        assert_eq!(exit_code(&truncated), EX_DATAERR);
+        assert_eq!(exit_code(&directory), EX_USAGE);
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert_eq!(exit_code(&denied), EX_NOPERM);
+        let locked = io::Error::from(io::ErrorKind::WouldBlock);
+        assert_eq!(exit_code(&locked), EX_TEMPFAIL);
+        assert_eq!(exit_code(&io::Error::other("disk")), EX_IOERR);
+    }
+
+    #[test]
+    fn file_io_load_bounded() {
+        let path = PathBuf::from("test_file_io_load_bounded.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io
+            .save(&Conf {
+                name: "x".repeat(100),
+            })
+            .unwrap();
+        let within = file_io.load_bounded(1000);
+        let over = file_io.load_bounded(50);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(within.unwrap().name.len(), 100);
+        let over = over.unwrap_err();
+        assert_eq!(over.kind(), io::ErrorKind::InvalidData);
+        assert!(over.to_string().starts_with("config too large"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_save_stable_sorts_nested_maps() {
+        use std::collections::HashMap;
+        #[derive(Serialize, Deserialize)]
+        struct Nested {
+            zones: HashMap<String, HashMap<String, Vec<HashMap<String, u32>>>>,
+        }
+        impl SerdeStr for Nested {
+            fn de_from_str(string: &str) -> Result<Self, io::Error> {
+                Ok(serde_json::from_str(string)?)
+            }
+            fn ser_to_string(&self) -> Result<String, io::Error> {
+                Ok(serde_json::to_string(self)?)
+            }
+        }
+        let build = |reversed: bool| {
+            let mut keys: Vec<_> = (0..8).map(|i| format!("k{}", i)).collect();
+            if reversed {
+                keys.reverse();
+            }
+            let leaf: HashMap<_, _> = keys.iter().map(|k| (k.clone(), 1)).collect();
+            let inner: HashMap<_, _> = keys
+                .iter()
+                .map(|k| (k.clone(), vec![leaf.clone()]))
+                .collect();
+            Nested {
+                zones: keys.iter().map(|k| (k.clone(), inner.clone())).collect(),
+            }
+        };
+        let path = PathBuf::from("test_file_io_save_stable.json");
+        let file_io = FileIO::<Nested>::new(path.clone());
+        file_io.save_stable(&build(false)).unwrap();
+        let first = fs::read(&path).unwrap();
+        file_io.save_stable(&build(true)).unwrap();
+        let second = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(first, second);
+        let text = String::from_utf8(first).unwrap();
+        assert!(text.starts_with(r#"{"zones":{"k0":{"k0":[{"k0":1,"k1":1,"#));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_to_env() {
+        let path = PathBuf::from("test_file_io_to_env.json");
+        fs::write(
+            &path,
+            r#"{"name":"app","server":{"port":9090,"tls":false,"hosts":["a","b"]},"gone":null}"#,
+        )
+        .unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let pairs = file_io.to_env("APP");
+        fs::remove_file(&path).unwrap();
+        let mut pairs = pairs.unwrap();
+        pairs.sort();
+        let expected = [
+            ("APP_NAME", "app"),
+            ("APP_SERVER__HOSTS", r#"["a","b"]"#),
+            ("APP_SERVER__PORT", "9090"),
+            ("APP_SERVER__TLS", "false"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(pairs, expected);
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn file_io_load_profile() {
+        let path = PathBuf::from("test_file_io_load_profile.toml");
+        fs::write(
+            &path,
+            "[profiles.dev]\nname = \"dev\"\n\n[profiles.prod]\nname = \"prod\"\n",
+        )
+        .unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let prod = file_io.load_profile("prod");
+        let missing = file_io.load_profile("staging");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(prod.unwrap().name, "prod");
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn file_io_load_front_matter() {
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Post {
+            title: String,
+            tags: Vec<String>,
+        }
+        impl SerdeStr for Post {
+            fn de_from_str(string: &str) -> Result<Self, io::Error> {
+                serde_yaml::from_str(string).map_err(io::Error::other)
+            }
+            fn ser_to_string(&self) -> Result<String, io::Error> {
+                serde_yaml::to_string(self).map_err(io::Error::other)
+            }
+        }
+        let path = PathBuf::from("test_file_io_load_front_matter.md");
+        let file_io = FileIO::<Post>::new(path.clone());
+        fs::write(
+            &path,
+            "---\ntitle: Hello\ntags:\n  - a\n  - b\n---\n# Hello\n\n---\nbody\n",
+        )
+        .unwrap();
+        let post = file_io.load_front_matter();
+        fs::write(&path, "# Hello\n---\ntitle: Hello\n---\n").unwrap();
+        let missing = file_io.load_front_matter();
+        fs::remove_file(&path).unwrap();
+        let post = post.unwrap();
+        assert_eq!(post.title, "Hello");
+        assert_eq!(post.tags, ["a", "b"]);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn file_io_value_round_trip() {
+        let path = PathBuf::from("test_file_io_value_round_trip.toml");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let value = serde_json::json!({
+            "name": "x",
+            "server": { "port": 8080, "hosts": ["a", "b"] },
+        });
+        file_io.save_value(&value).unwrap();
+        let text = fs::read_to_string(&path);
+        let loaded = file_io.load_value();
+        fs::remove_file(&path).unwrap();
+        assert!(text.unwrap().contains("[server]"));
+        assert_eq!(loaded.unwrap(), value);
+    }
+
+    #[test]
+    fn file_io_line_ending() {
+        #[derive(Serialize, Deserialize)]
+        struct Lines(Vec<String>);
+        impl SerdeStr for Lines {
+            fn de_from_str(string: &str) -> Result<Self, io::Error> {
+                Ok(Lines(string.lines().map(Into::into).collect()))
+            }
+            fn ser_to_string(&self) -> Result<String, io::Error> {
+                Ok(self.0.iter().map(|line| format!("{}\n", line)).collect())
+            }
+        }
+        let path = PathBuf::from("test_file_io_line_ending.txt");
+        let file_io = FileIO::<Lines>::new(path.clone());
+        let conf = Lines(vec!["a".into(), "b".into()]);
+        file_io
+            .clone()
+            .line_ending(LineEnding::CrLf)
+            .save(&conf)
+            .unwrap();
+        let crlf = fs::read_to_string(&path);
+        file_io.save(&conf).unwrap();
+        let lf = fs::read_to_string(&path);
+        fs::write(&path, "x\r\n").unwrap();
+        let preserving = file_io.clone().line_ending(LineEnding::Preserve);
+        preserving.save(&conf).unwrap();
+        let preserved = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(crlf.unwrap(), "a\r\nb\r\n");
+        assert_eq!(lf.unwrap(), "a\nb\n");
+        assert_eq!(preserved.unwrap(), "a\r\nb\r\n");
+    }
+
+    #[cfg(all(feature = "serde_snap", feature = "json", feature = "toml"))]
+    #[test]
+    fn file_io_value_fingerprint() {
+        let json = PathBuf::from("test_file_io_value_fingerprint.json");
+        let toml = PathBuf::from("test_file_io_value_fingerprint.toml");
+        fs::write(&json, "{\n  \"name\": \"same\",\n  \"unknown\": 1\n}").unwrap();
+        fs::write(&toml, "name = \"same\"\n").unwrap();
+        let from_json = FileIO::<Conf>::new(json.clone()).value_fingerprint();
+        let from_toml = FileIO::<Conf>::new(toml.clone()).value_fingerprint();
+        fs::write(&toml, "name = \"other\"\n").unwrap();
+        let changed = FileIO::<Conf>::new(toml.clone()).value_fingerprint();
+        fs::remove_file(&json).unwrap();
+        fs::remove_file(&toml).unwrap();
+        let from_json = from_json.unwrap();
+        assert_eq!(from_json, from_toml.unwrap());
+        assert_ne!(from_json, changed.unwrap());
+    }
+
+    #[cfg(all(feature = "serde_snap", feature = "json"))]
+    #[test]
+    fn file_io_value_fingerprint_paths() {
+        let path = PathBuf::from("test_file_io_value_fingerprint_paths.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let paths = ["server.port", "server.host", "missing"];
+        let fingerprint = |content: &str| {
+            fs::write(&path, content).unwrap();
+            file_io.value_fingerprint_paths(&paths).unwrap()
+        };
+        let base = fingerprint(r#"{"server":{"port":80,"host":"a"},"meta":{"edited":1}}"#);
+        let ignored = fingerprint(r#"{"meta":{"edited":2},"server":{"host":"a","port":80}}"#);
+        let changed = fingerprint(r#"{"server":{"port":81,"host":"a"},"meta":{"edited":1}}"#);
+        let null = fingerprint(r#"{"server":{"port":80,"host":"a"},"missing":null}"#);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(base, ignored);
+        assert_ne!(base, changed);
+        assert_ne!(base, null);
+    }
+
+    #[cfg(feature = "sha_snap")]
+    #[test]
+    fn file_io_verify_bundle() {
+        let path = PathBuf::from("test_file_io_verify_bundle.json");
+        let members = [
+            PathBuf::from("test_file_io_verify_bundle.include.json"),
+            PathBuf::from("test_file_io_verify_bundle.secrets"),
+        ];
+        let manifest = PathBuf::from("test_file_io_verify_bundle.manifest");
+        fs::write(&path, r#"{"name":"main"}"#).unwrap();
+        fs::write(&members[0], "{}").unwrap();
+        fs::write(&members[1], "token").unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let stored = file_io.save_bundle_manifest(&members, &manifest).unwrap();
+        let intact = file_io.check_bundle_manifest(&members, &manifest);
+        let mut digests = Vec::new();
+        for tampered in [&path, &members[0], &members[1]] {
+            let original = fs::read(tampered).unwrap();
+            fs::write(tampered, b"tampered").unwrap();
+            digests.push(file_io.verify_bundle(&members).unwrap());
+            fs::write(tampered, original).unwrap();
+        }
+        fs::write(&members[1], "other token").unwrap();
+        let tampered = file_io.check_bundle_manifest(&members, &manifest);
+        let reordered = file_io.verify_bundle(&[members[1].clone(), members[0].clone()]);
+        for file in [&path, &members[0], &members[1], &manifest] {
+            fs::remove_file(file).unwrap();
+        }
+        assert!(intact.unwrap());
+        assert!(digests.iter().all(|digest| *digest != stored));
+        assert!(!tampered.unwrap());
+        assert_ne!(reordered.unwrap(), stored);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_check_syntax() {
+        let path = PathBuf::from("test_file_io_check_syntax.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        fs::write(&path, r#"{"name": 42}"#).unwrap();
+        let mistyped = file_io.check_syntax();
+        let loaded = file_io.load();
+        fs::write(&path, r#"{"name": "#).unwrap();
+        let malformed = file_io.check_syntax();
+        fs::remove_file(&path).unwrap();
+        mistyped.unwrap();
+        assert!(loaded.is_err());
+        assert!(malformed.is_err());
+    }
+
+    #[cfg(all(feature = "json", feature = "toml"))]
+    #[test]
+    fn file_io_load_try_formats() {
+        let path = PathBuf::from("test_file_io_load_try_formats.conf");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        fs::write(&path, "name = \"from toml\"\n").unwrap();
+        let toml = file_io.load_try_formats(&[Format::Json, Format::Toml]);
+        fs::write(&path, "name: neither").unwrap();
+        let neither = file_io.load_try_formats(&[Format::Json, Format::Toml]);
+        fs::remove_file(&path).unwrap();
+        let (conf, format) = toml.unwrap();
+        assert_eq!(conf.name, "from toml");
+        assert_eq!(format, Format::Toml);
+        let err = neither.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("as Json: ") && err.to_string().contains("as Toml: "));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn file_io_load_all_profiles() {
+        let path = PathBuf::from("test_file_io_load_all_profiles.toml");
+        fs::write(
+            &path,
+            "[profiles.dev]\nname = \"dev\"\n\n[profiles.prod]\nname = \"prod\"\n",
+        )
+        .unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let all = file_io.load_all_profiles();
+        fs::write(&path, "[profiles.dev]\nname = 1\n").unwrap();
+        let malformed = file_io.load_all_profiles();
+        fs::remove_file(&path).unwrap();
+        let all = all.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all["dev"].name, "dev");
+        assert_eq!(all["prod"].name, "prod");
+        let err = malformed.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("profile `dev` in"));
+    }
+
+    #[test]
+    fn file_io_load_binary_file() {
+        let path = PathBuf::from("test_file_io_load_binary_file.json");
+        fs::write(&path, b"{\"name\":\"\xff\xfe\"}").unwrap();
+        let loaded = FileIO::<Conf>::new(path.clone()).load();
+        fs::remove_file(&path).unwrap();
+        let err = loaded.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err
+            .to_string()
+            .ends_with("is not valid UTF-8 text (at byte 9); is this a binary file?"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_push_to_array() {
+        let path = PathBuf::from("test_file_io_push_to_array.json");
+        // the pushed values needn't be `T`
+        let file_io = FileIO::<Conf>::new(path.clone());
+        for name in ["a", "b", "c"] {
+            file_io.push_to_array(&Conf { name: name.into() }).unwrap();
+        }
+        let pushed = fs::read_to_string(&path).map(|s| serde_json::from_str::<Vec<Conf>>(&s));
+        fs::write(&path, r#"{"name":"a"}"#).unwrap();
+        let not_array = file_io.push_to_array(&Conf { name: "b".into() });
+        fs::remove_file(&path).unwrap();
+        let names: Vec<_> = pushed
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(not_array.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_push_to_array_tail() {
+        let path = PathBuf::from("test_file_io_push_to_array_tail.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        fs::write(&path, "[ ]\n").unwrap();
+        file_io
+            .push_to_array_tail(&Conf { name: "a".into() })
+            .unwrap();
+        file_io
+            .push_to_array_tail(&Conf { name: "b".into() })
+            .unwrap();
+        let tail_pushed = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(tail_pushed.unwrap(), r#"[ {"name":"a"},{"name":"b"}]"#);
+    }
+
+    #[test]
+    fn file_io_error_predicates() {
+        let path = PathBuf::from("test_file_io_error_predicates.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let missing = file_io.load().unwrap_err();
+        fs::write(&path, "{\"name\": 1}").unwrap();
+        let malformed = file_io.load().unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(missing.is_not_found() && !missing.is_parse_error());
+        assert!(malformed.is_parse_error() && !malformed.is_not_found());
+        let cause = malformed
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<serde_json::Error>());
+        assert!(cause.unwrap().is_data());
+    }
+
+    #[test]
+    fn file_io_save_from_template() {
+        let path = PathBuf::from("test_file_io_save_from_template.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let template = r#"{"name": "${APP}-${ENV}"}"#;
+        let vars = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let unfilled = file_io.save_from_template(template, &vars(&[("APP", "demo")]));
+        let unfilled_exists = path.exists();
+        let invalid = file_io.save_from_template(r#"{"name": ${APP}}"#, &vars(&[("APP", "1")]));
+        file_io
+            .save_from_template(template, &vars(&[("APP", "demo"), ("ENV", "prod")]))
+            .unwrap();
+        let loaded = file_io.load();
+        fs::remove_file(&path).unwrap();
+        let unfilled = unfilled.unwrap_err();
+        assert_eq!(unfilled.kind(), io::ErrorKind::InvalidInput);
+        assert!(unfilled.to_string().ends_with(": ENV"));
+        assert!(!unfilled_exists);
+        assert_eq!(invalid.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(loaded.unwrap().name, "demo-prod");
+    }
+
+    #[cfg(all(feature = "sha_snap", feature = "test-util"))]
+    #[test]
+    fn file_io_remember_hash() {
+        crate::project_info::hermetic_root();
+        struct HashApp;
+        impl crate::AppAuthor for HashApp {
+            fn app_name() -> &'static str {
+                "sculptor-test-remember-hash"
+            }
+            fn author() -> &'static str {
+                "sculptor"
+            }
+        }
+        use crate::ProjectInfo;
+        let path = PathBuf::from("test_file_io_remember_hash.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io.save(&Conf { name: "a".into() }).unwrap();
+        let first = file_io.remember_hash::<HashApp>();
+        let unchanged = file_io.remember_hash::<HashApp>();
+        file_io.save(&Conf { name: "b".into() }).unwrap();
+        let changed = file_io.remember_hash::<HashApp>();
+        fs::remove_file(&path).unwrap();
+        let dir = HashApp::state_dir().unwrap_or_else(HashApp::data_dir);
+        fs::remove_dir_all(dir).unwrap();
+        assert!(first.unwrap());
+        assert!(!unchanged.unwrap());
+        assert!(changed.unwrap());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn file_io_snapshot_restore() {
+        crate::project_info::hermetic_root();
+        struct SnapshotApp;
+        impl crate::AppAuthor for SnapshotApp {
+            fn app_name() -> &'static str {
+                "sculptor-test-snapshot"
+            }
+            fn author() -> &'static str {
+                "sculptor"
+            }
+        }
+        use crate::ProjectInfo;
+        let path = PathBuf::from("test_file_io_snapshot_restore.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io
+            .save(&Conf {
+                name: "good".into(),
+            })
+            .unwrap();
+        file_io.snapshot::<SnapshotApp>("before-upgrade").unwrap();
+        file_io
+            .save(&Conf {
+                name: "broken".into(),
+            })
+            .unwrap();
+        let other = FileIO::<Conf>::new(PathBuf::from("test_file_io_snapshot_other.json"));
+        other
+            .save(&Conf {
+                name: "other".into(),
+            })
+            .unwrap();
+        other.snapshot::<SnapshotApp>("before-upgrade").unwrap();
+        file_io
+            .restore_snapshot::<SnapshotApp>("before-upgrade")
+            .unwrap();
+        let restored = file_io.load();
+        let names = file_io.list_snapshots::<SnapshotApp>();
+        fs::remove_file(&other.path).unwrap();
+        let missing = file_io.restore_snapshot::<SnapshotApp>("never-taken");
+        let escaping = file_io.snapshot::<SnapshotApp>("../outside");
+        fs::remove_file(&path).unwrap();
+        let dir = SnapshotApp::state_dir().unwrap_or_else(SnapshotApp::data_dir);
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(restored.unwrap().name, "good");
+        assert_eq!(names.unwrap(), ["before-upgrade"]);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(escaping.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_json_pointer() {
+        let path = PathBuf::from("test_file_io_json_pointer.json");
+        fs::write(
+            &path,
+            r#"{"hosts":{"example.com":{"port":80}},"tags":["a"]}"#,
+        )
+        .unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let port = file_io.get_pointer::<u16>("/hosts/example.com/port");
+        file_io.set_pointer("/hosts/example.com/port", 443).unwrap();
+        file_io.set_pointer("/paths/a~1b", "slash").unwrap();
+        file_io.set_pointer("/tags/0", "b").unwrap();
+        let past_end = file_io.set_pointer("/tags/1", "c");
+        let set = file_io.get_pointer::<u16>("/hosts/example.com/port");
+        let escaped = file_io.get_pointer::<String>("/paths/a~1b");
+        let tag = file_io.get_pointer::<String>("/tags/0");
+        let dotted = file_io.get_path::<u16>("hosts.example.com.port");
+        let relative = file_io.get_pointer::<u16>("hosts");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(port.unwrap(), 80);
+        assert_eq!(set.unwrap(), 443);
+        assert_eq!(escaped.unwrap(), "slash");
+        assert_eq!(tag.unwrap(), "b");
+        assert_eq!(past_end.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        // the dot makes the key unreachable as a dotted path
+        assert_eq!(dotted.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(relative.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_get_path() {
+        let path = PathBuf::from("test_file_io_get_path.json");
+        fs::write(&path, r#"{"server":{"port":8080,"tags":["a","b"]}}"#).unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let port = file_io.get_path::<u16>("server.port");
+        let tag = file_io.get_path::<String>("server.tags.1");
+        let missing = file_io.get_path::<u16>("server.host");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(port.unwrap(), 8080);
+        assert_eq!(tag.unwrap(), "b");
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_set_path() {
+        let path = PathBuf::from("test_file_io_set_path.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io.set_path("server.port", 8080).unwrap();
+        let created = file_io.get_path::<u16>("server.port");
+        file_io.set_path("server.port", 9090).unwrap();
+        let overwritten = file_io.get_path::<u16>("server.port");
+        let into_table = file_io.set_path("server", "scalar");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(created.unwrap(), 8080);
+        assert_eq!(overwritten.unwrap(), 9090);
+        assert_eq!(into_table.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_save_options_cover_whole_file_writes() {
+        let path = PathBuf::from("test_file_io_save_options_writes.json");
+        let link = PathBuf::from("test_file_io_save_options_writes.link");
+        let file_io = FileIO::<Conf>::new(path.clone()).atomic_saves();
+        fs::write(&path, r#"{"name":"old"}"#).unwrap();
+        // an atomic write replaces the file, so a hard link keeps the old content
+        let mut replaced = Vec::new();
+        for write in [
+            |io: &FileIO<Conf>| io.save_value(&serde_json::json!({ "name": "value" })),
+            |io: &FileIO<Conf>| io.set_path("name", "path"),
+            |io: &FileIO<Conf>| io.set_pointer("/name", "pointer"),
+            |io: &FileIO<Conf>| {
+                io.save_if_changed(&Conf {
+                    name: "changed".into(),
+                })
+                .map(drop)
+            },
+            |io: &FileIO<Conf>| {
+                io.save_stable(&Conf {
+                    name: "stable".into(),
+                })
+            },
+        ] {
+            fs::hard_link(&path, &link).unwrap();
+            let written = write(&file_io);
+            replaced.push(fs::read(&link).unwrap() != fs::read(&path).unwrap());
+            fs::remove_file(&link).unwrap();
+            written.unwrap();
+        }
+        let staged = temps_left(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replaced, [true; 5]);
+        assert!(!staged);
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn file_io_windows_1252_round_trip() {
+        use encoding_rs::WINDOWS_1252;
+        let path = PathBuf::from("test_file_io_windows_1252_round_trip.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let conf = Conf {
+            name: "café – naïve".to_string(),
+        };
+        file_io.save_with_encoding(&conf, WINDOWS_1252).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let as_utf8 = file_io.load();
+        let loaded = file_io.load_with_encoding(WINDOWS_1252);
+        let unmappable = file_io.save_with_encoding(
+            &Conf {
+                name: "雪".to_string(),
+            },
+            WINDOWS_1252,
+        );
+        fs::remove_file(&path).unwrap();
+        // é and the en dash are single bytes in Windows-1252
+        assert!(bytes.contains(&0xe9) && bytes.contains(&0x96));
+        assert_eq!(as_utf8.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(loaded.unwrap().name, conf.name);
+        assert_eq!(unmappable.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn file_io_load_sniffs_gzip() {
+        let plain = PathBuf::from("test_file_io_load_sniffs_gzip_plain.json");
+        let packed = PathBuf::from("test_file_io_load_sniffs_gzip_packed.json");
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        FileIO::<Conf>::new(plain.clone()).save(&conf).unwrap();
+        FileIO::<Conf>::new(packed.clone())
+            .save_gzip(&conf)
+            .unwrap();
+        let magic = fs::read(&packed).unwrap()[..2].to_vec();
+        let loaded_plain = FileIO::<Conf>::new(plain.clone()).load();
+        let loaded_packed = FileIO::<Conf>::new(packed.clone()).load();
+        let raw_packed = FileIO::<Conf>::new(packed.clone()).load_raw();
+        fs::remove_file(&plain).unwrap();
+        fs::remove_file(&packed).unwrap();
+        assert_eq!(magic, [0x1f, 0x8b]);
+        assert_eq!(raw_packed.unwrap().1, r#"{"name":"test"}"#);
+        assert_eq!(loaded_plain.unwrap(), conf);
+        assert_eq!(loaded_packed.unwrap(), conf);
+    }
+
+    #[test]
+    fn file_io_from_paths() {
+        let name = "test_file_io_from_paths.json";
+        fs::write(name, r#"{"name":"from"}"#).unwrap();
+        let from_str: FileIO<Conf> = name.into();
+        let from_string: FileIO<Conf> = name.to_string().into();
+        let from_path: FileIO<Conf> = Path::new(name).into();
+        let from_path_buf: FileIO<Conf> = PathBuf::from(name).into();
+        let loaded = [from_str, from_string, from_path, from_path_buf].map(|io| io.load());
+        fs::remove_file(name).unwrap();
+        for conf in loaded {
+            assert_eq!(conf.unwrap().name, "from");
+        }
+    }
+
+    #[test]
+    fn source_io_in_memory() {
+        struct Memory(std::sync::Mutex<String>);
+        impl Source for Memory {
+            fn read(&self) -> io::Result<String> {
+                Ok(self.0.lock().unwrap().clone())
+            }
+            fn write(&self, string: &str) -> io::Result<()> {
+                *self.0.lock().unwrap() = string.to_owned();
+                Ok(())
+            }
+        }
+        let io = SourceIO::<Conf, _>::new(Memory(Default::default()));
+        let conf = Conf {
+            name: "memory".to_string(),
+        };
+        io.save(&conf).unwrap();
+        assert_eq!(*io.source().0.lock().unwrap(), r#"{"name":"memory"}"#);
+        assert_eq!(io.load().unwrap(), conf);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_load_migrated() {
+        fn suffix(mut value: Value, tag: &str) -> Value {
+            let name = value["name"].as_str().unwrap_or_default().to_owned();
+            value["name"] = format!("{}-{}", name, tag).into();
+            value
+        }
+        let migrations: [Migration; 3] = [
+            (3, |value| suffix(value, "3")),
+            (1, |value| suffix(value, "1")),
+            (2, |value| suffix(value, "2")),
+        ];
+        let path = PathBuf::from("test_file_io_load_migrated.json");
+        fs::write(&path, r#"{"schema":1,"name":"x"}"#).unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let migrated = file_io.load_migrated("schema", &migrations);
+        let stored = file_io.get_path::<u32>("schema");
+        let again = file_io.load_migrated("schema", &migrations);
+        let too_new = file_io.load_migrated("schema", &migrations[1..]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(migrated.unwrap().name, "x-2-3");
+        assert_eq!(stored.unwrap(), 3);
+        assert_eq!(again.unwrap().name, "x-2-3");
+        assert_eq!(too_new.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn file_io_load_into() {
+        let path = PathBuf::from("test_file_io_load_into.json");
+        fs::write(&path, r#"{"name":"reloaded"}"#).unwrap();
+        let mut conf = Conf {
+            name: "stale".to_string(),
+        };
+        let res = FileIO::<Conf>::new(path.clone()).load_into(&mut conf);
+        fs::remove_file(&path).unwrap();
+        res.unwrap();
+        assert_eq!(conf.name, "reloaded");
+    }
+
+    #[test]
+    fn file_io_load_raw() {
+        let path = PathBuf::from("test_file_io_load_raw.json");
+        let raw = "{ \"name\": \"spaced\" }";
+        fs::write(&path, raw).unwrap();
+        let loaded = FileIO::<Conf>::new(path.clone()).load_raw();
+        fs::remove_file(&path).unwrap();
+        let (conf, string) = loaded.unwrap();
+        assert_eq!(string, raw);
+        assert_eq!(conf, Conf::de_from_str(&string).unwrap());
+    }
+
+    #[test]
+    fn file_io_try_load() {
+        let path = PathBuf::from("test_file_io_try_load.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let absent = file_io.try_load();
+        fs::write(&path, "{ corrupt").unwrap();
+        let corrupt = file_io.try_load();
+        fs::remove_file(&path).unwrap();
+        // permission bits don't stop root, so a directory stands in for an unreadable file
+        fs::create_dir(&path).unwrap();
+        let unreadable = file_io.try_load();
+        fs::remove_dir(&path).unwrap();
+        assert!(absent.unwrap().is_none());
+        assert!(corrupt.is_err());
+        assert!(unreadable.is_err());
+    }
+
+    #[test]
+    fn file_io_load_or_embedded() {
+        let path = PathBuf::from("test_file_io_load_or_embedded/absent.json");
+        let loaded = FileIO::<Conf>::new(path.clone()).load_or_embedded(r#"{"name":"embedded"}"#);
+        assert_eq!(loaded.unwrap().name, "embedded");
+        assert!(!path.exists());
+        assert!(!path.parent().unwrap().exists());
+    }
+
+    #[test]
+    fn file_io_on_event() {
+        use std::sync::Mutex;
+        let path = PathBuf::from("test_file_io_on_event.json");
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let file_io = FileIO::<Conf>::new(path.clone())
+            .on_event(Arc::new(move |event| sink.lock().unwrap().push(event)));
+        file_io.save(&conf).unwrap();
+        let size = fs::metadata(&path).unwrap().len() as usize;
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![FileIoEvent::Saved { path, bytes: size }]
+        );
+    }
+
+    #[test]
+    fn source_io_over_file_io() {
+        use std::sync::Mutex;
+        let path = PathBuf::from("test_source_io_over_file_io.json");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let file_io = FileIO::<Conf>::new(path.clone())
+            .line_ending(LineEnding::CrLf)
+            .on_event(Arc::new(move |event| sink.lock().unwrap().push(event)));
+        let io = SourceIO::<Conf, _>::new(file_io);
+        io.source().write("a\nb\n").unwrap();
+        let written = fs::read_to_string(&path);
+        let read = io.source().read();
+        fs::remove_file(&path).unwrap();
+        let directory = SourceIO::<Conf, _>::new(FileIO::<Conf>::new(PathBuf::from("src")));
+        assert_eq!(written.unwrap(), "a\r\nb\r\n");
+        assert_eq!(read.unwrap(), "a\r\nb\r\n");
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                FileIoEvent::Saved {
+                    path: path.clone(),
+                    bytes: 6
+                },
+                FileIoEvent::Loaded { path, bytes: 6 },
+            ]
+        );
+        let write_dir = directory.source().write("{}").unwrap_err();
+        assert_eq!(write_dir.kind(), io::ErrorKind::InvalidInput);
+        let read_dir = directory.source().read().unwrap_err();
+        assert_eq!(read_dir.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn file_io_root() {
+        let root = FileIORoot::new(PathBuf::from("test_file_io_root"));
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let app = root.file::<Conf>("app.json");
+        let other = root.file::<Conf>("nested/other.json");
+        app.save(&conf).unwrap();
+        other.save(&conf).unwrap();
+        let loaded = other.load();
+        fs::remove_dir_all(&root.base).unwrap();
+        assert!(app.path.starts_with(&root.base));
+        assert!(other.path.starts_with(&root.base));
+        assert_eq!(loaded.unwrap(), conf);
+    }
+
+    #[test]
+    fn file_io_backup_path() {
+        let path = PathBuf::from("test_file_io_backup_path.json");
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let file_io = FileIO::<Conf>::new(path.clone());
+        assert_eq!(file_io.backup_path(), None);
+        file_io.save(&conf).unwrap();
+        let predicted = file_io.backup_path().unwrap();
+        file_io.backup_and_save(&conf).unwrap();
+        let backups: Vec<PathBuf> = fs::read_dir(".")
+            .unwrap()
+            .map(|entry| PathBuf::from(entry.unwrap().file_name()))
+            .filter(|p| {
+                p.to_string_lossy()
+                    .starts_with("test_file_io_backup_path.json.")
+            })
+            .collect();
+        for backup in &backups {
+            fs::remove_file(backup).unwrap();
+        }
+        fs::remove_file(&path).unwrap();
+        let strip_ts = |p: &Path| {
+            let name = p.to_string_lossy().into_owned();
+            name.split('.')
+                .filter(|part| !part.chars().all(|c| c.is_ascii_digit()))
+                .collect::<Vec<_>>()
+                .join(".")
+        };
+        assert_eq!(backups.len(), 1);
+        assert_eq!(strip_ts(&backups[0]), strip_ts(&predicted));
+    }
+
+    #[test]
+    fn file_io_backup_and_save_at() {
+        let path = PathBuf::from("test_file_io_backup_and_save_at.json");
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io.save(&conf).unwrap();
+        let now = time::OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        file_io.backup_and_save_at(&conf, now).unwrap();
+        let backup = PathBuf::from("test_file_io_backup_and_save_at.json.1700000000.bak");
+        let backed_up = backup.exists();
+        let _ = fs::remove_file(&backup);
+        fs::remove_file(&path).unwrap();
+        assert!(backed_up);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn file_io_temp_is_unique_and_cleaned() {
+        let (first, first_guard) = FileIO::<Conf>::temp();
+        let (second, second_guard) = FileIO::<Conf>::temp_with_extension("json");
+        first.save(&Conf { name: "a".into() }).unwrap();
+        first.backup_and_save(&Conf { name: "b".into() }).unwrap();
+        second
+            .save_value(&serde_json::json!({ "name": "c" }))
+            .unwrap();
+        let dir = first.path.parent().unwrap().to_path_buf();
+        assert_ne!(first.path, second.path);
+        assert_eq!(second.path.extension().unwrap(), "json");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        drop(first_guard);
+        assert!(!dir.exists());
+        assert!(second.path.exists());
+        drop(second_guard);
+        assert!(!second.path.exists());
+    }
+
+    #[test]
+    fn file_io_reset_to_default() {
+        let path = PathBuf::from("test_file_io_reset_to_default.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io
+            .save(&Conf {
+                name: "mine".into(),
+            })
+            .unwrap();
+        file_io.reset_to_default().unwrap();
+        let reset = file_io.load();
+        let backups: Vec<_> = file_io.backups().collect::<io::Result<_>>().unwrap();
+        let backed_up = FileIO::<Conf>::new(backups[0].path.clone()).load();
+        for backup in &backups {
+            fs::remove_file(&backup.path).unwrap();
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(reset.unwrap(), Conf::default());
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backed_up.unwrap().name, "mine");
+    }
+
+    #[test]
+    fn file_io_backup_only_changes() {
+        let path = PathBuf::from("test_file_io_backup_only_changes.json");
+        let file_io = FileIO::<Conf>::new(path.clone()).backup_only_changes();
+        file_io.save(&Conf { name: "a".into() }).unwrap();
+        for ts in [1_700_000_000, 1_700_000_001] {
+            let now = time::OffsetDateTime::from_unix_timestamp(ts).unwrap();
+            file_io
+                .backup_and_save_at(&Conf { name: "b".into() }, now)
+                .unwrap();
+        }
+        let backups: Vec<_> = file_io.backups().collect();
+        for backup in &backups {
+            let _ = fs::remove_file(&backup.as_ref().unwrap().path);
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+    }
+
+    #[test]
+    fn backup_name_round_trips() {
+        let name = format_backup_name("app.config.json", 1700000000);
+        assert_eq!(name, "app.config.json.1700000000.bak");
+        let parsed = parse_backup_name(name.as_ref());
+        assert_eq!(parsed, Some(("app.config.json".to_owned(), 1700000000)));
+        let compressed = parse_backup_name("a.toml.-5.bak.gz".as_ref());
+        assert_eq!(compressed, Some(("a.toml".to_owned(), -5)));
+        assert_eq!(parse_backup_name("a.json.notes.bak".as_ref()), None);
+    }
+
+    #[test]
+    fn prune_backups_per_file() {
+        let dir = PathBuf::from("test_file_io_prune_backups");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        for (name, ts) in [("a.json", 1), ("a.json", 2), ("a.json", 3), ("b.toml", 1)] {
+            fs::write(dir.join(format!("{}.{}.bak", name, ts)), "12345").unwrap();
+        }
+        fs::write(dir.join("a.json"), "live").unwrap();
+        let freed = prune_backups(&dir, 1);
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(freed.unwrap(), 10);
+        assert_eq!(left, ["a.json", "a.json.3.bak", "b.toml.1.bak"]);
+    }
+
+    #[test]
+    fn file_io_move_to_keeps_backups() {
+        let mut file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_move_to_old.json"));
+        file_io.save(&Conf { name: "a".into() }).unwrap();
+        let now = time::OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        file_io
+            .backup_and_save_at(&Conf { name: "b".into() }, now)
+            .unwrap();
+        let new_path = PathBuf::from("test_file_io_move_to_new.json");
+        file_io.move_to(&new_path).unwrap();
+        let backup = PathBuf::from("test_file_io_move_to_new.json.1700000000.bak");
+        let (moved, backed_up) = (fs::read_to_string(&new_path), fs::read_to_string(&backup));
+        let old_left = Path::new("test_file_io_move_to_old.json").exists();
+        let _ = fs::remove_file(&backup);
+        fs::remove_file(&new_path).unwrap();
+        assert_eq!(file_io.path, new_path);
+        assert_eq!(moved.unwrap(), r#"{"name":"b"}"#);
+        assert_eq!(backed_up.unwrap(), r#"{"name":"a"}"#);
+        assert!(!old_left);
+    }
+
+    #[test]
+    fn file_io_move_to_rolls_back() {
+        let mut file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_move_to_back.json"));
+        file_io.save(&Conf { name: "a".into() }).unwrap();
+        let now = time::OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        file_io
+            .backup_and_save_at(&Conf { name: "b".into() }, now)
+            .unwrap();
+        // the file itself is gone, so its rename fails after the backup's
+        fs::remove_file(&file_io.path).unwrap();
+        let moved = file_io.move_to(Path::new("test_file_io_move_to_forth.json"));
+        let backup = PathBuf::from("test_file_io_move_to_back.json.1700000000.bak");
+        let kept = backup.exists();
+        let _ = fs::remove_file(&backup);
+        let _ = fs::remove_file("test_file_io_move_to_forth.json.1700000000.bak");
+        assert_eq!(moved.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(kept);
+        assert_eq!(file_io.path, Path::new("test_file_io_move_to_back.json"));
+    }
+
+    #[test]
+    fn file_io_backups() {
+        let path = PathBuf::from("test_file_io_backups.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        for (ts, name) in [
+            (1_700_000_100, "b"),
+            (1_700_000_000, "a"),
+            (1_700_000_200, "ccc"),
+        ] {
+            file_io.save(&Conf { name: name.into() }).unwrap();
+            let now = time::OffsetDateTime::from_unix_timestamp(ts).unwrap();
+            file_io
+                .backup_and_save_at(&Conf { name: name.into() }, now)
+                .unwrap();
+        }
+        fs::write("test_file_io_backups.json.notes.bak", "").unwrap();
+        let backups: Vec<BackupInfo> = file_io.backups().collect::<io::Result<_>>().unwrap();
+        for backup in &backups {
+            fs::remove_file(&backup.path).unwrap();
+        }
+        fs::remove_file("test_file_io_backups.json.notes.bak").unwrap();
+        fs::remove_file(&path).unwrap();
+        let stamps: Vec<i64> = backups
+            .iter()
+            .map(|b| b.timestamp.unix_timestamp())
+            .collect();
+        assert_eq!(stamps, [1_700_000_200, 1_700_000_100, 1_700_000_000]);
+        let sizes: Vec<u64> = backups.iter().map(|b| b.size).collect();
+        assert_eq!(sizes, [r#"{"name":"ccc"}"#.len() as u64, 12, 12]);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn file_io_save_emits_span() {
+        use std::sync::Mutex;
+        use tracing_subscriber::fmt::{format::FmtSpan, MakeWriter};
+
+        /// Collects the formatted output, which lists each span's fields once it closes
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+        impl io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        impl<'a> MakeWriter<'a> for Output {
+            type Writer = Output;
+            fn make_writer(&'a self) -> Output {
+                self.clone()
+            }
+        }
+
+        let output = Output::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(output.clone())
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(false)
+            .finish();
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_save_emits_span.json"));
+        let conf = Conf {
+            name: "traced".to_owned(),
+        };
+        tracing::subscriber::with_default(subscriber, || {
+            // the byte counts land on our own spans, not on whichever span is current
+            let outer = tracing::info_span!("outer", bytes = tracing::field::Empty);
+            let _entered = outer.enter();
+            file_io.save(&conf)?;
+            file_io.save_stable(&conf)?;
+            file_io.load_value().map(|_| ())
+        })
+        .unwrap();
+        fs::remove_file(&file_io.path).unwrap();
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let spans: Vec<_> = output.lines().collect();
+        assert_eq!(spans.len(), 4, "{}", output);
+        let path = "path=test_file_io_save_emits_span.json";
+        for (span, op) in spans.iter().zip(["save", "save", "load"]) {
+            let fields = format!("file_io{{op=\"{}\" {} bytes=17 duration_us=", op, path);
+            assert!(span.contains(&fields), "{}", span);
+        }
+        assert!(spans[3].contains(" outer: ") && !spans[3].contains("bytes"));
+    }
+
+    #[test]
+    fn file_io_load_stream() {
+        let path = PathBuf::from("test_file_io_load_stream.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let names = |file_io: &FileIO<Conf>| -> io::Result<Vec<String>> {
+            file_io
+                .load_stream::<Conf>()?
+                .map(|conf| Ok(conf?.name))
+                .collect()
+        };
+        fs::write(
+            &path,
+            "{\"name\":\"a\"} {\"name\":\"b\"}\n{\"name\":\"c\"}\n",
+        )
+        .unwrap();
+        let values = names(&file_io);
+        fs::write(&path, " [{\"name\":\"[,]\"}, {\"name\":\"\\\"\"}]\n").unwrap();
+        let elements = names(&file_io);
+        fs::write(&path, "[]").unwrap();
+        let empty = names(&file_io);
+        fs::write(&path, "[{\"name\":\"a\"}").unwrap();
+        let unterminated = names(&file_io);
+        fs::write(&path, "[{\"name\":\"a\"}}, {\"name\":\"b\"}]").unwrap();
+        let unbalanced = names(&file_io);
+        // elements longer than the read buffer span several fills
+        let long = "x".repeat(20_000);
+        fs::write(
+            &path,
+            format!("[{{\"name\":\"{long}\"}},{{\"name\":\"b\"}}]"),
+        )
+        .unwrap();
+        let spanning = names(&file_io);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(values.unwrap(), ["a", "b", "c"]);
+        assert_eq!(elements.unwrap(), ["[,]", "\""]);
+        assert_eq!(empty.unwrap(), Vec::<String>::new());
+        assert_eq!(
+            unterminated.unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(unbalanced.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(spanning.unwrap(), [long.as_str(), "b"]);
+    }
+
+    #[test]
+    fn file_io_load_if_newer() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_load_if_newer.json"));
+        file_io.save(&Conf { name: "v1".into() }).unwrap();
+        let modified = fs::metadata(&file_io.path).unwrap().modified().unwrap();
+        let unchanged = file_io.load_if_newer(modified);
+        let earlier = modified - std::time::Duration::from_secs(1);
+        let newer = file_io.load_if_newer(earlier);
+        fs::remove_file(&file_io.path).unwrap();
+        assert!(unchanged.unwrap().is_none());
+        assert_eq!(newer.unwrap().unwrap().name, "v1");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn file_io_load_with_meta() {
+        let path = PathBuf::from("test_file_io_load_with_meta.json");
+        fs::write(&path, r#"{"name":"meta"}"#).unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let loaded = file_io.load_with_meta();
+        let metadata = fs::metadata(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let (conf, meta) = loaded.unwrap();
+        assert_eq!(conf.name, "meta");
+        assert_eq!(meta.format, Some(Format::Json));
+        assert_eq!(meta.size, r#"{"name":"meta"}"#.len() as u64);
+        assert_eq!(meta.modified, metadata.modified().unwrap());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn file_io_compressed_backups_restore() {
+        let path = PathBuf::from("test_file_io_compressed_backups.json");
+        let file_io = FileIO::<Conf>::new(path.clone()).compress_backups();
+        file_io.save(&Conf { name: "old".into() }).unwrap();
+        let now = time::OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        file_io
+            .backup_and_save_at(&Conf { name: "new".into() }, now)
+            .unwrap();
+        let backup = PathBuf::from("test_file_io_compressed_backups.json.1700000000.bak.gz");
+        let compressed = fs::read(&backup).unwrap();
+        let live = fs::read_to_string(&path).unwrap();
+        let staged = temps_left(&path);
+        file_io.restore_latest_backup().unwrap();
+        let restored = file_io.load();
+        fs::remove_file(&backup).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(!staged);
+        assert!(compressed.starts_with(&[0x1f, 0x8b]));
+        assert_eq!(live, r#"{"name":"new"}"#);
+        assert_eq!(restored.unwrap().name, "old");
+    }
+
+    #[test]
+    fn file_io_restore_non_utf8_backup() {
+        let path = PathBuf::from("test_file_io_restore_non_utf8_backup.txt");
+        // Windows-1252 for "café"
+        let legacy = b"caf\xe9".to_vec();
+        fs::write(&path, &legacy).unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let now = time::OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        file_io
+            .backup_and_save_at(&Conf { name: "new".into() }, now)
+            .unwrap();
+        let restored = file_io.restore_latest_backup();
+        let content = fs::read(&path).unwrap();
+        fs::remove_file("test_file_io_restore_non_utf8_backup.txt.1700000000.bak").unwrap();
+        fs::remove_file(&path).unwrap();
+        restored.unwrap();
+        assert_eq!(content, legacy);
+    }
+
+    #[cfg(feature = "sha_snap")]
+    #[test]
+    fn file_io_checksum() {
+        use crate::ShaSnap;
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_checksum.json"));
+        let missing = file_io.checksum();
+        fs::write(&file_io.path, "not even json").unwrap();
+        let checksum = file_io.checksum();
+        fs::remove_file(&file_io.path).unwrap();
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(checksum.unwrap(), b"not even json".snap());
+    }
+
+    #[cfg(feature = "sha_snap")]
+    #[test]
+    fn file_io_content_id() {
+        let here = FileIO::<Conf>::new(PathBuf::from("test_file_io_content_id_a.json"));
+        let there = FileIO::<Conf>::new(PathBuf::from("test_file_io_content_id_b.json"));
+        let missing = there.content_id();
+        fs::write(&here.path, "same").unwrap();
+        fs::write(&there.path, "same").unwrap();
+        let (id_here, id_there) = (here.content_id(), there.content_id());
+        let id_again = here.content_id();
+        fs::remove_file(&here.path).unwrap();
+        fs::remove_file(&there.path).unwrap();
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_ne!(id_here.as_ref().unwrap(), id_there.as_ref().unwrap());
+        assert_eq!(id_here.unwrap(), id_again.unwrap());
+    }
+
+    #[cfg(feature = "sha_snap")]
+    #[test]
+    fn file_io_save_verified_from_reader() {
+        use crate::ShaSnap;
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_save_verified.json"));
+        let body = br#"{"name":"downloaded"}"#;
+        let corrupt = file_io.save_verified_from_reader(&b"{}"[..], &body.snap());
+        let left = file_io.path.exists() || temps_left(&file_io.path);
+        let verified = file_io.save_verified_from_reader(&body[..], &body.snap().to_uppercase());
+        let loaded = file_io.load();
+        fs::remove_file(&file_io.path).unwrap();
+        assert_eq!(corrupt.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(!left);
+        verified.unwrap();
+        assert_eq!(loaded.unwrap().name, "downloaded");
+    }
+
+    #[cfg(feature = "sha_snap")]
+    #[test]
+    fn file_io_snapshot_detects_tampering() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_snapshot.json"));
+        let conf = Conf {
+            name: "save-game".to_owned(),
+        };
+        file_io.save_snapshot(&conf).unwrap();
+        let intact = file_io.load_snapshot();
+        let mut bytes = fs::read(&file_io.path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&file_io.path, bytes).unwrap();
+        let tampered = file_io.load_snapshot();
+        fs::remove_file(&file_io.path).unwrap();
+        assert_eq!(intact.unwrap().name, "save-game");
+        assert_eq!(tampered.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn file_io_save_if_changed_diff() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_save_if_changed_diff.json"));
+        let created = file_io.save_if_changed_diff(&Conf { name: "a".into() });
+        let same = file_io.save_if_changed_diff(&Conf { name: "a".into() });
+        let edited = file_io.save_if_changed_diff(&Conf { name: "b".into() });
+        let rewritten = file_io.save_if_changed(&Conf { name: "b".into() });
+        fs::remove_file(&file_io.path).unwrap();
+        assert_eq!(created.unwrap().unwrap()[0].path(), "");
+        assert!(same.unwrap().is_none());
+        assert_eq!(
+            edited.unwrap().unwrap(),
+            [crate::JsonChange::Changed {
+                path: "name".into(),
+                old: "a".into(),
+                new: "b".into(),
+            }]
+        );
+        assert!(!rewritten.unwrap());
+    }
+
+    #[test]
+    fn file_io_reload() {
+        let path = PathBuf::from("test_file_io_reload.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let current = Conf { name: "a".into() };
+        // formatted differently from how `Conf` saves, yet the same content
+        fs::write(&path, r#"{ "name": "a" }"#).unwrap();
+        let unchanged = file_io.reload(&current);
+        fs::write(&path, r#"{"name":"b"}"#).unwrap();
+        let changed = file_io.reload(&current);
+        fs::remove_file(&path).unwrap();
+        assert!(unchanged.unwrap().is_none());
+        assert_eq!(changed.unwrap().unwrap().name, "b");
+    }
+
+    #[test]
+    fn file_io_drift() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_drift.json"));
+        file_io.save(&Conf { name: "a".into() }).unwrap();
+        let in_sync = file_io.drift(&Conf { name: "a".into() });
+        let drifted = file_io.drift(&Conf { name: "b".into() });
+        fs::remove_file(&file_io.path).unwrap();
+        assert!(in_sync.unwrap().is_empty());
+        assert_eq!(
+            drifted.unwrap(),
+            [crate::JsonChange::Changed {
+                path: "name".into(),
+                old: "b".into(),
+                new: "a".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn file_io_edit_guard() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_edit_guard.json"));
+        file_io.save(&Conf { name: "v1".into() }).unwrap();
+        {
+            let mut conf = file_io.edit_guard().unwrap();
+            conf.name = "v2".into();
+        }
+        let dropped = file_io.load();
+        let mut conf = file_io.edit_guard().unwrap();
+        conf.name = "v3".into();
+        conf.cancel();
+        let cancelled = file_io.load();
+        let mut conf = file_io.edit_guard().unwrap();
+        conf.name = "v4".into();
+        let committed = conf.commit().and_then(|()| file_io.load());
+        fs::remove_file(&file_io.path).unwrap();
+        assert_eq!(dropped.unwrap().name, "v2");
+        assert_eq!(cancelled.unwrap().name, "v2");
+        assert_eq!(committed.unwrap().name, "v4");
+    }
+
+    #[test]
+    fn file_io_edit_guard_panicking() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_edit_guard_panicking.json"));
+        file_io.save(&Conf { name: "v1".into() }).unwrap();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut conf = file_io.edit_guard().unwrap();
+            conf.name = "v2".into();
+            panic!("edit failed halfway");
+        }));
+        let loaded = file_io.load();
+        fs::remove_file(&file_io.path).unwrap();
+        assert!(panicked.is_err());
+        assert_eq!(loaded.unwrap().name, "v1");
+    }
+
+    #[test]
+    fn file_io_save_reversible() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_save_reversible.json"));
+        file_io.save(&Conf { name: "v1".into() }).unwrap();
+        let guard = file_io.save_reversible(&Conf { name: "v2".into() });
+        let during = file_io.load();
+        guard.unwrap().rollback().unwrap();
+        let rolled_back = file_io.load();
+        drop(
+            file_io
+                .save_reversible(&Conf { name: "v3".into() })
+                .unwrap(),
+        );
+        let committed = file_io.load();
+        fs::remove_file(&file_io.path).unwrap();
+        assert_eq!(during.unwrap().name, "v2");
+        assert_eq!(rolled_back.unwrap().name, "v1");
+        assert_eq!(committed.unwrap().name, "v3");
+    }
+
+    #[test]
+    fn file_io_save_atomic_in_temp_dir() {
+        let dir = PathBuf::from("test_file_io_temp_dir");
+        let _ = fs::remove_dir_all(&dir);
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_temp_dir.json"))
+            .with_temp_dir(dir.clone());
+        let conf = Conf {
+            name: "staged".to_owned(),
+        };
+        // staging into the missing directory can only fail
+        let missing = file_io.save_atomic(&conf);
+        fs::create_dir(&dir).unwrap();
+        let saved = file_io.save_atomic(&conf);
+        let left = fs::read_dir(&dir).unwrap().count();
+        let loaded = file_io.load();
+        fs::remove_dir(&dir).unwrap();
+        fs::remove_file(&file_io.path).unwrap();
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        saved.unwrap();
+        assert_eq!(left, 0);
+        assert_eq!(loaded.unwrap().name, "staged");
+    }
+
+    #[test]
+    fn file_io_temp_dir_shared_by_same_names() {
+        let root = PathBuf::from("test_file_io_temp_dir_shared");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("staging")).unwrap();
+        let file_ios: Vec<_> = ["a", "b"]
+            .map(|dir| {
+                FileIO::<Conf>::new(root.join(dir).join("conf.json"))
+                    .with_temp_dir(root.join("staging"))
+            })
+            .into();
+        let saved: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (file_ios.iter())
+                .map(|file_io| {
+                    scope.spawn(move || {
+                        let name = file_io.path.parent().unwrap().display().to_string();
+                        (0..20).try_for_each(|_| file_io.save_atomic(&Conf { name: name.clone() }))
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let loaded: Vec<_> = file_ios.iter().map(FileIO::load).collect();
+        let left = fs::read_dir(root.join("staging")).unwrap().count();
+        fs::remove_dir_all(&root).unwrap();
+        assert!(saved.iter().all(Result::is_ok), "{:?}", saved);
+        for (file_io, loaded) in file_ios.iter().zip(loaded) {
+            let dir = file_io.path.parent().unwrap().display().to_string();
+            assert_eq!(loaded.unwrap().name, dir);
+        }
+        assert_eq!(left, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_io_save_atomic_keeps_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = PathBuf::from("test_file_io_save_atomic_keeps_mode.json");
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io.save(&conf).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        file_io.save_atomic(&conf).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn editor_command_splits_arguments() {
+        let path = PathBuf::from("test_file_io_editor_command.txt");
+        fs::write(&path, "before").unwrap();
+        // an "editor" with arguments and quoting of its own, which the shell splits
+        let editor = |_: &str| Some(r#"sh -c 'printf "%s" "$1" > "$2"' sh 'after edit'"#.into());
+        let status = editor_command_from(editor, &path).unwrap().status();
+        let edited = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(status.unwrap().success());
+        assert_eq!(edited.unwrap(), "after edit");
+    }
+
+    #[test]
+    fn editor_resolution_order() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                let found = vars.iter().find(|(var, _)| *var == name);
+                found.map(|(_, value)| value.into())
+            }
+        };
+        let both = env(&[("VISUAL", "code -w"), ("EDITOR", "nano")]);
+        assert_eq!(resolve_editor_from(both).unwrap(), "code -w");
+        #[cfg(unix)]
+        {
+            let command = editor_command_from(both, Path::new("a b.toml")).unwrap();
+            let args: Vec<_> = command.get_args().collect();
+            assert_eq!(command.get_program(), "sh");
+            assert_eq!(args, ["-c", r#"code -w "$@""#, "code -w", "a b.toml"]);
+        }
+        let empty_visual = env(&[("VISUAL", ""), ("EDITOR", "nano")]);
+        assert_eq!(resolve_editor_from(empty_visual).unwrap(), "nano");
+        let fallback = if cfg!(windows) { "notepad" } else { "vi" };
+        assert_eq!(resolve_editor_from(env(&[])).unwrap(), fallback);
+    }
+
+    #[test]
+    fn merge_arrays_by_key() {
+        use serde_json::json;
+        let base = json!({"servers": [
+            {"name": "a", "port": 1, "tls": true},
+            {"name": "b", "port": 2},
+        ]});
+        let overlay = json!({"servers": [{"name": "b", "port": 20}, {"name": "c", "port": 3}]});
+        let mut merged = base.clone();
+        merge_values_with(
+            &mut merged,
+            overlay.clone(),
+            &ArrayMerge::MergeByKey("name".into()),
+        );
+        assert_eq!(
+            merged,
+            json!({"servers": [
+                {"name": "a", "port": 1, "tls": true},
+                {"name": "b", "port": 20},
+                {"name": "c", "port": 3},
+            ]})
+        );
+        let mut replaced = base;
+        merge_values(&mut replaced, overlay.clone());
+        assert_eq!(replaced, overlay);
+    }
+
+    #[test]
+    fn retry_torn_recovers_and_gives_up() {
+        let mut calls = 0;
+        let recovered = retry_torn(|| {
+            calls += 1;
+            match calls {
+                1 => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                _ => Ok(calls),
+            }
+        });
+        assert_eq!(recovered.unwrap(), 2);
+        let mut calls = 0;
+        let corrupt = retry_torn::<()>(|| {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::InvalidData))
+        });
+        assert_eq!(corrupt.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(calls, STABLE_ATTEMPTS);
+        let missing = retry_torn::<()>(|| Err(io::Error::from(io::ErrorKind::NotFound)));
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn file_io_concurrent_atomic_saves() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_file_io_concurrent_saves.json"));
+        let saved: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let file_io = &file_io;
+                    scope.spawn(move || {
+                        file_io.save_atomic(&Conf {
+                            name: i.to_string(),
+                        })
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let loaded = file_io.load();
+        let left = temps_left(&file_io.path);
+        fs::remove_file(&file_io.path).unwrap();
+        assert!(saved.iter().all(Result::is_ok), "{:?}", saved);
+        assert!(loaded.unwrap().name.parse::<u8>().unwrap() < 8);
+        assert!(!left);
+    }
+
+    #[test]
+    fn temp_file_removed_on_panic() {
+        let path = Path::new("test_temp_file_removed_on_panic.json");
+        let res = std::panic::catch_unwind(|| {
+            let (_temp, mut file) =
+                TempFile::create(Path::new(""), "test_temp_file_removed_on_panic.json").unwrap();
+            file.write_all(b"partial").unwrap();
+            assert!(temps_left(path));
+            panic!("simulated failure before the rename");
+        });
+        assert!(res.is_err());
+        assert!(!temps_left(path));
+    }
+
+    #[test]
+    fn file_io_touch() {
+        let path = PathBuf::from("test_file_io_touch").join("conf.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io.touch().unwrap();
+        let created = fs::read_to_string(&path);
+        let past = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        fs::write(&path, "kept").unwrap();
+        fs::File::options()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+        file_io.touch().unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let kept = fs::read_to_string(&path);
+        fs::remove_dir_all("test_file_io_touch").unwrap();
+        assert_eq!(created.unwrap(), "");
+        assert!(modified > past);
+        assert_eq!(kept.unwrap(), "kept");
+    }
+
+    #[test]
+    fn file_io_swap_with() {
+        let active = PathBuf::from("test_file_io_swap_with_active.json");
+        let staged = PathBuf::from("test_file_io_swap_with_staged.json");
+        fs::write(&active, "active").unwrap();
+        fs::write(&staged, "staged").unwrap();
+        FileIO::<Conf>::new(active.clone())
+            .swap_with(&staged)
+            .unwrap();
+        let active_now = fs::read_to_string(&active).unwrap();
+        let staged_now = fs::read_to_string(&staged).unwrap();
+        fs::remove_file(&active).unwrap();
+        fs::remove_file(&staged).unwrap();
+        assert_eq!(active_now, "staged");
+        assert_eq!(staged_now, "active");
+    }
+
+    #[cfg(feature = "json")]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    struct StreamedConf {
+        pub name: String,
+    }
+    #[cfg(feature = "json")]
+    impl_serde_str_json!(StreamedConf);
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn serde_str_reader_writer() {
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let streamed = StreamedConf {
+            name: "test".to_string(),
+        };
+        let mut buffered_out = Vec::new();
+        conf.ser_to_writer(&mut buffered_out).unwrap();
+        let mut streamed_out = Vec::new();
+        streamed.ser_to_writer(&mut streamed_out).unwrap();
+        assert_eq!(buffered_out, streamed_out);
+        assert_eq!(Conf::de_from_reader(&buffered_out[..]).unwrap(), conf);
+        assert_eq!(
+            StreamedConf::de_from_reader(&streamed_out[..]).unwrap(),
+            streamed
+        );
+    }
+
+    #[test]
+    fn file_io_load_save_through_reader_writer() {
+        // the string methods are never used, so FileIO must go through the overrides
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Raw(String);
+        impl SerdeStr for Raw {
+            fn de_from_str(_: &str) -> Result<Self, io::Error> {
+                unreachable!()
+            }
+            fn ser_to_string(&self) -> Result<String, io::Error> {
+                unreachable!()
+            }
+            fn de_from_reader<R: Read>(mut reader: R) -> Result<Self, io::Error> {
+                let mut string = String::new();
+                reader.read_to_string(&mut string)?;
+                Ok(Raw(string))
+            }
+            fn ser_to_writer<W: Write>(&self, mut writer: W) -> Result<(), io::Error> {
+                writer.write_all(self.0.as_bytes())
+            }
+        }
+        let path = PathBuf::from("test_file_io_load_save_through_reader_writer.txt");
+        let file_io = FileIO::<Raw>::new(path.clone());
+        file_io.save(&Raw("raw text".into())).unwrap();
+        let loaded = file_io.load();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().0, "raw text");
+    }
+
+    #[test]
+    fn file_io_save_durable() {
+        let path = PathBuf::from("test_file_io_save_durable.json");
+        let conf = Conf {
+            name: "test".to_string(),
+        };
+        let file_io = FileIO::<Conf>::new(path.clone());
+        file_io.save_durable(&conf).unwrap();
+        let loaded_durable = file_io.load();
+        file_io.save_atomic_durable(&conf).unwrap();
+        let loaded_atomic = file_io.load();
+        let temp_left = temps_left(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded_durable.unwrap(), conf);
+        assert_eq!(loaded_atomic.unwrap(), conf);
+        assert!(!temp_left);
+    }
+
+    #[cfg(feature = "toml")]
+    mod toml_format {
+        use super::*;
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Server {
+            pub host: String,
+            pub port: u16,
+        }
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Nested {
+            pub server: Server,
+        }
+        impl_serde_str_toml!(Nested);
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+        struct Stamped {
+            pub name: String,
+            pub at: Option<toml::value::Datetime>,
+        }
+        impl_serde_str_toml!(Stamped);
+
+        #[test]
+        fn file_io_toml_datetime() {
+            let path = PathBuf::from("test_file_io_toml_datetime.toml");
+            let conf = Stamped {
+                name: "test".to_string(),
+                at: Some("1979-05-27T07:32:00Z".parse().unwrap()),
+            };
+            let file_io = FileIO::<Stamped>::new(path.clone());
+            file_io.save(&conf).unwrap();
+            let raw = fs::read_to_string(&path).unwrap();
+            let loaded = file_io.load();
+            let defaulted = file_io.load_with_defaults();
+            fs::write(&path, "name = \"test\"\nat = 1979-13-27T07:32:00Z\n").unwrap();
+            let invalid = file_io.load();
+            fs::remove_file(&path).unwrap();
+            assert!(raw.contains("at = 1979-05-27T07:32:00Z"));
+            assert_eq!(loaded.unwrap(), conf);
+            assert_eq!(defaulted.unwrap(), conf);
+            let err = invalid.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains("line 2"), "{}", err);
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Documented {
+            pub name: String,
+            pub server: Server,
+        }
+        impl_serde_str_toml!(Documented);
+
+        #[cfg(feature = "toml_edit")]
+        #[test]
+        fn file_io_patch_keeps_comments() {
+            let path = PathBuf::from("test_file_io_patch_keeps_comments.toml");
+            let original = "# the app name\nname = \"old\" # shown in the title\n\n\
+                            # where to listen\n[server]\nhost = \"localhost\"\nport = 8080\n";
+            fs::write(&path, original).unwrap();
+            let file_io = FileIO::<Documented>::new(path.clone());
+            let patched = file_io.patch(serde_json::json!({ "server": { "port": 9090 } }));
+            let text = fs::read_to_string(&path).unwrap();
+            let invalid = file_io.patch(serde_json::json!({ "server": { "port": "high" } }));
+            let after_invalid = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            patched.unwrap();
+            assert_eq!(text, original.replace("8080", "9090"));
+            assert!(invalid.is_err());
+            assert_eq!(after_invalid, text);
+        }
+
+        #[cfg(feature = "toml_edit")]
+        #[test]
+        fn file_io_patch_rejects_huge_integers() {
+            let path = PathBuf::from("test_file_io_patch_rejects_huge_integers.toml");
+            let original = "name = \"app\"\n[server]\nhost = \"localhost\"\nport = 8080\n";
+            fs::write(&path, original).unwrap();
+            let file_io = FileIO::<Documented>::new(path.clone());
+            let huge = file_io.patch(serde_json::json!({ "big": u64::MAX }));
+            let after = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            let huge = huge.unwrap_err();
+            assert_eq!(huge.kind(), io::ErrorKind::InvalidData);
+            assert!(huge.to_string().contains("too large for a TOML integer"));
+            assert_eq!(after, original);
+        }
+
+        #[test]
+        fn file_io_load_layered() {
+            let base = PathBuf::from("test_file_io_load_layered.toml");
+            let prod = PathBuf::from("test_file_io_load_layered.prod.toml");
+            let missing = PathBuf::from("test_file_io_load_layered.missing.toml");
+            fs::write(
+                &base,
+                "name = \"app\"\n[server]\nhost = \"localhost\"\nport = 8080\n",
+            )
+            .unwrap();
+            fs::write(&prod, "[server]\nport = 443\n").unwrap();
+            let file_io = FileIO::<Documented>::new(base.clone());
+            let layered = file_io.load_layered(&[prod.clone(), missing]);
+            fs::write(&prod, "[server\n").unwrap();
+            let malformed = file_io.load_layered(std::slice::from_ref(&prod));
+            fs::remove_file(&base).unwrap();
+            fs::remove_file(&prod).unwrap();
+            let layered = layered.unwrap();
+            assert_eq!(layered.name, "app");
+            assert_eq!(
+                layered.server,
+                Server {
+                    host: "localhost".to_string(),
+                    port: 443,
+                }
+            );
+            assert_eq!(malformed.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn file_io_load_with_includes() {
+            let main = PathBuf::from("test_file_io_includes.toml");
+            let server = PathBuf::from("test_file_io_includes.server.toml");
+            fs::write(
+                &main,
+                "include = \"test_file_io_includes.server.toml\"\nname = \"app\"\n",
+            )
+            .unwrap();
+            fs::write(&server, "[server]\nhost = \"localhost\"\nport = 8080\n").unwrap();
+            let file_io = FileIO::<Documented>::new(main.clone());
+            let included = file_io.load_with_includes("include");
+            fs::write(
+                &server,
+                "include = \"test_file_io_includes.toml\"\n[server]\nhost = \"x\"\nport = 1\n",
+            )
+            .unwrap();
+            let cyclic = file_io.load_with_includes("include");
+            fs::remove_file(&main).unwrap();
+            fs::remove_file(&server).unwrap();
+            let included = included.unwrap();
+            assert_eq!(included.name, "app");
+            assert_eq!(included.server.port, 8080);
+            assert_eq!(cyclic.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn file_io_save_documented() {
+            let path = PathBuf::from("test_file_io_save_documented.toml");
+            let conf = Documented {
+                name: "test".to_string(),
+                server: Server {
+                    host: "localhost".to_string(),
+                    port: 8080,
+                },
+            };
+            let file_io = FileIO::<Documented>::new(path.clone());
+            let docs = [
+                ("name", "Display name"),
+                ("server", "Where to listen\nin full"),
+            ];
+            file_io.save_documented(&conf, &docs).unwrap();
+            let raw = fs::read_to_string(&path).unwrap();
+            let loaded = file_io.load();
+            fs::remove_file(&path).unwrap();
+            assert!(raw.contains("# Display name\nname = "), "{}", raw);
+            assert!(
+                raw.contains("# Where to listen\n# in full\n[server]"),
+                "{}",
+                raw
+            );
+            assert_eq!(loaded.unwrap(), conf);
+        }
+
+        #[cfg(feature = "json")]
+        #[test]
+        fn file_io_convert_json_to_toml() {
+            let json = PathBuf::from("test_file_io_convert.json");
+            let toml = PathBuf::from("test_file_io_convert.toml");
+            fs::write(&json, r#"{"server":{"host":"localhost","port":8080}}"#).unwrap();
+            FileIO::<Nested>::convert(&json, &toml).unwrap();
+            let converted = FileIO::<Nested>::new(toml.clone()).load();
+            fs::remove_file(&json).unwrap();
+            fs::remove_file(&toml).unwrap();
+            assert_eq!(
+                converted.unwrap().server,
+                Server {
+                    host: "localhost".to_string(),
+                    port: 8080,
+                }
+            );
+        }
+
+        #[test]
+        fn file_io_load_with_env_overlay() {
+            let path = PathBuf::from("test_file_io_load_with_env_overlay.toml");
+            fs::write(&path, "[server]\nhost = \"localhost\"\nport = 8080\n").unwrap();
+            std::env::set_var("SCULPTOR_ENV_OVERLAY_SERVER__PORT", "9090");
+            let loaded =
+                FileIO::<Nested>::new(path.clone()).load_with_env_overlay("SCULPTOR_ENV_OVERLAY");
+            std::env::remove_var("SCULPTOR_ENV_OVERLAY_SERVER__PORT");
+            fs::remove_file(&path).unwrap();
+            assert_eq!(
+                loaded.unwrap().server,
+                Server {
+                    host: "localhost".to_string(),
+                    port: 9090,
+                }
+            );
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Layers {
+            pub default: String,
+            pub file: String,
+            pub env: String,
+            pub cli: String,
+        }
+        impl Default for Layers {
+            fn default() -> Self {
+                Self {
+                    default: "default".to_string(),
+                    file: "default".to_string(),
+                    env: "default".to_string(),
+                    cli: "default".to_string(),
+                }
+            }
+        }
+        impl_serde_str_toml!(Layers);
+
+        #[test]
+        fn file_io_resolve_precedence() {
+            let path = PathBuf::from("test_file_io_resolve_precedence.toml");
+            fs::write(&path, "file = \"file\"\nenv = \"file\"\ncli = \"file\"\n").unwrap();
+            std::env::set_var("SCULPTOR_RESOLVE_ENV", "env");
+            std::env::set_var("SCULPTOR_RESOLVE_CLI", "env");
+            let cli = serde_json::json!({ "cli": "cli" });
+            let resolved =
+                FileIO::<Layers>::new(path.clone()).resolve("SCULPTOR_RESOLVE", Some(cli));
+            std::env::remove_var("SCULPTOR_RESOLVE_ENV");
+            std::env::remove_var("SCULPTOR_RESOLVE_CLI");
+            fs::remove_file(&path).unwrap();
+            assert_eq!(
+                resolved.unwrap(),
+                Layers {
+                    default: "default".to_string(),
+                    file: "file".to_string(),
+                    env: "env".to_string(),
+                    cli: "cli".to_string(),
+                }
+            );
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Evolving {
+            pub name: String,
+            pub retries: u32,
+        }
+        impl Default for Evolving {
+            fn default() -> Self {
+                Self {
+                    name: "default".to_string(),
+                    retries: 3,
+                }
+            }
+        }
+        impl_serde_str_toml!(Evolving);
+
+        #[test]
+        fn file_io_load_with_defaults() {
+            let path = PathBuf::from("test_file_io_load_with_defaults.toml");
+            fs::write(&path, "name = \"partial\"\n").unwrap();
+            let file_io = FileIO::<Evolving>::new(path.clone());
+            let strict = file_io.load();
+            let loaded = file_io.load_with_defaults();
+            fs::remove_file(&path).unwrap();
+            assert!(strict.is_err());
+            assert_eq!(
+                loaded.unwrap(),
+                Evolving {
+                    name: "partial".to_string(),
+                    retries: 3,
+                }
+            );
+        }
+    }
 }
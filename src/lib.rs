@@ -1,3 +1,9 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+//! Without the default `std` feature only the core of `sha_snap` (`ShaSnap`, `Digest`,
+//! framing, resumable and rolling hashing) is available, on `alloc`
+
+extern crate alloc;
+
 #[macro_export]
 macro_rules! submod {
     ( $( $m:ident ),* ) => {
@@ -8,6 +14,12 @@ macro_rules! submod {
     };
 }
 
+#[cfg(all(
+    feature = "std",
+    any(feature = "project_info", feature = "file_io", feature = "sha_snap")
+))]
+mod temp;
+
 #[cfg(feature = "project_info")]
 submod!(project_info);
 #[cfg(feature = "file_io")]
@@ -15,5 +27,17 @@ submod!(file_io);
 #[cfg(feature = "sha_snap")]
 submod!(sha_snap);
 
-// diff
-// watch
\ No newline at end of file
+#[cfg(feature = "lock")]
+submod!(lock);
+#[cfg(feature = "watch")]
+submod!(watch);
+#[cfg(feature = "schema")]
+submod!(schema);
+// only adds methods to `FileIO`, so there is nothing to re-export
+#[cfg(feature = "reveal")]
+mod reveal;
+
+#[cfg(feature = "file_io")]
+submod!(diff);
+#[cfg(feature = "file_io")]
+submod!(validate);
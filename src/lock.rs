@@ -0,0 +1,528 @@
+use crate::{FileIO, SerdeStr};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Locks older than this are considered abandoned even if their pid looks alive,
+/// which guards against pid reuse after a crash
+pub const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An advisory lock held through a sidecar `<file>.lock`; released on drop
+///
+/// While held, the lock also keeps an exclusive OS file lock on `<file>.rwlock`, which
+/// [`FileIO::load_shared`] readers lock shared. The sidecar records the owner as a JSON
+/// [`LockInfo`]. A lock is broken when its
+/// owner on this host is gone (checked through `/proc` on Linux) or it is older than
+/// [`STALE_AFTER`]. Breaking is not atomic: two processes judging the same lock stale
+/// at the same moment may both acquire it, so the lock only guards against
+/// cooperating processes, not adversarial ones.
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    /// Locked exclusively, and unlocked once closed
+    rw: Option<fs::File>,
+}
+
+impl FileLock {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Who holds a lock, as recorded in its sidecar, see [`FileIO::lock_holder`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub pid: u32,
+    pub hostname: String,
+    /// Unix time the lock was taken at
+    pub timestamp: u64,
+}
+
+impl std::fmt::Display for LockInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let since = time::OffsetDateTime::from_unix_timestamp(self.timestamp as i64)
+            .ok()
+            .and_then(|t| {
+                t.format(&time::format_description::well_known::Rfc3339)
+                    .ok()
+            })
+            .unwrap_or_else(|| self.timestamp.to_string());
+        write!(
+            f,
+            "PID {} on host {} since {}",
+            self.pid, self.hostname, since
+        )
+    }
+}
+
+fn hostname() -> String {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok());
+    name.map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn pid_alive(pid: u32) -> Option<bool> {
+    if cfg!(target_os = "linux") {
+        Some(Path::new("/proc").join(pid.to_string()).exists())
+    } else {
+        None
+    }
+}
+
+/// Reads a sidecar lock; `None` if it is gone, and `Some(None)` if it is unreadable,
+/// such as while its owner is still writing it
+fn read_holder(path: &Path) -> io::Result<Option<Option<LockInfo>>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content).ok())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads a sidecar lock and decides whether its owner has abandoned it
+fn is_stale(path: &Path) -> io::Result<bool> {
+    let info = match read_holder(path)? {
+        // released in the meantime
+        None => return Ok(true),
+        Some(Some(info)) => info,
+        Some(None) => {
+            // the owner may not have finished writing yet, so only old garbage is stale
+            let age = fs::metadata(path)?.modified()?.elapsed().unwrap_or_default();
+            return Ok(age > Duration::from_secs(1));
+        }
+    };
+    let expired = now_secs().saturating_sub(info.timestamp) > STALE_AFTER.as_secs();
+    // another host's pids say nothing about the owner
+    let dead = info.hostname == hostname() && pid_alive(info.pid) == Some(false);
+    Ok(expired || dead)
+}
+
+fn create_lock(path: &Path) -> io::Result<FileLock> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    let lock = FileLock {
+        path: path.to_path_buf(),
+        rw: None,
+    };
+    let info = LockInfo {
+        pid: std::process::id(),
+        hostname: hostname(),
+        timestamp: now_secs(),
+    };
+    file.write_all(serde_json::to_string(&info)?.as_bytes())?;
+    Ok(lock)
+}
+
+impl<T> FileIO<T>
+where
+    T: SerdeStr,
+{
+    pub fn lock_path(&self) -> PathBuf {
+        let mut ext = self
+            .path
+            .extension()
+            .unwrap_or_default()
+            .to_owned()
+            .into_string()
+            .unwrap_or_default();
+        ext += ".lock";
+        self.path.with_extension(ext)
+    }
+    /// The file [`FileIO::load_shared`] readers and lock holders coordinate through; it is
+    /// left in place, as removing it could let a reader and a writer lock different files
+    pub fn rw_lock_path(&self) -> PathBuf {
+        let mut ext = self
+            .path
+            .extension()
+            .unwrap_or_default()
+            .to_owned()
+            .into_string()
+            .unwrap_or_default();
+        ext += ".rwlock";
+        self.path.with_extension(ext)
+    }
+    fn open_rw_lock(&self) -> io::Result<fs::File> {
+        fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.rw_lock_path())
+    }
+    /// Takes the lock if it is free or stale, failing with `WouldBlock` otherwise,
+    /// including while [`FileIO::load_shared`] readers are busy
+    pub fn try_lock(&self) -> io::Result<FileLock> {
+        let mut lock = self.try_lock_sidecar()?;
+        let rw = self.open_rw_lock()?;
+        match rw.try_lock() {
+            Ok(()) => {}
+            Err(fs::TryLockError::WouldBlock) => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{} is being read by another process", self.path.display()),
+            ))?,
+            Err(fs::TryLockError::Error(e)) => Err(e)?,
+        }
+        lock.rw = Some(rw);
+        Ok(lock)
+    }
+    fn try_lock_sidecar(&self) -> io::Result<FileLock> {
+        self.ensure_parent()?;
+        let path = self.lock_path();
+        match create_lock(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            res => return res,
+        }
+        if !is_stale(&path)? {
+            Err(self.held_error())?;
+        }
+        log::warn!("breaking stale lock {}", path.display());
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e)?,
+            _ => {}
+        }
+        create_lock(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => self.held_error(),
+            _ => e,
+        })
+    }
+    /// The `WouldBlock` error of a failed [`FileIO::try_lock`], naming the holder if known
+    fn held_error(&self) -> io::Error {
+        let message = match self.lock_holder() {
+            Ok(Some(holder)) => format!("{} is locked by {}", self.path.display(), holder),
+            _ => format!("{} is locked by another process", self.path.display()),
+        };
+        io::Error::new(io::ErrorKind::WouldBlock, message)
+    }
+    /// Who holds the lock, or `None` if it is free, e.g. to tell the user what blocks them
+    ///
+    /// A sidecar that doesn't hold a [`LockInfo`], such as one still being written, fails
+    /// with `InvalidData`.
+    pub fn lock_holder(&self) -> io::Result<Option<LockInfo>> {
+        let path = self.lock_path();
+        match read_holder(&path)? {
+            None => Ok(None),
+            Some(Some(info)) => Ok(Some(info)),
+            Some(None) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} doesn't record a lock holder", path.display()),
+            )),
+        }
+    }
+    /// Waits until the lock can be taken
+    pub fn lock(&self) -> io::Result<FileLock> {
+        loop {
+            match self.try_lock() {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(RETRY_INTERVAL),
+                res => return res,
+            }
+        }
+    }
+    /// Loads the file under a shared OS lock on [`FileIO::rw_lock_path`], so the read
+    /// never overlaps a holder of [`FileIO::lock`]: it waits for a current holder to
+    /// finish, and writers trying to lock meanwhile wait for the read instead
+    ///
+    /// Any number of shared reads run at once. This only guards against writers that
+    /// take the lock before writing, e.g. through [`FileIO::locked_saves`]; plain saves
+    /// may still be observed half-written.
+    ///
+    /// Reading creates nothing: if no writer ever locked the file, so that the rwlock file
+    /// is missing, the file is loaded without the shared lock.
+    pub fn load_shared(&self) -> io::Result<T> {
+        let rw = match fs::File::open(self.rw_lock_path()) {
+            Ok(rw) => Some(rw),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => Err(e)?,
+        };
+        if let Some(rw) = &rw {
+            rw.lock_shared()?;
+        }
+        // unlocked as `rw` is closed
+        self.load_read_only()
+    }
+    /// Optimistic concurrency: saves `conf` atomically only if the file's
+    /// [`checksum`](FileIO::checksum) still is `expected_hash`, e.g. the one taken when it
+    /// was loaded, returning `false` without writing if another writer got there first
+    ///
+    /// The check and the save happen under [`FileIO::lock`], so writers going through
+    /// this method can't lose each other's updates. A missing file is `NotFound`.
+    #[cfg(feature = "sha_snap")]
+    pub fn compare_and_save(&self, expected_hash: &str, conf: &T) -> io::Result<bool> {
+        let _lock = self.lock()?;
+        if self.checksum()? != expected_hash {
+            return Ok(false);
+        }
+        self.save_atomic(conf)?;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl FileLock {
+    /// Releases the lock on tokio's blocking thread pool, reporting a failure to remove
+    /// the sidecar instead of ignoring it as dropping does
+    pub async fn release_async(self) -> io::Result<()> {
+        // skips the synchronous removal in `Drop`
+        let mut this = std::mem::ManuallyDrop::new(self);
+        let path = std::mem::take(&mut this.path);
+        let rw = this.rw.take();
+        tokio::task::spawn_blocking(move || {
+            let removed = fs::remove_file(path);
+            drop(rw);
+            removed
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T> FileIO<T>
+where
+    T: SerdeStr + Send + 'static,
+{
+    /// [`FileIO::lock`] for async code: every attempt runs on tokio's blocking thread pool
+    /// and the waits in between are async sleeps, so the executor is never stalled
+    ///
+    /// The lock itself is the same advisory sidecar, so async and blocking holders
+    /// exclude each other. Dropping the lock still releases it, synchronously.
+    pub async fn lock_async(&self) -> io::Result<FileLock> {
+        loop {
+            let file_io = self.clone();
+            let attempt = tokio::task::spawn_blocking(move || file_io.try_lock())
+                .await
+                .map_err(io::Error::other)?;
+            match attempt {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    tokio::time::sleep(RETRY_INTERVAL).await
+                }
+                res => return res,
+            }
+        }
+    }
+    /// Runs `f` while holding the lock, then releases it with [`FileLock::release_async`]
+    pub async fn with_lock_async<F, Fut, R>(&self, f: F) -> io::Result<R>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = R>,
+    {
+        let lock = self.lock_async().await?;
+        let res = f().await;
+        lock.release_async().await?;
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct Conf {
+        pub name: String,
+    }
+    impl SerdeStr for Conf {
+        fn de_from_str(string: &str) -> Result<Self, io::Error> {
+            Ok(serde_json::from_str(string)?)
+        }
+        fn ser_to_string(&self) -> Result<String, io::Error> {
+            Ok(serde_json::to_string(self)?)
+        }
+    }
+
+    /// Removes a test's file and its lock files once dropped, even if the test panics
+    struct Litter(Vec<PathBuf>);
+    impl Litter {
+        fn of<T: SerdeStr>(file_io: &FileIO<T>) -> Self {
+            Self(vec![
+                file_io.path.clone(),
+                file_io.lock_path(),
+                file_io.rw_lock_path(),
+            ])
+        }
+    }
+    impl Drop for Litter {
+        fn drop(&mut self) {
+            for path in &self.0 {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    #[test]
+    fn live_lock_blocks() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_lock_live_lock_blocks.json"));
+        let _litter = Litter::of(&file_io);
+        let lock = file_io.try_lock().unwrap();
+        let holder = file_io.lock_holder().unwrap().unwrap();
+        let second = file_io.try_lock().unwrap_err();
+        drop(lock);
+        assert_eq!(holder.pid, std::process::id());
+        assert_eq!(holder.hostname, hostname());
+        assert_eq!(second.kind(), io::ErrorKind::WouldBlock);
+        let held_by = format!("PID {} on host {} since ", holder.pid, holder.hostname);
+        assert!(second.to_string().contains(&held_by));
+        assert!(!file_io.lock_path().exists());
+        assert!(file_io.lock_holder().unwrap().is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn dead_pid_lock_is_reclaimed() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_lock_dead_pid_reclaimed.json"));
+        let _litter = Litter::of(&file_io);
+        // above the kernel's pid_max, so never alive
+        let dead = LockInfo {
+            pid: 999999999,
+            hostname: hostname(),
+            timestamp: now_secs(),
+        };
+        fs::write(file_io.lock_path(), serde_json::to_string(&dead).unwrap()).unwrap();
+        let lock = file_io.try_lock();
+        let holder = file_io.lock_holder();
+        drop(lock);
+        assert_eq!(holder.unwrap().unwrap().pid, std::process::id());
+    }
+
+    #[test]
+    fn shared_read_blocks_writer() {
+        #[derive(Serialize, Deserialize)]
+        struct SlowConf {
+            name: String,
+        }
+        impl SerdeStr for SlowConf {
+            fn de_from_str(string: &str) -> Result<Self, io::Error> {
+                thread::sleep(Duration::from_millis(300));
+                Ok(serde_json::from_str(string)?)
+            }
+            fn ser_to_string(&self) -> Result<String, io::Error> {
+                Ok(serde_json::to_string(self)?)
+            }
+        }
+        let file_io = FileIO::<SlowConf>::new(PathBuf::from("test_lock_shared_read.json"));
+        let _litter = Litter::of(&file_io);
+        fs::write(&file_io.path, r#"{"name":"shared"}"#).unwrap();
+        // a first writer creates the rwlock file readers lock
+        drop(file_io.try_lock().unwrap());
+        let started = std::time::Instant::now();
+        let reader = {
+            let file_io = file_io.clone();
+            thread::spawn(move || file_io.load_shared())
+        };
+        thread::sleep(Duration::from_millis(100));
+        let during_read = file_io.try_lock().map(drop).unwrap_err();
+        let lock = file_io.lock();
+        let waited = started.elapsed();
+        drop(lock);
+        let conf = reader.join().unwrap();
+        assert_eq!(during_read.kind(), io::ErrorKind::WouldBlock);
+        assert!(!file_io.lock_path().exists());
+        assert!(waited >= Duration::from_millis(300));
+        assert_eq!(conf.unwrap().name, "shared");
+    }
+
+    #[test]
+    fn robust_save_persists_and_unlocks() {
+        let path = PathBuf::from("test_lock_robust_save.json");
+        let file_io = FileIO::<Conf>::new(path.clone())
+            .atomic_saves()
+            .durable_saves()
+            .locked_saves()
+            .retry_saves(2);
+        let _litter = Litter::of(&file_io);
+        let saved = file_io.save(&Conf {
+            name: "safe".into(),
+        });
+        let content = file_io.load();
+        let lock_left = file_io.lock_path().exists();
+        saved.unwrap();
+        assert_eq!(content.unwrap().name, "safe");
+        assert!(!lock_left);
+    }
+
+    #[test]
+    fn load_shared_creates_nothing() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_lock_load_shared_creates.json"));
+        let _litter = Litter::of(&file_io);
+        fs::write(&file_io.path, r#"{"name":"unlocked"}"#).unwrap();
+        let loaded = file_io.load_shared();
+        let rw_created = file_io.rw_lock_path().exists();
+        let nested = PathBuf::from("test_lock_load_shared_missing").join("conf.json");
+        let missing = FileIO::<Conf>::new(nested.clone()).load_shared();
+        assert_eq!(loaded.unwrap().name, "unlocked");
+        assert!(!rw_created);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(!nested.parent().unwrap().exists());
+    }
+
+    #[cfg(feature = "sha_snap")]
+    #[test]
+    fn compare_and_save_rejects_stale_hash() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_lock_compare_and_save.json"));
+        let _litter = Litter::of(&file_io);
+        file_io.save(&Conf { name: "v1".into() }).unwrap();
+        let loaded_hash = file_io.checksum().unwrap();
+        let first = file_io.compare_and_save(&loaded_hash, &Conf { name: "v2".into() });
+        let stale = file_io.compare_and_save(&loaded_hash, &Conf { name: "v3".into() });
+        let content = file_io.load();
+        assert!(first.unwrap());
+        assert!(!stale.unwrap());
+        assert_eq!(content.unwrap().name, "v2");
+        assert!(!file_io.lock_path().exists());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_lock_serializes_tasks() {
+        use std::sync::{Arc, Mutex};
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_lock_async_serializes.json"));
+        let _litter = Litter::of(&file_io);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let task = |name: &'static str, delay: u64| {
+            let (file_io, log) = (file_io.clone(), log.clone());
+            async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                file_io
+                    .with_lock_async(|| async {
+                        log.lock().unwrap().push(format!("{} start", name));
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        log.lock().unwrap().push(format!("{} end", name));
+                    })
+                    .await
+            }
+        };
+        let (first, second) = tokio::join!(task("first", 0), task("second", 20));
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["first start", "first end", "second start", "second end"]
+        );
+        assert!(!file_io.lock_path().exists());
+    }
+}
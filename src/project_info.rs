@@ -1,6 +1,20 @@
-use directories::ProjectDirs;
+use directories::{BaseDirs, ProjectDirs};
 use once_cell::sync::Lazy;
-use std::path::PathBuf;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// The kinds of directories a project may own, see [`ProjectInfo::all_dirs`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DirKind {
+    Config,
+    Data,
+    Cache,
+    State,
+    Runtime,
+}
 
 /// Implement this trait to get ProjectInfo, which provides directories for the project
 pub trait AppAuthor {
@@ -27,16 +41,617 @@ pub trait ProjectInfo: LazyProjectDirs {
         Self::lazy_project_dirs().to_owned()
     }
     fn config_dir() -> PathBuf {
-        Self::lazy_project_dirs().config_dir().to_path_buf()
+        let dirs = Self::lazy_project_dirs();
+        redirected("config", dirs.project_path()).unwrap_or_else(|| dirs.config_dir().to_path_buf())
     }
     fn data_dir() -> PathBuf {
-        Self::lazy_project_dirs().data_dir().to_path_buf()
+        let dirs = Self::lazy_project_dirs();
+        redirected("data", dirs.project_path()).unwrap_or_else(|| dirs.data_dir().to_path_buf())
     }
     fn cache_dir() -> PathBuf {
-        Self::lazy_project_dirs().cache_dir().to_path_buf()
+        let dirs = Self::lazy_project_dirs();
+        redirected("cache", dirs.project_path()).unwrap_or_else(|| dirs.cache_dir().to_path_buf())
+    }
+    /// Whether this platform has a state directory at all: true on Linux and other XDG
+    /// platforms, false on macOS and Windows, regardless of whether it exists yet
+    fn has_state_dir() -> bool {
+        Self::lazy_project_dirs().state_dir().is_some()
     }
     fn state_dir() -> Option<PathBuf> {
-        Some(Self::lazy_project_dirs().state_dir()?.to_path_buf())
+        let dirs = Self::lazy_project_dirs();
+        let real = dirs.state_dir()?;
+        Some(redirected("state", dirs.project_path()).unwrap_or_else(|| real.to_path_buf()))
+    }
+    /// Every directory the project may have created, e.g. for an uninstaller; kinds the
+    /// platform lacks are left out, and some kinds may share a path (config and data on macOS)
+    ///
+    /// The runtime directory is never redirected by the test override root.
+    fn all_dirs() -> Vec<(DirKind, PathBuf)> {
+        let mut dirs = vec![
+            (DirKind::Config, Self::config_dir()),
+            (DirKind::Data, Self::data_dir()),
+            (DirKind::Cache, Self::cache_dir()),
+        ];
+        dirs.extend(Self::state_dir().map(|dir| (DirKind::State, dir)));
+        let runtime = Self::lazy_project_dirs()
+            .runtime_dir()
+            .map(Path::to_path_buf);
+        dirs.extend(runtime.map(|dir| (DirKind::Runtime, dir)));
+        dirs
+    }
+    /// The project's own path component(s) shared by the other directories, e.g. `<app>`
+    /// on Linux or `<author>.<app>` on macOS; relative, see [`ProjectDirs::project_path`]
+    fn project_path() -> PathBuf {
+        Self::lazy_project_dirs().project_path().to_path_buf()
+    }
+
+    /// A human-readable report of how the directories were resolved, for debugging
+    /// "why is my config over there" reports
+    fn describe() -> String
+    where
+        Self: AppAuthor,
+    {
+        let strategy = match std::env::consts::OS {
+            "linux" | "freebsd" | "netbsd" | "openbsd" | "dragonfly" => "XDG base directories",
+            "macos" | "ios" => "Apple standard directories",
+            "windows" => "Known Folder API",
+            _ => "unknown",
+        };
+        let state = match Self::state_dir() {
+            Some(dir) => dir.display().to_string(),
+            None => "(none on this platform)".to_owned(),
+        };
+        let mut report = format!(
+            "platform: {} ({})\n\
+             qualifier: \"\"\n\
+             organization: {:?}\n\
+             application: {:?}\n\
+             project path: {}\n",
+            std::env::consts::OS,
+            strategy,
+            Self::author(),
+            Self::app_name(),
+            Self::project_path().display(),
+        );
+        if redirected("config", Path::new("")).is_some() {
+            report += "override root: active\n";
+        }
+        report += &format!(
+            "config: {}\ndata: {}\ncache: {}\nstate: {}\n",
+            Self::config_dir().display(),
+            Self::data_dir().display(),
+            Self::cache_dir().display(),
+            state,
+        );
+        report
+    }
+
+    /// Calls `on_event` for every file created, modified or removed under the config
+    /// directory; watching stops when the returned guard is dropped
+    #[cfg(feature = "watch")]
+    fn watch_config_dir(
+        on_event: impl FnMut(crate::ConfigEvent) + Send + 'static,
+    ) -> io::Result<crate::WatchGuard> {
+        crate::watch::watch_dir(&Self::config_dir(), on_event)
+    }
+
+    /// The conventional preferences file `name` inside the config directory
+    fn preferences_file(name: &str) -> PathBuf {
+        Self::config_dir().join(name)
+    }
+    /// A [`FileIO`](crate::FileIO) for [`ProjectInfo::preferences_file`]
+    #[cfg(feature = "file_io")]
+    fn preferences<T: crate::SerdeStr>(name: &str) -> crate::FileIO<T> {
+        crate::FileIO::new(Self::preferences_file(name))
+    }
+    /// [`ProjectInfo::preferences_file`] as a source for a `config::Config::builder()`,
+    /// its format guessed from the extension; chain `.required(false)` for optional files
+    #[cfg(feature = "config")]
+    fn config_source(name: &str) -> config::File<config::FileSourceFile, config::FileFormat> {
+        config::File::from(Self::preferences_file(name))
+    }
+    /// `true` only on the first call ever for this app, tracked by `<data_dir>/.initialized`
+    fn is_first_run() -> io::Result<bool> {
+        Self::is_first_run_with(".initialized")
+    }
+    /// [`ProjectInfo::is_first_run`] with a custom marker file name inside the data directory
+    fn is_first_run_with(marker: &str) -> io::Result<bool> {
+        let dir = Self::data_dir();
+        fs::create_dir_all(&dir)?;
+        // creating the marker atomically makes exactly one caller see the first run
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(marker))
+        {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    /// Scaffolds the config directory from `(relative_path, contents)` pairs, e.g. bundled
+    /// default files on first launch, returning the files it created
+    ///
+    /// Files that already exist are left untouched, so user edits survive. Paths escaping
+    /// the config directory, being absolute or going through `..`, fail with `InvalidInput`.
+    fn init_from_skeleton(files: &[(&str, &str)]) -> io::Result<Vec<PathBuf>> {
+        let dir = Self::config_dir();
+        let mut created = Vec::new();
+        for &(relative, contents) in files {
+            let relative = Path::new(relative);
+            let escapes = relative.components().any(|c| {
+                !matches!(
+                    c,
+                    std::path::Component::Normal(_) | std::path::Component::CurDir
+                )
+            });
+            if escapes {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not inside the config directory", relative.display()),
+                ))?;
+            }
+            let path = dir.join(relative);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    file.write_all(contents.as_bytes())?;
+                    created.push(path);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => Err(e)?,
+            }
+        }
+        Ok(created)
+    }
+
+    /// Creates a new, uniquely named `<prefix>.<pid>.<n>.tmp` file in the data directory,
+    /// so that renaming it over a data file stays on one file system and thus atomic
+    ///
+    /// The file is not removed automatically.
+    fn temp_in_data_dir(prefix: &str) -> io::Result<(fs::File, PathBuf)> {
+        let dir = Self::data_dir();
+        fs::create_dir_all(&dir)?;
+        crate::temp::create_unique(&dir, prefix)
+    }
+
+    /// Removes everything inside the cache directory, keeping the directory itself
+    fn clear_cache_dir() -> io::Result<()> {
+        clear_dir_contents(&Self::cache_dir())
+    }
+    /// Removes everything inside the config directory, keeping the directory itself
+    fn clear_config_dir() -> io::Result<()> {
+        clear_dir_contents(&Self::config_dir())
+    }
+    /// Removes everything inside the data directory, keeping the directory itself
+    fn clear_data_dir() -> io::Result<()> {
+        clear_dir_contents(&Self::data_dir())
     }
 }
 impl<T: LazyProjectDirs> ProjectInfo for T {}
+
+/// Provides the same directories as [`ProjectInfo`] for names only known at runtime
+pub struct RuntimeProjectInfo {
+    dirs: ProjectDirs,
+}
+
+impl RuntimeProjectInfo {
+    pub fn new(qualifier: &str, organization: &str, application: &str) -> Option<Self> {
+        let dirs = ProjectDirs::from(qualifier, organization, application)?;
+        Some(Self { dirs })
+    }
+    pub fn project_dirs(&self) -> &ProjectDirs {
+        &self.dirs
+    }
+    pub fn config_dir(&self) -> PathBuf {
+        redirected("config", self.dirs.project_path())
+            .unwrap_or_else(|| self.dirs.config_dir().to_path_buf())
+    }
+    pub fn data_dir(&self) -> PathBuf {
+        redirected("data", self.dirs.project_path())
+            .unwrap_or_else(|| self.dirs.data_dir().to_path_buf())
+    }
+    pub fn cache_dir(&self) -> PathBuf {
+        redirected("cache", self.dirs.project_path())
+            .unwrap_or_else(|| self.dirs.cache_dir().to_path_buf())
+    }
+    /// See [`ProjectInfo::has_state_dir`]
+    pub fn has_state_dir(&self) -> bool {
+        self.dirs.state_dir().is_some()
+    }
+    pub fn state_dir(&self) -> Option<PathBuf> {
+        let real = self.dirs.state_dir()?;
+        Some(redirected("state", self.dirs.project_path()).unwrap_or_else(|| real.to_path_buf()))
+    }
+    pub fn project_path(&self) -> PathBuf {
+        self.dirs.project_path().to_path_buf()
+    }
+    /// See [`ProjectInfo::all_dirs`]
+    pub fn all_dirs(&self) -> Vec<(DirKind, PathBuf)> {
+        let mut dirs = vec![
+            (DirKind::Config, self.config_dir()),
+            (DirKind::Data, self.data_dir()),
+            (DirKind::Cache, self.cache_dir()),
+        ];
+        dirs.extend(self.state_dir().map(|dir| (DirKind::State, dir)));
+        let runtime = self.dirs.runtime_dir().map(Path::to_path_buf);
+        dirs.extend(runtime.map(|dir| (DirKind::Runtime, dir)));
+        dirs
+    }
+}
+
+#[cfg(feature = "test-util")]
+static OVERRIDE_ROOT: std::sync::RwLock<Option<PathBuf>> = std::sync::RwLock::new(None);
+
+/// Redirects the config, data, cache and state directories of every project to
+/// `<root>/<kind>/<project path>` for the rest of the process, keeping tests away from
+/// the real user directories; [`migrate_from`] follows it too, while platforms without
+/// a state directory still have none
+///
+/// This is process-wide, so tests relying on it are best kept in their own integration
+/// test binary. [`ProjectInfo::project_dirs`] still reports the real directories.
+#[cfg(feature = "test-util")]
+pub fn set_override_root(root: PathBuf) {
+    *OVERRIDE_ROOT.write().unwrap_or_else(|e| e.into_inner()) = Some(root);
+}
+
+/// Undoes [`set_override_root`]
+#[cfg(feature = "test-util")]
+pub fn clear_override_root() {
+    *OVERRIDE_ROOT.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Points the crate's own unit tests at a per-process root under the temp directory,
+/// set once and never cleared so that concurrent tests agree on it
+///
+/// Without `test-util` this does nothing, so tests writing to the directories are gated
+/// on that feature.
+#[cfg(test)]
+pub(crate) fn hermetic_root() {
+    #[cfg(feature = "test-util")]
+    {
+        static ROOT: std::sync::Once = std::sync::Once::new();
+        ROOT.call_once(|| {
+            let root =
+                std::env::temp_dir().join(format!("sculptor-test-root-{}", std::process::id()));
+            set_override_root(root);
+        });
+    }
+}
+
+/// Copies the config and data directories of `old` over to `new`, e.g. after the app
+/// was renamed, returning whether anything was copied
+///
+/// Each directory is only copied if the old one exists and the new one is missing or
+/// empty, so settings made under the new identity are never overwritten and later runs
+/// do nothing. The old directories are left in place.
+pub fn migrate_from(old: &ProjectDirs, new: &ProjectDirs) -> io::Result<bool> {
+    let mut migrated = false;
+    let dir = |dirs: &ProjectDirs, kind: &str, real: &Path| {
+        redirected(kind, dirs.project_path()).unwrap_or_else(|| real.to_path_buf())
+    };
+    let pairs = [
+        (
+            dir(old, "config", old.config_dir()),
+            dir(new, "config", new.config_dir()),
+        ),
+        (
+            dir(old, "data", old.data_dir()),
+            dir(new, "data", new.data_dir()),
+        ),
+    ];
+    for (from, to) in pairs {
+        if from.is_dir() && from != to && is_empty_dir(&to)? {
+            copy_dir(&from, &to)?;
+            migrated = true;
+        }
+    }
+    Ok(migrated)
+}
+
+fn is_empty_dir(dir: &Path) -> io::Result<bool> {
+    match fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "test-util"), allow(unused_variables))]
+fn redirected(kind: &str, project_path: &Path) -> Option<PathBuf> {
+    #[cfg(feature = "test-util")]
+    if let Some(root) = &*OVERRIDE_ROOT.read().unwrap_or_else(|e| e.into_inner()) {
+        return Some(root.join(kind).join(project_path));
+    }
+    None
+}
+
+/// Refuses directories that are too close to the root to be a project directory,
+/// e.g. `/`, `/home` or the home directory itself
+fn guard_dir(dir: &Path) -> io::Result<()> {
+    let suspicious = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("refusing to clear suspicious directory {}", dir.display()),
+        )
+    };
+    // compare resolved paths, so `..` and symlinks cannot sneak past the checks
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let resolved = canonical(dir);
+    if resolved.components().count() <= 2 {
+        Err(suspicious())?;
+    }
+    if let Some(base) = BaseDirs::new() {
+        if canonical(base.home_dir()).starts_with(&resolved) {
+            Err(suspicious())?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the contents of `dir` but not `dir` itself; a missing directory is already clear
+fn clear_dir_contents(dir: &Path) -> io::Result<()> {
+    guard_dir(dir)?;
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => Err(e)?,
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ClearApp;
+    impl AppAuthor for ClearApp {
+        fn app_name() -> &'static str {
+            "sculptor-test-clear"
+        }
+        fn author() -> &'static str {
+            "sculptor"
+        }
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn clear_cache_dir_keeps_dir() {
+        hermetic_root();
+        let dir = ClearApp::cache_dir();
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("nested").join("b.txt"), "b").unwrap();
+        ClearApp::clear_cache_dir().unwrap();
+        let left = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir(&dir).unwrap();
+        assert_eq!(left, 0);
+    }
+
+    #[cfg(all(feature = "config", feature = "test-util"))]
+    #[test]
+    fn config_source_reads_preferences_file() {
+        hermetic_root();
+        let path = ClearApp::preferences_file("config-source.toml");
+        fs::create_dir_all(ClearApp::config_dir()).unwrap();
+        fs::write(&path, "name = \"from-file\"\n").unwrap();
+        let conf = config::Config::builder()
+            .add_source(ClearApp::config_source("config-source.toml"))
+            .add_source(ClearApp::config_source("missing.toml").required(false))
+            .build();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(conf.unwrap().get_string("name").unwrap(), "from-file");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn first_run_only_once() {
+        hermetic_root();
+        struct FirstRunApp;
+        impl AppAuthor for FirstRunApp {
+            fn app_name() -> &'static str {
+                "sculptor-test-first-run"
+            }
+            fn author() -> &'static str {
+                "sculptor"
+            }
+        }
+        let _ = fs::remove_dir_all(FirstRunApp::data_dir());
+        let first = FirstRunApp::is_first_run().unwrap();
+        let second = FirstRunApp::is_first_run().unwrap();
+        let custom = FirstRunApp::is_first_run_with(".onboarded").unwrap();
+        fs::remove_dir_all(FirstRunApp::data_dir()).unwrap();
+        assert!(first);
+        assert!(!second);
+        assert!(custom);
+    }
+
+    #[test]
+    fn project_path_ends_config_dir() {
+        hermetic_root();
+        let dirs = ProjectDirs::from("", "sculptor", "sculptor-test-clear").unwrap();
+        assert_eq!(ClearApp::project_path(), dirs.project_path());
+        if cfg!(target_os = "linux") {
+            assert_eq!(ClearApp::project_path(), Path::new("sculptor-test-clear"));
+            assert!(ClearApp::config_dir().ends_with(ClearApp::project_path()));
+        }
+    }
+
+    #[test]
+    fn describe_reports_inputs_and_paths() {
+        hermetic_root();
+        let report = ClearApp::describe();
+        assert!(report.contains("application: \"sculptor-test-clear\""));
+        let config = report
+            .lines()
+            .find_map(|line| line.strip_prefix("config: "))
+            .unwrap();
+        assert!(!config.is_empty());
+    }
+
+    #[test]
+    fn preferences_under_config_dir() {
+        hermetic_root();
+        let path = ClearApp::preferences_file("prefs.toml");
+        assert_eq!(path.parent(), Some(&*ClearApp::config_dir()));
+        #[cfg(feature = "file_io")]
+        {
+            #[derive(serde::Serialize, serde::Deserialize)]
+            struct Prefs {}
+            impl crate::SerdeStr for Prefs {
+                fn de_from_str(string: &str) -> io::Result<Self> {
+                    Ok(serde_json::from_str(string)?)
+                }
+                fn ser_to_string(&self) -> io::Result<String> {
+                    Ok(serde_json::to_string(self)?)
+                }
+            }
+            assert_eq!(ClearApp::preferences::<Prefs>("prefs.toml").path, path);
+        }
+    }
+
+    #[test]
+    fn state_dir_support_per_platform() {
+        hermetic_root();
+        let supported = !cfg!(any(target_os = "macos", target_os = "windows"));
+        assert_eq!(ClearApp::has_state_dir(), supported);
+        assert_eq!(ClearApp::state_dir().is_some(), supported);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn migrate_from_copies_into_empty_only() {
+        hermetic_root();
+        let old = RuntimeProjectInfo::new("", "sculptor", "sculptor-test-migrate-old").unwrap();
+        let new = RuntimeProjectInfo::new("", "sculptor", "sculptor-test-migrate-new").unwrap();
+        let _ = fs::remove_dir_all(new.config_dir());
+        fs::create_dir_all(old.config_dir().join("nested")).unwrap();
+        fs::write(old.config_dir().join("nested").join("app.toml"), "old").unwrap();
+        let copied = migrate_from(old.project_dirs(), new.project_dirs());
+        let moved = fs::read_to_string(new.config_dir().join("nested").join("app.toml"));
+        fs::write(new.config_dir().join("nested").join("app.toml"), "new").unwrap();
+        let again = migrate_from(old.project_dirs(), new.project_dirs());
+        let kept = fs::read_to_string(new.config_dir().join("nested").join("app.toml"));
+        fs::remove_dir_all(old.config_dir()).unwrap();
+        fs::remove_dir_all(new.config_dir()).unwrap();
+        assert!(copied.unwrap());
+        assert_eq!(moved.unwrap(), "old");
+        assert!(!again.unwrap());
+        assert_eq!(kept.unwrap(), "new");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn init_from_skeleton_keeps_existing() {
+        hermetic_root();
+        struct SkeletonApp;
+        impl AppAuthor for SkeletonApp {
+            fn app_name() -> &'static str {
+                "sculptor-test-skeleton"
+            }
+            fn author() -> &'static str {
+                "sculptor"
+            }
+        }
+        let dir = SkeletonApp::config_dir();
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("app.toml"), "edited").unwrap();
+        let skeleton = [("app.toml", "default"), ("themes/dark.toml", "dark")];
+        let created = SkeletonApp::init_from_skeleton(&skeleton);
+        let kept = fs::read_to_string(dir.join("app.toml"));
+        let theme = fs::read_to_string(dir.join("themes/dark.toml"));
+        let escaping = SkeletonApp::init_from_skeleton(&[("../outside.toml", "")]);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(created.unwrap(), [dir.join("themes/dark.toml")]);
+        assert_eq!(kept.unwrap(), "edited");
+        assert_eq!(theme.unwrap(), "dark");
+        assert_eq!(escaping.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn all_dirs_lists_footprint() {
+        hermetic_root();
+        let dirs = ClearApp::all_dirs();
+        assert_eq!(
+            dirs[..3],
+            [
+                (DirKind::Config, ClearApp::config_dir()),
+                (DirKind::Data, ClearApp::data_dir()),
+                (DirKind::Cache, ClearApp::cache_dir()),
+            ]
+        );
+        let has_state = dirs.iter().any(|(kind, _)| *kind == DirKind::State);
+        assert_eq!(has_state, ClearApp::has_state_dir());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn temp_in_data_dir_unique() {
+        hermetic_root();
+        let (_, first) = ClearApp::temp_in_data_dir("save").unwrap();
+        let (_, second) = ClearApp::temp_in_data_dir("save").unwrap();
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+        assert_eq!(first.parent(), Some(&*ClearApp::data_dir()));
+        assert_eq!(second.parent(), Some(&*ClearApp::data_dir()));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn runtime_project_info() {
+        hermetic_root();
+        let app = format!("sculptor-test-{}", "runtime");
+        let info = RuntimeProjectInfo::new("", "sculptor", &app).unwrap();
+        let dirs = ProjectDirs::from("", "sculptor", "sculptor-test-runtime").unwrap();
+        let expected = redirected("config", dirs.project_path())
+            .unwrap_or_else(|| dirs.config_dir().to_path_buf());
+        assert_eq!(info.config_dir(), expected);
+        assert!(info.config_dir().to_string_lossy().contains(&app));
+    }
+
+    #[test]
+    fn guard_rejects_shallow_dirs() {
+        // only the guard itself, never `clear_dir_contents`, so a broken guard wipes nothing
+        assert!(guard_dir(Path::new("/")).is_err());
+        assert!(guard_dir(Path::new("/home")).is_err());
+        let temp = std::env::temp_dir();
+        let up: PathBuf = temp.components().skip(1).map(|_| "..").collect();
+        assert!(guard_dir(&temp.join(up)).is_err());
+        if let Some(base) = BaseDirs::new() {
+            let home = base.home_dir();
+            assert!(guard_dir(home).is_err());
+            if let Some(name) = home.file_name() {
+                assert!(guard_dir(&home.join("..").join(name)).is_err());
+            }
+        }
+    }
+}
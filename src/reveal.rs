@@ -0,0 +1,99 @@
+use crate::{FileIO, SerdeStr};
+use std::{io, path::Path, process::Command};
+
+/// The platform's file manager invocation for showing `target`
+///
+/// - macOS: `open -R <file>`, which selects the file in Finder
+/// - Windows: `explorer /select,<file>`, which selects the file in Explorer
+/// - elsewhere: `xdg-open <parent>`, which only opens the directory
+///
+/// A file that doesn't exist yet can't be selected, so its parent is opened instead.
+fn reveal_command(target: &Path, parent: &Path) -> Command {
+    let exists = target.exists();
+    if cfg!(target_os = "macos") && exists {
+        let mut cmd = Command::new("open");
+        cmd.arg("-R").arg(target);
+        cmd
+    } else if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("open");
+        cmd.arg(parent);
+        cmd
+    } else if cfg!(windows) && exists {
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(target);
+        let mut cmd = Command::new("explorer");
+        cmd.arg(select);
+        cmd
+    } else if cfg!(windows) {
+        let mut cmd = Command::new("explorer");
+        cmd.arg(parent);
+        cmd
+    } else {
+        let mut cmd = Command::new("xdg-open");
+        cmd.arg(parent);
+        cmd
+    }
+}
+
+impl<T> FileIO<T>
+where
+    T: SerdeStr,
+{
+    /// Shows the file in the OS file manager, selecting it where the platform supports it
+    ///
+    /// Returns as soon as the file manager is launched, without waiting for it; a
+    /// background thread reaps the launcher once it exits, so no zombie is left behind.
+    pub fn reveal(&self) -> io::Result<()> {
+        self.reveal_with(|cmd| {
+            let mut child = cmd.spawn()?;
+            std::thread::spawn(move || child.wait());
+            Ok(())
+        })
+    }
+    /// [`FileIO::reveal`] with a custom `run` for the launcher command
+    pub fn reveal_with(&self, run: impl FnOnce(&mut Command) -> io::Result<()>) -> io::Result<()> {
+        let target = std::path::absolute(&self.path)?;
+        let parent = target.parent().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The path has no parent directory",
+            )
+        })?;
+        run(&mut reveal_command(&target, parent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::path::PathBuf;
+
+    #[derive(Serialize, Deserialize)]
+    struct Conf {
+        pub name: String,
+    }
+    impl SerdeStr for Conf {
+        fn de_from_str(string: &str) -> Result<Self, io::Error> {
+            Ok(serde_json::from_str(string)?)
+        }
+        fn ser_to_string(&self) -> Result<String, io::Error> {
+            Ok(serde_json::to_string(self)?)
+        }
+    }
+
+    #[test]
+    fn reveal_launches_with_parent() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_reveal").join("conf.json"));
+        let parent = std::env::current_dir().unwrap().join("test_reveal");
+        let mut launched = None;
+        file_io
+            .reveal_with(|cmd| {
+                launched = cmd.get_args().last().map(|arg| arg.to_owned());
+                Ok(())
+            })
+            .unwrap();
+        let arg = PathBuf::from(launched.unwrap());
+        assert!(arg.to_string_lossy().contains(&*parent.to_string_lossy()));
+    }
+}
@@ -0,0 +1,460 @@
+use crate::{FileIO, SerdeStr};
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::{fmt, io};
+
+/// A place where a file doesn't match its schema
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaError {
+    /// JSON pointer to the offending value, empty for the document itself
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+impl<T> FileIO<T>
+where
+    T: SerdeStr + JsonSchema,
+{
+    /// The JSON Schema of `T`, e.g. to publish for editor completion
+    pub fn json_schema() -> Value {
+        serde_json::to_value(schemars::schema_for!(T)).expect("schemas are plain JSON")
+    }
+    /// Checks the file against [`FileIO::json_schema`], returning every mismatch found
+    ///
+    /// Supports the subset of JSON Schema that `schemars` generates for plain types:
+    /// `$ref`, `type`, `enum`, `const`, `minimum`/`maximum` and their exclusive forms,
+    /// `minLength`/`maxLength`, `minItems`/`maxItems`, `uniqueItems`, `required`,
+    /// `properties`, `additionalProperties`, `items` (single or per position), the
+    /// `anyOf`/`oneOf`/`allOf` combinators, and the integer `format`s (`int8` to `uint64`,
+    /// `int`, `uint`) as ranges, with `float` and `double` accepted as they are.
+    /// Annotations such as `title` or `default` are ignored. A schema using any other
+    /// keyword or format, like `pattern` or `date-time`, fails with `Unsupported` rather
+    /// than passing files it can't check. Other `io::Error`s mean the file couldn't be
+    /// read or parsed at all.
+    pub fn validate_file(&self) -> io::Result<Vec<SchemaError>> {
+        let schema = Self::json_schema();
+        check_supported(&schema)?;
+        let mut errors = Vec::new();
+        validate(&schema, &schema, &self.load_value()?, "", &mut errors);
+        Ok(errors)
+    }
+}
+
+/// Keywords [`validate`] checks, or ignores as annotations
+const KEYWORDS: &[&str] = &[
+    "$ref",
+    "type",
+    "enum",
+    "const",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "minLength",
+    "maxLength",
+    "minItems",
+    "maxItems",
+    "uniqueItems",
+    "required",
+    "properties",
+    "additionalProperties",
+    "items",
+    "anyOf",
+    "oneOf",
+    "allOf",
+    "format",
+    "definitions",
+    "$schema",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+];
+
+/// The inclusive range of an integer `format` that `schemars` emits
+fn format_range(format: &str) -> Option<(i128, i128)> {
+    Some(match format {
+        "int8" => (i8::MIN.into(), i8::MAX.into()),
+        "int16" => (i16::MIN.into(), i16::MAX.into()),
+        "int32" => (i32::MIN.into(), i32::MAX.into()),
+        "int64" | "int" => (i64::MIN.into(), i64::MAX.into()),
+        "uint8" => (0, u8::MAX.into()),
+        "uint16" => (0, u16::MAX.into()),
+        "uint32" => (0, u32::MAX.into()),
+        "uint64" | "uint" => (0, u64::MAX.into()),
+        _ => None?,
+    })
+}
+
+/// Fails with `Unsupported` if `schema` or any schema within it uses a keyword or format
+/// beyond [`KEYWORDS`]
+fn check_supported(schema: &Value) -> io::Result<()> {
+    let Value::Object(schema) = schema else {
+        return Ok(());
+    };
+    let unsupported = |what: String| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("schema {} is not supported by validate_file", what),
+        )
+    };
+    for (key, value) in schema {
+        match key.as_str() {
+            "properties" | "definitions" => {
+                for child in value.as_object().into_iter().flatten() {
+                    check_supported(child.1)?;
+                }
+            }
+            "items" | "anyOf" | "oneOf" | "allOf" if value.is_array() => {
+                for child in value.as_array().into_iter().flatten() {
+                    check_supported(child)?;
+                }
+            }
+            "items" | "additionalProperties" => check_supported(value)?,
+            "format" => match value.as_str() {
+                Some(format) if format_range(format).is_some() => {}
+                Some("float" | "double") => {}
+                _ => Err(unsupported(format!("format {}", value)))?,
+            },
+            key if KEYWORDS.contains(&key) => {}
+            key => Err(unsupported(format!("keyword `{}`", key)))?,
+        }
+    }
+    Ok(())
+}
+
+fn type_matches(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => true,
+    }
+}
+
+fn error(path: &str, message: impl Into<String>) -> SchemaError {
+    SchemaError {
+        path: path.to_owned(),
+        message: message.into(),
+    }
+}
+
+/// Collects where `value` breaks `schema`; `root` is the document `$ref`s point into
+fn validate(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+    errors: &mut Vec<SchemaError>,
+) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => return errors.push(error(path, "no value is allowed here")),
+        Value::Object(schema) => schema,
+        _ => return,
+    };
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match root.pointer(reference.trim_start_matches('#')) {
+            Some(target) => validate(root, target, value, path, errors),
+            None => errors.push(error(
+                path,
+                format!("unresolvable reference `{}`", reference),
+            )),
+        }
+    }
+    if let Some(ty) = schema.get("type") {
+        let names: Vec<&str> = match ty {
+            Value::String(name) => vec![name],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !names.is_empty() && !names.iter().any(|name| type_matches(value, name)) {
+            return errors.push(error(
+                path,
+                format!("expected {}, found {}", names.join(" or "), value),
+            ));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            errors.push(error(
+                path,
+                format!("{} is not one of the allowed values", value),
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            errors.push(error(
+                path,
+                format!("expected {}, found {}", expected, value),
+            ));
+        }
+    }
+    let range = schema
+        .get("format")
+        .and_then(Value::as_str)
+        .and_then(format_range);
+    if let Some((min, max)) = range {
+        let n = (value.as_i64().map(i128::from)).or(value.as_u64().map(i128::from));
+        if n.is_some_and(|n| n < min || n > max) {
+            errors.push(error(
+                path,
+                format!("{} is out of range for {}", value, schema["format"]),
+            ));
+        }
+    }
+    if let Some(n) = value.as_f64() {
+        let bound = |key: &str| schema.get(key).and_then(Value::as_f64);
+        if bound("minimum").is_some_and(|min| n < min) {
+            errors.push(error(
+                path,
+                format!("{} is below the minimum of {}", value, schema["minimum"]),
+            ));
+        }
+        if bound("maximum").is_some_and(|max| n > max) {
+            errors.push(error(
+                path,
+                format!("{} is above the maximum of {}", value, schema["maximum"]),
+            ));
+        }
+        if bound("exclusiveMinimum").is_some_and(|min| n <= min) {
+            let min = &schema["exclusiveMinimum"];
+            errors.push(error(path, format!("{} is not above {}", value, min)));
+        }
+        if bound("exclusiveMaximum").is_some_and(|max| n >= max) {
+            let max = &schema["exclusiveMaximum"];
+            errors.push(error(path, format!("{} is not below {}", value, max)));
+        }
+    }
+    let limit = |key: &str| schema.get(key).and_then(Value::as_u64);
+    let mut check_len = |len: usize, what: &str, min: &str, max: &str| {
+        if limit(min).is_some_and(|min| (len as u64) < min) {
+            let message = format!("has fewer than {} {}", schema[min], what);
+            errors.push(error(path, message));
+        }
+        if limit(max).is_some_and(|max| (len as u64) > max) {
+            let message = format!("has more than {} {}", schema[max], what);
+            errors.push(error(path, message));
+        }
+    };
+    match value {
+        Value::String(s) => check_len(s.chars().count(), "characters", "minLength", "maxLength"),
+        Value::Array(items) => check_len(items.len(), "items", "minItems", "maxItems"),
+        _ => {}
+    }
+    if let (Value::Array(items), Some(Value::Bool(true))) = (value, schema.get("uniqueItems")) {
+        if (1..items.len()).any(|i| items[..i].contains(&items[i])) {
+            errors.push(error(path, "has duplicate items"));
+        }
+    }
+    if let Value::Object(map) = value {
+        for required in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(key) = required.as_str().filter(|key| !map.contains_key(*key)) {
+                errors.push(error(path, format!("missing required field `{}`", key)));
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, child) in map {
+            let child_path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => validate(root, property, child, &child_path, errors),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        errors.push(error(&child_path, format!("unknown field `{}`", key)))
+                    }
+                    Some(additional) => validate(root, additional, child, &child_path, errors),
+                    None => {}
+                },
+            }
+        }
+    }
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            // an array of schemas checks items by position, as `schemars` emits for tuples
+            let item_schema = match item_schema {
+                Value::Array(schemas) => match schemas.get(i) {
+                    Some(item_schema) => item_schema,
+                    None => continue,
+                },
+                item_schema => item_schema,
+            };
+            validate(root, item_schema, item, &format!("{}/{}", path, i), errors);
+        }
+    }
+    let branches = |key: &str| {
+        schema
+            .get(key)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+    };
+    for branch in branches("allOf") {
+        validate(root, branch, value, path, errors);
+    }
+    let matching = |key: &str| {
+        branches(key)
+            .filter(|branch| {
+                let mut branch_errors = Vec::new();
+                validate(root, branch, value, path, &mut branch_errors);
+                branch_errors.is_empty()
+            })
+            .count()
+    };
+    if schema.contains_key("anyOf") && matching("anyOf") == 0 {
+        errors.push(error(path, "matches none of the allowed shapes"));
+    }
+    if schema.contains_key("oneOf") && matching("oneOf") != 1 {
+        errors.push(error(
+            path,
+            "doesn't match exactly one of the allowed shapes",
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::{fs, path::PathBuf};
+
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct Conf {
+        name: String,
+        server: Server,
+        tags: Option<Vec<String>>,
+    }
+    impl SerdeStr for Conf {
+        fn de_from_str(string: &str) -> io::Result<Self> {
+            Ok(serde_json::from_str(string)?)
+        }
+        fn ser_to_string(&self) -> io::Result<String> {
+            Ok(serde_json::to_string(self)?)
+        }
+    }
+
+    #[test]
+    fn validate_file_reports_missing_field() {
+        let file_io = FileIO::<Conf>::new(PathBuf::from("test_schema_validate_file.json"));
+        fs::write(
+            &file_io.path,
+            r#"{"name":"app","server":{"host":"localhost","port":8080},"tags":["a"]}"#,
+        )
+        .unwrap();
+        let valid = file_io.validate_file();
+        fs::write(
+            &file_io.path,
+            r#"{"name":"app","server":{"host":"localhost"},"tags":[1]}"#,
+        )
+        .unwrap();
+        let invalid = file_io.validate_file();
+        fs::remove_file(&file_io.path).unwrap();
+        assert_eq!(valid.unwrap(), []);
+        let messages: Vec<String> = invalid.unwrap().iter().map(ToString::to_string).collect();
+        assert_eq!(messages[0], "/server: missing required field `port`");
+        assert!(
+            messages.iter().any(|m| m.starts_with("/tags")),
+            "{:?}",
+            messages
+        );
+    }
+
+    #[test]
+    fn validate_file_checks_ranges_and_lengths() {
+        #[derive(Serialize, Deserialize, JsonSchema)]
+        struct Named {
+            #[schemars(length(min = 1))]
+            name: String,
+            #[schemars(length(max = 2))]
+            tags: std::collections::BTreeSet<String>,
+        }
+        impl SerdeStr for Named {
+            fn de_from_str(string: &str) -> io::Result<Self> {
+                Ok(serde_json::from_str(string)?)
+            }
+            fn ser_to_string(&self) -> io::Result<String> {
+                Ok(serde_json::to_string(self)?)
+            }
+        }
+        let conf = FileIO::<Conf>::new(PathBuf::from("test_schema_ranges.json"));
+        fs::write(
+            &conf.path,
+            r#"{"name":"app","server":{"host":"localhost","port":70000}}"#,
+        )
+        .unwrap();
+        let port = conf.validate_file();
+        let named = FileIO::<Named>::new(conf.path.clone());
+        fs::write(&named.path, r#"{"name":"","tags":["a","a","b"]}"#).unwrap();
+        let lengths = named.validate_file();
+        fs::remove_file(&conf.path).unwrap();
+        let messages = |errors: Vec<SchemaError>| -> Vec<String> {
+            errors.iter().map(ToString::to_string).collect()
+        };
+        assert_eq!(
+            messages(port.unwrap()),
+            ["/server/port: 70000 is out of range for \"uint16\""]
+        );
+        assert_eq!(
+            messages(lengths.unwrap()),
+            [
+                "/name: has fewer than 1 characters",
+                "/tags: has more than 2 items",
+                "/tags: has duplicate items",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_file_rejects_unsupported_keywords() {
+        #[derive(Serialize, Deserialize, JsonSchema)]
+        struct Patterned {
+            #[schemars(regex(pattern = r"^[a-z]+$"))]
+            name: String,
+        }
+        impl SerdeStr for Patterned {
+            fn de_from_str(string: &str) -> io::Result<Self> {
+                Ok(serde_json::from_str(string)?)
+            }
+            fn ser_to_string(&self) -> io::Result<String> {
+                Ok(serde_json::to_string(self)?)
+            }
+        }
+        let file_io = FileIO::<Patterned>::new(PathBuf::from("test_schema_unsupported.json"));
+        fs::write(&file_io.path, r#"{"name":"APP"}"#).unwrap();
+        let checked = file_io.validate_file();
+        fs::remove_file(&file_io.path).unwrap();
+        let err = checked.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(err.to_string().contains("`pattern`"), "{}", err);
+    }
+}
@@ -1,12 +1,477 @@
-use sha2::{Digest, Sha512};
+use alloc::{format, string::String, vec::Vec};
+use core::fmt;
+use sha2::{Digest as _, Sha512};
+
+mod rolling;
+pub use self::rolling::*;
+
+/// File, reader and directory helpers, which need `std`
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+pub use self::stream::*;
+
+/// A raw SHA-512 digest; 64 bytes instead of a 128-char hex `String` as a map key
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Digest(pub [u8; 64]);
+
+impl Digest {
+    pub fn as_bytes(&self) -> &[u8; 64] {
+        &self.0
+    }
+    /// Same lowercase hex as [`ShaSnap::snap`]
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        Some(Self(decode_hex(hex)?.try_into().ok()?))
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({})", self.to_hex())
+    }
+}
+
+/// The multicodec code of SHA2-512, as used by [`ShaSnap::snap_multihash`]
+#[cfg(feature = "multihash")]
+pub const MULTIHASH_SHA2_512: u64 = 0x13;
+
+/// Common purposes of a digest, each hashed under its own tag by [`ShaSnap::snap_domain`]
+/// so that digests made for one purpose never collide with another's
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SnapDomain {
+    CacheKey,
+    Etag,
+    ContentId,
+}
+
+impl SnapDomain {
+    /// The tag framed before the content; stable across releases, as digests depend on it
+    pub fn tag(self) -> &'static [u8] {
+        match self {
+            Self::CacheKey => b"sculptor.cache-key",
+            Self::Etag => b"sculptor.etag",
+            Self::ContentId => b"sculptor.content-id",
+        }
+    }
+}
 
 pub trait ShaSnap: AsRef<[u8]> {
     fn snap(&self) -> String {
         let mut hasher = Sha512::new();
-        hasher.update(&self);
+        hasher.update(self);
         let result = hasher.finalize();
         format!("{:x}", result)
     }
+    /// Digest of the content framed after `domain`'s tag, like [`frame`]
+    fn snap_domain(&self, domain: SnapDomain) -> String {
+        let mut hasher = FramedHasher::new();
+        hasher.update(domain.tag());
+        hasher.update(self);
+        hasher.finalize()
+    }
+    /// The raw SHA-512 output, without allocating; hex-encode only when needed
+    fn snap_array(&self) -> [u8; 64] {
+        Sha512::digest(self).into()
+    }
+    fn snap_digest(&self) -> Digest {
+        Digest(self.snap_array())
+    }
+    /// The digest as a multihash: varint code `0x13`, varint length 64, then the digest
+    #[cfg(feature = "multihash")]
+    fn snap_multihash(&self) -> Vec<u8> {
+        let digest = Sha512::digest(self);
+        multihash::Multihash::<64>::wrap(MULTIHASH_SHA2_512, &digest)
+            .expect("a SHA-512 digest fits 64 bytes")
+            .to_bytes()
+    }
+    /// [`ShaSnap::snap_multihash`] in lowercase hex
+    #[cfg(feature = "multihash")]
+    fn snap_multihash_hex(&self) -> String {
+        self.snap_multihash()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+    /// [`ShaSnap::snap_multihash`] in base58 (Bitcoin alphabet), as in IPFS CIDv0
+    #[cfg(feature = "multihash")]
+    fn snap_multihash_base58(&self) -> String {
+        bs58::encode(self.snap_multihash()).into_string()
+    }
+    /// Hex SHAKE256 output of `out_len` bytes, for protocols needing digests of other widths
+    ///
+    /// Not SHA-512: this never matches [`ShaSnap::snap`], and a shorter output is a prefix
+    /// of a longer one over the same content.
+    #[cfg(feature = "shake")]
+    fn snap_xof(&self, out_len: usize) -> String {
+        use sha3::digest::{ExtendableOutput, Update, XofReader};
+        let mut hasher = sha3::Shake256::default();
+        hasher.update(self.as_ref());
+        let mut out = alloc::vec![0; out_len];
+        hasher.finalize_xof().read(&mut out);
+        out.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    /// Keyed digest: hex HMAC-SHA512 of the content under `key`
+    #[cfg(feature = "hmac")]
+    fn snap_hmac(&self, key: &[u8]) -> String {
+        use hmac::Mac;
+        let mut mac =
+            hmac::Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(self.as_ref());
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+    /// Checks a hex HMAC produced by [`ShaSnap::snap_hmac`] in constant time
+    #[cfg(feature = "hmac")]
+    fn verify_hmac(&self, key: &[u8], expected: &str) -> bool {
+        use hmac::Mac;
+        let Some(expected) = decode_hex(expected) else {
+            return false;
+        };
+        let mut mac =
+            hmac::Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(self.as_ref());
+        mac.verify_slice(&expected).is_ok()
+    }
 }
 
 impl<T: AsRef<[u8]>> ShaSnap for T {}
+
+/// Concatenates `parts` unambiguously, each prefixed by its length as a little-endian `u64`
+///
+/// This is the crate's framing convention: `[b"ab", b"c"]` and `[b"a", b"bc"]` frame differently.
+pub fn frame(parts: &[&[u8]]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(parts.iter().map(|p| p.len() + 8).sum());
+    for part in parts {
+        framed.extend_from_slice(&(part.len() as u64).to_le_bytes());
+        framed.extend_from_slice(part);
+    }
+    framed
+}
+
+/// Same as `data.snap()`, for call sites hashing a region of a larger buffer
+pub fn snap_slice(data: &[u8]) -> String {
+    data.snap()
+}
+
+/// Digest of the buffers concatenated, without concatenating them; unlike [`frame`],
+/// `[b"ab", b"c"]` and `[b"a", b"bc"]` hash the same
+pub fn snap_iovec(bufs: &[&[u8]]) -> String {
+    let mut hasher = Sha512::new();
+    for buf in bufs {
+        hasher.update(buf);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// A weak HTTP `ETag` header value for `bytes`: the first 16 hex chars of their
+/// [`SnapDomain::Etag`] digest, quoted, as in `W/"0123456789abcdef"`
+pub fn etag(bytes: &[u8]) -> String {
+    format!("W/\"{}\"", &bytes.snap_domain(SnapDomain::Etag)[..16])
+}
+
+/// Whether an `If-None-Match` header value matches the [`etag`] of `bytes`, by weak
+/// comparison: `*` matches anything, and the `W/` prefix of each listed tag is ignored
+pub fn matches_etag(bytes: &[u8], header_value: &str) -> bool {
+    let header_value = header_value.trim();
+    if header_value == "*" {
+        return true;
+    }
+    let own = etag(bytes);
+    let own = own.trim_start_matches("W/");
+    header_value
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag.strip_prefix("W/").unwrap_or(tag) == own)
+}
+
+/// Digest of an archive's logical contents, whatever order its reader yields the entries in
+///
+/// Entries are sorted by name, then data for duplicate names, and each framed as name and
+/// data, so timestamps and other archive metadata never affect the digest.
+pub fn snap_archive_entries<I: IntoIterator<Item = (String, Vec<u8>)>>(entries: I) -> String {
+    let mut entries: Vec<_> = entries.into_iter().collect();
+    entries.sort_unstable();
+    let mut hasher = FramedHasher::new();
+    for (name, data) in &entries {
+        hasher.update(name);
+        hasher.update(data);
+    }
+    hasher.finalize()
+}
+
+/// A SHA-512 hasher that frames every update like [`frame`], without building the buffer
+#[derive(Clone, Default)]
+pub struct FramedHasher {
+    hasher: Sha512,
+}
+
+impl FramedHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn update(&mut self, part: impl AsRef<[u8]>) {
+        let part = part.as_ref();
+        self.hasher.update((part.len() as u64).to_le_bytes());
+        self.hasher.update(part);
+    }
+    pub fn finalize(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+/// The completed-chunk state of a [`ResumableSnap`], enough to continue hashing later
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapCheckpoint {
+    pub chunk_size: u64,
+    pub chunks: Vec<Digest>,
+}
+
+impl SnapCheckpoint {
+    /// How many input bytes the checkpoint covers; resume feeding input from here
+    pub fn offset(&self) -> u64 {
+        self.chunk_size * self.chunks.len() as u64
+    }
+}
+
+/// Hashing that can be checkpointed and resumed across processes, e.g. for uploads
+///
+/// SHA-512's internal state can't be exported, so the input is hashed in fixed-size
+/// chunks and the digest is SHA-512 over the chunk size and the chunk digests. This is
+/// *not* [`ShaSnap::snap`] of the input, and differs between chunk sizes. A checkpoint
+/// only holds completed chunks, so resuming re-hashes at most one chunk.
+#[derive(Clone)]
+pub struct ResumableSnap {
+    chunk_size: u64,
+    chunks: Vec<Digest>,
+    current: Sha512,
+    in_chunk: u64,
+}
+
+impl ResumableSnap {
+    pub fn new(chunk_size: u64) -> Self {
+        Self::resume(SnapCheckpoint {
+            chunk_size,
+            chunks: Vec::new(),
+        })
+    }
+    pub fn resume(checkpoint: SnapCheckpoint) -> Self {
+        Self {
+            chunk_size: checkpoint.chunk_size.max(1),
+            chunks: checkpoint.chunks,
+            current: Sha512::new(),
+            in_chunk: 0,
+        }
+    }
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let room = (self.chunk_size - self.in_chunk).min(data.len() as u64) as usize;
+            self.current.update(&data[..room]);
+            self.in_chunk += room as u64;
+            data = &data[room..];
+            if self.in_chunk == self.chunk_size {
+                let chunk = core::mem::take(&mut self.current).finalize();
+                self.chunks.push(Digest(chunk.into()));
+                self.in_chunk = 0;
+            }
+        }
+    }
+    /// The state up to the last completed chunk; bytes after its offset must be fed again
+    pub fn checkpoint(&self) -> SnapCheckpoint {
+        SnapCheckpoint {
+            chunk_size: self.chunk_size,
+            chunks: self.chunks.clone(),
+        }
+    }
+    pub fn finalize(mut self) -> String {
+        if self.in_chunk > 0 || self.chunks.is_empty() {
+            self.chunks.push(Digest(self.current.finalize().into()));
+        }
+        let mut hasher = Sha512::new();
+        hasher.update(self.chunk_size.to_le_bytes());
+        for chunk in &self.chunks {
+            hasher.update(chunk.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Only uses the core API, so `cargo test --no-default-features --features sha_snap`
+    /// runs it against the `no_std` build
+    #[test]
+    fn snap_core_known_vector() {
+        let expected = "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                        2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f";
+        assert_eq!(b"abc".snap(), expected);
+        assert_eq!(b"abc".snap_digest().to_hex(), expected);
+    }
+
+    #[test]
+    fn snap_array_hex_is_snap() {
+        let data = b"hot path";
+        let hex: String = data
+            .snap_array()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_eq!(hex, data.snap());
+    }
+
+    #[test]
+    fn snap_domains_separate() {
+        let content = b"same bytes";
+        let cache_key = content.snap_domain(SnapDomain::CacheKey);
+        assert_ne!(cache_key, content.snap_domain(SnapDomain::Etag));
+        assert_ne!(cache_key, content.snap_domain(SnapDomain::ContentId));
+        assert_ne!(cache_key, content.snap());
+        let framed = frame(&[b"sculptor.cache-key", content]);
+        assert_eq!(cache_key, framed.snap());
+    }
+
+    #[test]
+    fn etag_round_trip() {
+        let tag = etag(b"body");
+        assert_eq!(tag, etag(b"body"));
+        assert_ne!(tag, etag(b"other"));
+        assert!(tag.starts_with("W/\"") && tag.ends_with('"'));
+        assert_eq!(tag.len(), 2 + 16 + 2);
+        assert!(matches_etag(b"body", &tag));
+        let strong = tag.trim_start_matches("W/");
+        assert!(matches_etag(b"body", &format!("\"stale\", {}", strong)));
+        assert!(matches_etag(b"body", "*"));
+        assert!(!matches_etag(b"other", &tag));
+        assert!(!matches_etag(b"body", ""));
+    }
+
+    #[test]
+    fn snap_archive_entries_sorts() {
+        let entry = |name: &str, data: &[u8]| (String::from(name), data.to_vec());
+        let forward = snap_archive_entries([entry("a.txt", b"one"), entry("b/c.txt", b"two")]);
+        let reversed = snap_archive_entries([entry("b/c.txt", b"two"), entry("a.txt", b"one")]);
+        let renamed = snap_archive_entries([entry("a.txt", b"two"), entry("b/c.txt", b"one")]);
+        assert_eq!(forward, reversed);
+        assert_ne!(forward, renamed);
+    }
+
+    #[cfg(feature = "shake")]
+    #[test]
+    fn snap_xof_extends_prefix() {
+        let short = b"content".snap_xof(16);
+        let long = b"content".snap_xof(100);
+        assert_eq!(short.len(), 32);
+        assert_eq!(long.len(), 200);
+        assert!(long.starts_with(&short));
+        assert_ne!(short, b"other".snap_xof(16));
+        assert_eq!(b"".snap_xof(0), "");
+        assert_eq!(
+            b"".snap_xof(32),
+            "46b9dd2b0ba88d13233b3feb743eeb243fcd52ea62b81b82b50c27646ed5762f"
+        );
+    }
+
+    #[test]
+    fn snap_iovec_is_concatenation() {
+        let (head, body) = (&b"header:"[..], &b"payload"[..]);
+        assert_eq!(snap_iovec(&[head, body]), b"header:payload".snap());
+        assert_eq!(snap_iovec(&[]), b"".snap());
+    }
+
+    #[test]
+    fn resumable_snap_resumes() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let mut single = ResumableSnap::new(1024);
+        single.update(&data);
+        let single = single.finalize();
+
+        let mut interrupted = ResumableSnap::new(1024);
+        interrupted.update(&data[..3500]);
+        let checkpoint = interrupted.checkpoint();
+        drop(interrupted);
+        assert_eq!(checkpoint.offset(), 3072);
+        let mut resumed = ResumableSnap::resume(checkpoint);
+        for piece in data[3072..].chunks(700) {
+            resumed.update(piece);
+        }
+        assert_eq!(resumed.finalize(), single);
+
+        let mut other_size = ResumableSnap::new(2048);
+        other_size.update(&data);
+        assert_ne!(other_size.finalize(), single);
+    }
+
+    #[test]
+    fn framing_is_unambiguous() {
+        let ab_c = frame(&[b"ab", b"c"]);
+        let a_bc = frame(&[b"a", b"bc"]);
+        assert_ne!(ab_c, a_bc);
+        let mut hasher = FramedHasher::new();
+        hasher.update(b"ab");
+        hasher.update(b"c");
+        assert_eq!(hasher.finalize(), ab_c.snap());
+    }
+
+    #[test]
+    fn digest_as_map_key() {
+        use std::collections::HashMap;
+        let mut seen = HashMap::new();
+        seen.insert("same".snap_digest(), "a");
+        seen.insert("same".snap_digest(), "b");
+        seen.insert("other".snap_digest(), "c");
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[&"same".snap_digest()], "b");
+        let digest = "same".snap_digest();
+        assert_eq!(digest.to_hex(), "same".snap());
+        assert_eq!(Digest::from_hex(&digest.to_hex()), Some(digest));
+        assert_eq!(Digest::from_hex("abcd"), None);
+    }
+
+    #[cfg(feature = "multihash")]
+    #[test]
+    fn multihash_round_trips() {
+        let bytes = "content".snap_multihash();
+        assert_eq!(bytes[..2], [0x13, 0x40]);
+        let decoded = multihash::Multihash::<64>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.code(), MULTIHASH_SHA2_512);
+        assert_eq!(decoded.digest(), "content".snap_digest().as_bytes());
+        assert_eq!(
+            "content".snap_multihash_hex(),
+            format!("1340{}", "content".snap())
+        );
+        let base58 = bs58::decode("content".snap_multihash_base58()).into_vec();
+        assert_eq!(base58.unwrap(), bytes);
+    }
+
+    #[cfg(feature = "hmac")]
+    #[test]
+    fn hmac_rfc4231_case_2() {
+        let expected = "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+                        9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737";
+        let data = "what do ya want for nothing?";
+        assert_eq!(data.snap_hmac(b"Jefe"), expected);
+        assert!(data.verify_hmac(b"Jefe", expected));
+        assert!(!data.verify_hmac(b"jefe", expected));
+        assert!(!data.verify_hmac(b"Jefe", "not hex"));
+    }
+}
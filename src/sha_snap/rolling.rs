@@ -0,0 +1,142 @@
+/// Byte count of the window [`chunk_boundaries`] hashes
+pub const CHUNK_WINDOW: usize = 48;
+
+/// Pseudo-random values per byte, from splitmix64 so the table is fixed forever
+const BUZ_TABLE: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// A buzhash over a sliding window of bytes, for content-defined chunking
+///
+/// Not cryptographic, unlike [`ShaSnap::snap`](super::ShaSnap::snap): it only needs to
+/// be cheap to slide and to depend on nothing but the window's bytes. The caller keeps
+/// the window, feeding the first bytes through [`RollingHasher::push`] and then every
+/// next byte along with the one leaving the window through [`RollingHasher::roll`].
+#[derive(Clone, Debug)]
+pub struct RollingHasher {
+    hash: u64,
+    window: usize,
+}
+
+impl RollingHasher {
+    pub fn new(window: usize) -> Self {
+        Self { hash: 0, window }
+    }
+    /// Adds a byte while the window is still filling
+    pub fn push(&mut self, byte_in: u8) -> u64 {
+        self.hash = self.hash.rotate_left(1) ^ BUZ_TABLE[byte_in as usize];
+        self.hash
+    }
+    /// Slides the window by one byte: `byte_out` is the byte `window` positions back
+    pub fn roll(&mut self, byte_in: u8, byte_out: u8) -> u64 {
+        let out = BUZ_TABLE[byte_out as usize].rotate_left((self.window % 64) as u32);
+        self.hash = self.hash.rotate_left(1) ^ out ^ BUZ_TABLE[byte_in as usize];
+        self.hash
+    }
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// Offsets at which to cut `reader` into content-defined chunks, e.g. for dedup
+///
+/// A cut follows every byte where the hash of the last [`CHUNK_WINDOW`] bytes has no bit
+/// of `mask` set, so chunks average `mask + 1` bytes for a mask of low bits, and the same
+/// content is cut the same way wherever it sits in the stream. Neither `0` nor the end of
+/// the stream is listed, and no minimum or maximum chunk size is enforced.
+#[cfg(feature = "std")]
+pub fn chunk_boundaries<R: std::io::Read>(mut reader: R, mask: u64) -> std::io::Result<Vec<usize>> {
+    use std::io;
+    let mut hasher = RollingHasher::new(CHUNK_WINDOW);
+    let mut window = [0; CHUNK_WINDOW];
+    let mut buf = vec![0; 64 * 1024];
+    let mut pos = 0;
+    let mut cuts = Vec::new();
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e)?,
+        };
+        for &byte in &buf[..n] {
+            let slot = pos % CHUNK_WINDOW;
+            let hash = if pos < CHUNK_WINDOW {
+                hasher.push(byte)
+            } else {
+                hasher.roll(byte, window[slot])
+            };
+            window[slot] = byte;
+            pos += 1;
+            if pos >= CHUNK_WINDOW && hash & mask == 0 {
+                cuts.push(pos);
+            }
+        }
+    }
+    if cuts.last() == Some(&pos) {
+        cuts.pop();
+    }
+    Ok(cuts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolled_hash_depends_on_window_only() {
+        let data: Vec<u8> = (0..200u32).map(|i| (i * 7 + 3) as u8).collect();
+        let mut rolled = RollingHasher::new(CHUNK_WINDOW);
+        for (i, &byte) in data.iter().enumerate() {
+            match i.checked_sub(CHUNK_WINDOW) {
+                Some(out) => rolled.roll(byte, data[out]),
+                None => rolled.push(byte),
+            };
+        }
+        let mut fresh = RollingHasher::new(CHUNK_WINDOW);
+        for &byte in &data[data.len() - CHUNK_WINDOW..] {
+            fresh.push(byte);
+        }
+        assert_eq!(rolled.hash(), fresh.hash());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn chunk_boundaries_ignore_surroundings() {
+        let mut state = 1u32;
+        let content: Vec<u8> = (0..64 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let cuts_after = |prefix: &[u8]| {
+            let stream = [prefix, &content, b"trailer"].concat();
+            let cuts = chunk_boundaries(&stream[..], 0xff).unwrap();
+            // cuts within the content's first window still see the prefix
+            cuts.into_iter()
+                .filter(|&cut| {
+                    cut >= prefix.len() + CHUNK_WINDOW && cut <= prefix.len() + content.len()
+                })
+                .map(|cut| cut - prefix.len())
+                .collect::<Vec<_>>()
+        };
+        let short = cuts_after(b"x");
+        let long = cuts_after(&[0xaa; 1000]);
+        assert!(short.len() > 100);
+        assert_eq!(short, long);
+    }
+}
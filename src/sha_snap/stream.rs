@@ -0,0 +1,1515 @@
+#[cfg(any(feature = "serde_snap", feature = "cbor"))]
+use super::ShaSnap;
+use super::{frame, snap_slice, Digest, FramedHasher};
+use sha2::{Digest as _, Sha512};
+use std::{
+    fs,
+    io::{self, BufRead, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Digest of several `(start, end)` byte ranges of `data`, in order and framed like
+/// [`frame`], e.g. the index sections of a memory-mapped archive
+///
+/// Fails with `InvalidInput` if a span is reversed or reaches past the end of `data`.
+pub fn snap_spans(data: &[u8], spans: &[(usize, usize)]) -> io::Result<String> {
+    let mut hasher = FramedHasher::new();
+    for &(start, end) in spans {
+        let span = data.get(start..end).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "span {}..{} out of bounds for {} bytes",
+                    start,
+                    end,
+                    data.len()
+                ),
+            )
+        })?;
+        hasher.update(span);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Read buffer size used by [`snap_reader`]
+///
+/// Untuned: 64 KiB is a conventional default, not a measured optimum. On a one-core
+/// shared VM `benches/snap.rs` put 8 KiB and 64 KiB within noise of each other
+/// (roughly 320–360 MiB/s), so run `cargo bench --features sha_snap` on the target
+/// machine before relying on any particular size.
+pub const SNAP_BUFFER_CAPACITY: usize = 64 * 1024;
+
+/// Streams a reader through SHA-512 without holding all of it in memory
+pub fn snap_reader<R: Read>(reader: R) -> io::Result<String> {
+    snap_reader_with_capacity(reader, SNAP_BUFFER_CAPACITY)
+}
+
+/// [`snap_reader`] with an explicit read buffer size
+pub fn snap_reader_with_capacity<R: Read>(mut reader: R, capacity: usize) -> io::Result<String> {
+    let mut hasher = Sha512::new();
+    let mut buf = vec![0; capacity.max(1)];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => Err(e)?,
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// How much of the start of a stream [`snap_reader_typed`] sniffs the content type from
+#[cfg(feature = "infer")]
+const SNIFF_LEN: usize = 8 * 1024;
+
+/// [`snap_reader`] that also guesses the MIME type, such as `image/png`, from the magic
+/// bytes at the start of the stream, in the same pass; `None` if nothing matches
+#[cfg(feature = "infer")]
+pub fn snap_reader_typed<R: Read>(mut reader: R) -> io::Result<(String, Option<String>)> {
+    let mut head = vec![0; SNIFF_LEN];
+    let n = read_full(&mut reader, &mut head)?;
+    head.truncate(n);
+    let mime = infer::get(&head).map(|kind| kind.mime_type().to_owned());
+    let digest = snap_reader(io::Cursor::new(head).chain(reader))?;
+    Ok((digest, mime))
+}
+
+/// Streams a file through [`snap_reader`]
+pub fn snap_file(path: &Path) -> io::Result<String> {
+    snap_reader(fs::File::open(path)?)
+}
+
+/// [`snap_file`] for a file or the [`build_merkle`] root digest for a directory, so callers
+/// needn't branch on what `path` is
+///
+/// Symlinks are followed, here and inside directories, so a link hashes as its target;
+/// a dangling one fails with `NotFound`, and one leading back to an enclosing directory
+/// with `InvalidData`.
+pub fn snap_path(path: &Path) -> io::Result<String> {
+    if fs::metadata(path)?.is_dir() {
+        Ok(build_merkle(path)?.digest.to_hex())
+    } else {
+        snap_file(path)
+    }
+}
+
+/// The digest of each line of `reader` in one streaming pass, e.g. to dedup NDJSON records
+///
+/// Lines are split on `\n` and hashed without it, or a `\r\n` ending; they needn't be UTF-8.
+pub fn snap_lines<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<String>> {
+    reader.split(b'\n').map(|line| {
+        let line = line?;
+        Ok(snap_slice(line.strip_suffix(b"\r").unwrap_or(&line)))
+    })
+}
+
+/// Streams the file at `path` and checks its digest against the hex `expected` in
+/// constant time; a malformed `expected` never matches, and `NotFound` propagates
+pub fn verify_file(path: &Path, expected: &str) -> io::Result<bool> {
+    let actual = digest_reader(fs::File::open(path)?)?;
+    let Some(expected) = Digest::from_hex(expected.trim()) else {
+        return Ok(false);
+    };
+    // accumulates every difference, so the timing doesn't reveal the first one
+    let diff = (actual.0.iter().zip(expected.0)).fold(0, |acc, (a, b)| acc | (a ^ b));
+    Ok(diff == 0)
+}
+
+/// Checks `data` against a digest naming its algorithm, such as `sha256:<hex>`, in
+/// constant time; `sha256`, `sha384` and `sha512` are known
+///
+/// An unknown algorithm fails with `Unsupported` and a digest without a `:` prefix with
+/// `InvalidInput`, while malformed hex just never matches.
+pub fn verify_prefixed(data: &[u8], digest: &str) -> io::Result<bool> {
+    let (algorithm, expected) = digest.trim().split_once(':').ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("digest `{}` has no `<algorithm>:` prefix", digest),
+        )
+    })?;
+    let actual = match algorithm.to_ascii_lowercase().as_str() {
+        "sha256" => format!("{:x}", sha2::Sha256::digest(data)),
+        "sha384" => format!("{:x}", sha2::Sha384::digest(data)),
+        "sha512" => format!("{:x}", Sha512::digest(data)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("unknown digest algorithm `{}`", algorithm),
+        ))?,
+    };
+    let expected = expected.to_ascii_lowercase();
+    if expected.len() != actual.len() {
+        return Ok(false);
+    }
+    // accumulates every difference, so the timing doesn't reveal the first one
+    let diff = (actual.bytes().zip(expected.bytes())).fold(0, |acc, (a, b)| acc | (a ^ b));
+    Ok(diff == 0)
+}
+
+/// Hashes files concurrently, keeping the input order; one file failing leaves the
+/// others unaffected
+#[cfg(feature = "rayon")]
+pub fn snap_files_parallel(paths: &[PathBuf]) -> Vec<io::Result<(PathBuf, String)>> {
+    use rayon::prelude::*;
+    paths
+        .par_iter()
+        .map(|path| Ok((path.clone(), snap_file(path)?)))
+        .collect()
+}
+
+/// Fills `buf` as far as the reader allows, short only at end of input
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => Err(e)?,
+        }
+    }
+    Ok(filled)
+}
+
+/// Compares two streams byte by byte, stopping at the first difference
+///
+/// Cheaper than comparing [`snap_reader`] digests when inputs tend to differ early.
+pub fn readers_equal<R: Read, S: Read>(mut a: R, mut b: S) -> io::Result<bool> {
+    let mut buf_a = vec![0; SNAP_BUFFER_CAPACITY];
+    let mut buf_b = vec![0; SNAP_BUFFER_CAPACITY];
+    loop {
+        let n = read_full(&mut a, &mut buf_a)?;
+        let m = read_full(&mut b, &mut buf_b)?;
+        if buf_a[..n] != buf_b[..m] {
+            return Ok(false);
+        }
+        if n < buf_a.len() {
+            return Ok(true);
+        }
+    }
+}
+
+/// Forwards writes to `inner` while hashing exactly the bytes it accepted
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha512,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha512::new(),
+        }
+    }
+    /// The digest of everything written so far
+    pub fn finalize(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+    /// The digest along with the inner writer
+    pub fn into_parts(self) -> (W, String) {
+        (self.inner, format!("{:x}", self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hashes chunks as they arrive and returns the digest once every sender is dropped
+pub fn snap_channel(rx: std::sync::mpsc::Receiver<Vec<u8>>) -> String {
+    let mut hasher = Sha512::new();
+    for chunk in rx {
+        hasher.update(chunk);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Hashes an async stream of chunks, such as an HTTP body of `Bytes`, as they arrive;
+/// the first error from the stream aborts hashing
+#[cfg(feature = "tokio")]
+pub async fn snap_bytes_stream<S, B, E>(stream: S) -> io::Result<String>
+where
+    S: futures_core::Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut hasher = Sha512::new();
+    while let Some(chunk) = std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+        hasher.update(chunk.map_err(io::Error::other)?);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn digest_reader<R: Read>(mut reader: R) -> io::Result<Digest> {
+    let mut hasher = Sha512::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(Digest(hasher.finalize().into()))
+}
+
+/// A node of a directory's hash tree: a file's content digest, or a directory's
+/// digest over its entries' names and digests
+///
+/// Entries are ordered by file name and symlinks are followed, except one leading back to
+/// an enclosing directory, which is unreadable rather than an endless tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleNode {
+    pub path: PathBuf,
+    pub digest: Digest,
+    /// Empty for files
+    pub children: Vec<MerkleNode>,
+    pub is_dir: bool,
+}
+
+/// What directory hashing does with an entry it cannot read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WalkPolicy {
+    /// Abort with the first error
+    FailFast,
+    /// Leave the entry out of the tree, log a warning and report its path
+    SkipUnreadable,
+}
+
+/// Hashes every file below `root` into a [`MerkleNode`] tree
+pub fn build_merkle(root: &Path) -> io::Result<MerkleNode> {
+    Ok(build_merkle_with(root, WalkPolicy::FailFast)?.0)
+}
+
+/// [`build_merkle`] under an explicit [`WalkPolicy`], also returning the skipped paths
+///
+/// The root itself must be readable under either policy.
+pub fn build_merkle_with(
+    root: &Path,
+    policy: WalkPolicy,
+) -> io::Result<(MerkleNode, Vec<PathBuf>)> {
+    let mut skipped = Vec::new();
+    let node = merkle_node(root, policy, &mut skipped, &mut Vec::new(), &mut |_| {})?;
+    Ok((node, skipped))
+}
+
+/// [`build_merkle`] calling `on_file(path, index, total)` after each file is hashed, e.g.
+/// to drive a progress bar over a large tree
+///
+/// A first pass counts the files to know `total`; `index` counts from 1, so the last call
+/// has `index == total` unless the tree changes between the two passes.
+pub fn build_merkle_progress<F>(root: &Path, mut on_file: F) -> io::Result<MerkleNode>
+where
+    F: FnMut(&Path, usize, usize),
+{
+    let total = count_files(root, &mut Vec::new())?;
+    let mut index = 0;
+    let mut skipped = Vec::new();
+    let mut ancestors = Vec::new();
+    merkle_node(
+        root,
+        WalkPolicy::FailFast,
+        &mut skipped,
+        &mut ancestors,
+        &mut |path| {
+            index += 1;
+            on_file(path, index, total);
+        },
+    )
+}
+
+/// Groups the files below `root` by content, returning each set of two or more files
+/// with the same digest, paths and groups in file name order
+///
+/// Files are hashed in parallel when the `rayon` feature is on. Symlinks are followed,
+/// and entries that can't be read or lead back up the tree are skipped as under
+/// [`WalkPolicy::SkipUnreadable`].
+pub fn find_duplicates(root: &Path) -> io::Result<Vec<Vec<PathBuf>>> {
+    let mut files = Vec::new();
+    collect_files(root, &mut Vec::new(), &mut files)?;
+    #[cfg(feature = "rayon")]
+    let snaps = (snap_files_parallel(&files).into_iter()).map(|snap| snap.map(|(_, d)| d));
+    #[cfg(not(feature = "rayon"))]
+    let snaps = files.iter().map(|path| snap_file(path));
+    let mut groups: std::collections::HashMap<String, Vec<PathBuf>> = Default::default();
+    for (path, snap) in files.iter().zip(snaps) {
+        match snap {
+            Ok(digest) => groups.entry(digest).or_default().push(path.clone()),
+            Err(e) => log::warn!("skipping unreadable {}: {}", path.display(), e),
+        }
+    }
+    let mut duplicates: Vec<_> = groups.into_values().filter(|g| g.len() > 1).collect();
+    duplicates.sort();
+    Ok(duplicates)
+}
+
+/// Pushes the files below `path` in file name order, skipping unreadable subdirectories
+/// and symlink loops
+fn collect_files(
+    path: &Path,
+    ancestors: &mut Vec<PathBuf>,
+    out: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    if !fs::metadata(path)?.is_dir() {
+        out.push(path.to_path_buf());
+        return Ok(());
+    }
+    let canonical = enter_dir(path, ancestors)?;
+    let mut paths = fs::read_dir(path)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+    ancestors.push(canonical);
+    for child in paths {
+        if let Err(e) = collect_files(&child, ancestors, out) {
+            log::warn!("skipping unreadable {}: {}", child.display(), e);
+        }
+    }
+    ancestors.pop();
+    Ok(())
+}
+
+fn count_files(path: &Path, ancestors: &mut Vec<PathBuf>) -> io::Result<usize> {
+    if !fs::metadata(path)?.is_dir() {
+        return Ok(1);
+    }
+    ancestors.push(enter_dir(path, ancestors)?);
+    let mut count = 0;
+    for entry in fs::read_dir(path)? {
+        count += count_files(&entry?.path(), ancestors)?;
+    }
+    ancestors.pop();
+    Ok(count)
+}
+
+/// The canonical path of the directory `path`, failing with `InvalidData` if it is one of
+/// the `ancestors` being walked, i.e. a symlink leads back up the tree
+fn enter_dir(path: &Path, ancestors: &[PathBuf]) -> io::Result<PathBuf> {
+    let canonical = fs::canonicalize(path)?;
+    if ancestors.contains(&canonical) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("symlink loop at {}", path.display()),
+        ));
+    }
+    Ok(canonical)
+}
+
+fn merkle_node(
+    path: &Path,
+    policy: WalkPolicy,
+    skipped: &mut Vec<PathBuf>,
+    ancestors: &mut Vec<PathBuf>,
+    on_file: &mut dyn FnMut(&Path),
+) -> io::Result<MerkleNode> {
+    if !fs::metadata(path)?.is_dir() {
+        let digest = digest_reader(fs::File::open(path)?)?;
+        on_file(path);
+        return Ok(MerkleNode {
+            path: path.to_path_buf(),
+            digest,
+            children: Vec::new(),
+            is_dir: false,
+        });
+    }
+    let canonical = enter_dir(path, ancestors)?;
+    let mut paths = fs::read_dir(path)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+    ancestors.push(canonical);
+    let mut children = Vec::with_capacity(paths.len());
+    for child in paths {
+        match merkle_node(&child, policy, skipped, ancestors, on_file) {
+            Ok(node) => children.push(node),
+            Err(e) if policy == WalkPolicy::SkipUnreadable => {
+                log::warn!("skipping unreadable {}: {}", child.display(), e);
+                skipped.push(child);
+            }
+            Err(e) => Err(e)?,
+        }
+    }
+    ancestors.pop();
+    let mut node = MerkleNode {
+        path: path.to_path_buf(),
+        digest: Digest([0; 64]),
+        children,
+        is_dir: true,
+    };
+    node.combine();
+    Ok(node)
+}
+
+impl MerkleNode {
+    fn combine(&mut self) {
+        let mut hasher = Sha512::new();
+        for child in &self.children {
+            let name = child
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .as_encoded_bytes();
+            hasher.update(frame(&[
+                name,
+                &[child.is_dir as u8],
+                child.digest.as_bytes(),
+            ]));
+        }
+        self.digest = Digest(hasher.finalize().into());
+    }
+    /// Recomputes only the nodes between `self` and `changed`, which may have been
+    /// modified, created or removed since the tree was built
+    pub fn rehash(&mut self, changed: &Path) -> io::Result<()> {
+        self.rehash_with(changed, WalkPolicy::FailFast).map(|_| ())
+    }
+    /// [`MerkleNode::rehash`] for a tree from [`build_merkle_with`], re-reading entries
+    /// under the same `policy` and returning the paths skipped along the way
+    pub fn rehash_with(&mut self, changed: &Path, policy: WalkPolicy) -> io::Result<Vec<PathBuf>> {
+        let mut skipped = Vec::new();
+        self.rehash_into(changed, policy, &mut skipped)?;
+        Ok(skipped)
+    }
+    fn rehash_into(
+        &mut self,
+        changed: &Path,
+        policy: WalkPolicy,
+        skipped: &mut Vec<PathBuf>,
+    ) -> io::Result<()> {
+        let rebuild = |node: &mut MerkleNode, skipped: &mut Vec<PathBuf>| {
+            let (rebuilt, rebuilt_skipped) = build_merkle_with(&node.path, policy)?;
+            *node = rebuilt;
+            skipped.extend(rebuilt_skipped);
+            Ok(())
+        };
+        if !self.is_dir {
+            return rebuild(self, skipped);
+        }
+        let child = self
+            .children
+            .iter_mut()
+            .find(|child| changed.starts_with(&child.path));
+        let result = match child {
+            Some(child) if child.path.exists() => child.rehash_into(changed, policy, skipped),
+            // an entry appeared or vanished, so this directory's listing is stale
+            _ => return rebuild(self, skipped),
+        };
+        match result {
+            // the entry turned unreadable, so let the listing leave it out
+            Err(_) if policy == WalkPolicy::SkipUnreadable => return rebuild(self, skipped),
+            result => result?,
+        }
+        self.combine();
+        Ok(())
+    }
+    /// Files whose content differs between the two trees, including added and removed
+    /// ones, as paths relative to the roots so that trees at different places compare
+    pub fn diff(&self, other: &MerkleNode) -> Vec<PathBuf> {
+        let DirDiff {
+            added,
+            removed,
+            changed,
+        } = self.compare(other);
+        let mut paths: Vec<_> = added.into_iter().chain(removed).chain(changed).collect();
+        paths.sort();
+        paths
+    }
+    /// [`MerkleNode::diff`] split by what happened to each file, going from `self` to `other`
+    pub fn compare(&self, other: &MerkleNode) -> DirDiff {
+        let mut diff = DirDiff::default();
+        diff_nodes(Path::new(""), Some(self), Some(other), &mut diff);
+        diff
+    }
+    fn child(&self, name: &std::ffi::OsStr) -> Option<&MerkleNode> {
+        (self.children.iter()).find(|child| child.path.file_name() == Some(name))
+    }
+    fn files(&self, relative: &Path, out: &mut Vec<PathBuf>) {
+        let mut leaves = Vec::new();
+        self.leaves(relative, &mut leaves);
+        out.extend(leaves.into_iter().map(|(path, _)| path));
+    }
+    fn leaves<'a>(&'a self, relative: &Path, out: &mut Vec<(PathBuf, &'a MerkleNode)>) {
+        if self.is_dir {
+            for child in &self.children {
+                child.leaves(
+                    &relative.join(child.path.file_name().unwrap_or_default()),
+                    out,
+                );
+            }
+        } else {
+            out.push((relative.to_path_buf(), self));
+        }
+    }
+}
+
+/// The files that differ between two directory trees, as sorted paths relative to the roots
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirDiff {
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    /// Present in both with different content
+    pub changed: Vec<PathBuf>,
+}
+
+/// Compares the trees below `a` and `b`, see [`MerkleNode::compare`]
+pub fn diff_dirs(a: &Path, b: &Path) -> io::Result<DirDiff> {
+    Ok(build_merkle(a)?.compare(&build_merkle(b)?))
+}
+
+fn diff_nodes(
+    relative: &Path,
+    old: Option<&MerkleNode>,
+    new: Option<&MerkleNode>,
+    out: &mut DirDiff,
+) {
+    match (old, new) {
+        (Some(old), Some(new)) if old.digest == new.digest && old.is_dir == new.is_dir => {}
+        (Some(old), Some(new)) if old.is_dir && new.is_dir => {
+            let mut names: Vec<_> = (old.children.iter().chain(&new.children))
+                .filter_map(|child| child.path.file_name())
+                .collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                let (old_child, new_child) = (old.child(name), new.child(name));
+                diff_nodes(&relative.join(name), old_child, new_child, out);
+            }
+        }
+        (Some(old), Some(new)) if !old.is_dir && !new.is_dir => {
+            out.changed.push(relative.to_path_buf())
+        }
+        // one side is missing, or a file became a directory or vice versa
+        (old, new) => {
+            if let Some(old) = old {
+                old.files(relative, &mut out.removed);
+            }
+            if let Some(new) = new {
+                new.files(relative, &mut out.added);
+            }
+        }
+    }
+}
+
+/// An entry of a manifest that no longer matches the tree, see [`verify_manifest`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Changed(PathBuf),
+    Missing(PathBuf),
+}
+
+/// Writes a `sha512sum`-compatible manifest of every file below `root` to `out`: one
+/// `<hex>  <relative path>` line per file, with `/` separators, ordered by path
+///
+/// `out` is best kept outside `root`, as it would otherwise list its own stale content.
+/// Names that aren't UTF-8 or contain a newline or backslash, which `sha512sum` would
+/// escape, are rejected with `InvalidData`.
+pub fn write_manifest(root: &Path, out: &Path) -> io::Result<()> {
+    let tree = build_merkle(root)?;
+    let mut leaves = Vec::new();
+    tree.leaves(Path::new(""), &mut leaves);
+    let mut manifest = String::new();
+    for (relative, node) in leaves {
+        let mut line = String::new();
+        for component in relative.components() {
+            let name = component.as_os_str().to_str();
+            let name = name.filter(|name| !name.contains(['\n', '\\']));
+            let name = name.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unrepresentable name in manifest: {}", relative.display()),
+                )
+            })?;
+            if !line.is_empty() {
+                line.push('/');
+            }
+            line += name;
+        }
+        manifest += &format!("{}  {}\n", node.digest, line);
+    }
+    fs::write(out, manifest)
+}
+
+/// Checks every entry of a manifest written by [`write_manifest`] against the files below
+/// `root`; files present in the tree but not in the manifest are not reported
+pub fn verify_manifest(root: &Path, manifest: &Path) -> io::Result<Vec<Mismatch>> {
+    let mut mismatches = Vec::new();
+    for line in fs::read_to_string(manifest)?.lines() {
+        // `sha512sum` marks files hashed in binary mode with `*` instead of a space
+        let entry = line
+            .split_once("  ")
+            .or_else(|| line.split_once(" *"))
+            .and_then(|(hex, relative)| Some((Digest::from_hex(hex)?, relative)));
+        let (expected, relative) = entry.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed manifest line: {}", line),
+            )
+        })?;
+        let relative = PathBuf::from(relative);
+        match fs::File::open(root.join(&relative)) {
+            Ok(file) => {
+                if digest_reader(file)? != expected {
+                    mismatches.push(Mismatch::Changed(relative));
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                mismatches.push(Mismatch::Missing(relative))
+            }
+            Err(e) => Err(e)?,
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Digest of `value` serialized as compact JSON
+///
+/// Floats are written in their shortest round-tripping form (`0.1`, `1e100`) by `ryu`
+/// with one fixed exponent syntax, so the digest depends neither on the platform nor on
+/// the serde_json release's float formatting.
+/// Non-finite floats (NaN, ±infinity) have no JSON form and hash as `null`, the same
+/// as `None`; reject them beforehand if that distinction matters.
+#[cfg(feature = "serde_snap")]
+pub fn snap_serialized<T: serde::Serialize>(value: &T) -> io::Result<String> {
+    Ok(canonical_json(value)?.snap())
+}
+
+/// Compact JSON as `serde_json` writes it, except for floats, which are pinned to `ryu`'s
+/// shortest representation; see [`snap_serialized`]
+#[cfg(feature = "serde_snap")]
+fn canonical_json<T: serde::Serialize + ?Sized>(value: &T) -> io::Result<Vec<u8>> {
+    struct RyuFloats;
+    impl serde_json::ser::Formatter for RyuFloats {
+        fn write_f32<W: ?Sized + io::Write>(
+            &mut self,
+            writer: &mut W,
+            value: f32,
+        ) -> io::Result<()> {
+            writer.write_all(ryu::Buffer::new().format_finite(value).as_bytes())
+        }
+        fn write_f64<W: ?Sized + io::Write>(
+            &mut self,
+            writer: &mut W,
+            value: f64,
+        ) -> io::Result<()> {
+            writer.write_all(ryu::Buffer::new().format_finite(value).as_bytes())
+        }
+    }
+    let mut bytes = Vec::new();
+    value.serialize(&mut serde_json::Serializer::with_formatter(
+        &mut bytes, RyuFloats,
+    ))?;
+    Ok(bytes)
+}
+
+/// Digest of a sequence of records, each serialized like [`snap_serialized`] and framed
+/// like [`frame`], so it depends on both their content and their order
+#[cfg(feature = "serde_snap")]
+pub fn snap_records<T, I>(items: I) -> io::Result<String>
+where
+    T: serde::Serialize,
+    I: IntoIterator<Item = T>,
+{
+    let mut hasher = FramedHasher::new();
+    for item in items {
+        hasher.update(canonical_json(&item)?);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Digest of `value` in the JSON Canonicalization Scheme (RFC 8785), so that digests
+/// and signatures agree with other languages' JCS implementations
+///
+/// Object keys are sorted by UTF-16 code units and strings and numbers formatted as
+/// ECMAScript's `JSON.stringify` does. Limits: numbers are IEEE doubles as in JCS, so
+/// integers beyond ±2^53 lose precision, and non-finite floats hash as `null` like in
+/// [`snap_serialized`] where JCS would reject them.
+#[cfg(feature = "serde_snap")]
+pub fn snap_jcs<T: serde::Serialize>(value: &T) -> io::Result<String> {
+    Ok(jcs(&serde_json::to_value(value)?).snap())
+}
+
+#[cfg(feature = "serde_snap")]
+fn jcs(value: &serde_json::Value) -> String {
+    use serde_json::Value;
+    use std::fmt::Write as _;
+    fn string(s: &str, out: &mut String) {
+        out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\u{8}' => out.push_str("\\b"),
+                '\u{c}' => out.push_str("\\f"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    /// ECMAScript's `Number.prototype.toString`, from Rust's shortest round-trip digits
+    fn number(n: f64, out: &mut String) {
+        if n == 0.0 {
+            return out.push('0');
+        }
+        if n < 0.0 {
+            out.push('-');
+        }
+        let sci = format!("{:e}", n.abs());
+        let (mantissa, exp) = sci.split_once('e').expect("`{:e}` has an exponent");
+        let digits = mantissa.replace('.', "");
+        let (k, n) = (digits.len() as i32, exp.parse::<i32>().unwrap() + 1);
+        if k <= n && n <= 21 {
+            out.push_str(&digits);
+            out.extend(std::iter::repeat_n('0', (n - k) as usize));
+        } else if 0 < n && n <= 21 {
+            let (int, frac) = digits.split_at(n as usize);
+            write!(out, "{}.{}", int, frac).unwrap();
+        } else if -6 < n && n <= 0 {
+            out.push_str("0.");
+            out.extend(std::iter::repeat_n('0', -n as usize));
+            out.push_str(&digits);
+        } else {
+            let (first, rest) = digits.split_at(1);
+            let sign = if n > 0 { '+' } else { '-' };
+            out.push_str(first);
+            if !rest.is_empty() {
+                write!(out, ".{}", rest).unwrap();
+            }
+            write!(out, "e{}{}", sign, (n - 1).abs()).unwrap();
+        }
+    }
+    fn write(value: &Value, out: &mut String) {
+        match value {
+            Value::Null => out.push_str("null"),
+            Value::Bool(b) => write!(out, "{}", b).unwrap(),
+            Value::Number(n) => number(n.as_f64().expect("JSON numbers fit a double"), out),
+            Value::String(s) => string(s, out),
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write(item, out);
+                }
+                out.push(']');
+            }
+            Value::Object(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by_cached_key(|(key, _)| key.encode_utf16().collect::<Vec<_>>());
+                out.push('{');
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    string(key, out);
+                    out.push(':');
+                    write(value, out);
+                }
+                out.push('}');
+            }
+        }
+    }
+    let mut out = String::new();
+    write(value, &mut out);
+    out
+}
+
+/// Digest of `value` in canonical CBOR (RFC 8949 §4.2.1): definite lengths, shortest
+/// encodings and map keys sorted by their encoded bytes, so field order doesn't matter
+/// and other languages' canonical CBOR encoders agree on the digest
+#[cfg(feature = "cbor")]
+pub fn snap_canonical<T: serde::Serialize>(value: &T) -> io::Result<String> {
+    Ok(canonical_bytes(value)?.snap())
+}
+
+/// The canonical CBOR [`snap_canonical`] hashes, for signing with one's own signer
+///
+/// CBOR items are self-delimiting, so the encoding is prefix-free: no encoded value is
+/// a prefix of another, and a signature over it can't be reinterpreted.
+#[cfg(feature = "cbor")]
+pub fn canonical_bytes<T: serde::Serialize>(value: &T) -> io::Result<Vec<u8>> {
+    use ciborium::Value;
+    fn encode(value: &Value) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).map_err(io::Error::other)?;
+        Ok(bytes)
+    }
+    fn canonicalize(value: &mut Value) -> io::Result<()> {
+        match value {
+            Value::Array(items) => items.iter_mut().try_for_each(canonicalize)?,
+            Value::Tag(_, inner) => canonicalize(inner)?,
+            Value::Map(entries) => {
+                let mut keyed = Vec::with_capacity(entries.len());
+                for (mut k, mut v) in std::mem::take(entries) {
+                    canonicalize(&mut k)?;
+                    canonicalize(&mut v)?;
+                    keyed.push((encode(&k)?, k, v));
+                }
+                keyed.sort_by(|a, b| a.0.cmp(&b.0));
+                *entries = keyed.into_iter().map(|(_, k, v)| (k, v)).collect();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    let mut value =
+        Value::serialized(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    canonicalize(&mut value)?;
+    encode(&value)
+}
+
+/// A cache key over `config_bytes` and the current values of the env vars `env_keys`
+///
+/// The vars are framed as sorted `(key, value)` pairs, so their order in `env_keys` doesn't
+/// matter, and an unset var is framed distinctly from an empty one. Vars not named are
+/// ignored.
+pub fn fingerprint(config_bytes: &[u8], env_keys: &[&str]) -> String {
+    let mut keys = env_keys.to_vec();
+    keys.sort_unstable();
+    keys.dedup();
+    let mut hasher = FramedHasher::new();
+    hasher.update(config_bytes);
+    for key in keys {
+        hasher.update(key);
+        match std::env::var_os(key) {
+            Some(value) => {
+                hasher.update([1]);
+                hasher.update(value.as_encoded_bytes());
+            }
+            None => hasher.update([0]),
+        }
+    }
+    hasher.finalize()
+}
+
+/// Lays out a digest as nested shards under `base`, e.g. `ab/cd/<rest>` for 2 levels
+///
+/// Sharding stops early if the digest is too short to leave a non-empty file name,
+/// and `levels = 0` is just `base/<digest>`.
+pub fn sharded_path(digest: &str, base: &Path, levels: usize) -> PathBuf {
+    let mut path = base.to_path_buf();
+    let mut rest = digest;
+    for _ in 0..levels {
+        match (rest.get(..2), rest.get(2..)) {
+            (Some(shard), Some(tail)) if !tail.is_empty() => {
+                path.push(shard);
+                rest = tail;
+            }
+            _ => break,
+        }
+    }
+    path.push(rest);
+    path
+}
+
+/// Writes `data` into the content-addressed `store` at its [`sharded_path`] (2 levels),
+/// unless a blob with that digest is already there, and returns the digest
+///
+/// The blob is written to a temp file beside its final path and renamed into place, so
+/// readers never see it half-written, even with concurrent writers of the same blob.
+pub fn store_blob(store: &Path, data: &[u8]) -> io::Result<String> {
+    let digest = snap_slice(data);
+    let path = sharded_path(&digest, store, 2);
+    if path.exists() {
+        return Ok(digest);
+    }
+    let dir = path.parent().expect("sharded paths are below the store");
+    fs::create_dir_all(dir)?;
+    let (mut file, temp) = crate::temp::create_unique(dir, &format!(".{}", &digest[4..]))?;
+    let written = file.write_all(data);
+    drop(file);
+    let res = written.and_then(|()| fs::rename(&temp, &path));
+    if res.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    res.map(|()| digest)
+}
+
+/// Reads back a blob written by [`store_blob`], checking it still matches `digest`
+///
+/// A `digest` that isn't a hex SHA-512 one fails with `InvalidInput`, so it can't name
+/// paths outside the store; a corrupted blob fails with `InvalidData`.
+pub fn load_blob(store: &Path, digest: &str) -> io::Result<Vec<u8>> {
+    let digest = Digest::from_hex(digest).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("`{}` is not a SHA-512 digest", digest),
+        )
+    })?;
+    let data = fs::read(sharded_path(&digest.to_hex(), store, 2))?;
+    if Sha512::digest(&data)[..] != digest.0 {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("blob {} is corrupted", digest),
+        ))?;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha_snap::ShaSnap;
+
+    #[test]
+    fn sharded_path_two_levels() {
+        let digest = "abcdef0123".snap();
+        let path = sharded_path(&digest, Path::new("store"), 2);
+        let expected = Path::new("store")
+            .join(&digest[..2])
+            .join(&digest[2..4])
+            .join(&digest[4..]);
+        assert_eq!(path, expected);
+    }
+
+    #[test]
+    fn store_blob_is_idempotent() {
+        let store = PathBuf::from("test_sha_snap_store_blob");
+        let _ = fs::remove_dir_all(&store);
+        let digest = store_blob(&store, b"blob").unwrap();
+        let path = sharded_path(&digest, &store, 2);
+        let written = fs::metadata(&path).unwrap().modified().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let again = store_blob(&store, b"blob").unwrap();
+        let rewritten = fs::metadata(&path).unwrap().modified().unwrap();
+        let loaded = load_blob(&store, &digest);
+        let traversal = load_blob(&store, "../../etc/passwd");
+        fs::write(&path, b"tampered").unwrap();
+        let corrupted = load_blob(&store, &digest);
+        fs::remove_dir_all(&store).unwrap();
+        assert_eq!(digest, again);
+        assert_eq!(written, rewritten);
+        assert_eq!(loaded.unwrap(), b"blob");
+        assert_eq!(traversal.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(corrupted.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn sharded_path_edge_cases() {
+        let base = Path::new("store");
+        assert_eq!(sharded_path("abcd", base, 0), base.join("abcd"));
+        assert_eq!(sharded_path("abc", base, 5), base.join("ab").join("c"));
+        assert_eq!(sharded_path("ab", base, 1), base.join("ab"));
+    }
+
+    #[test]
+    fn fingerprint_tracks_named_vars() {
+        let (tracked, unrelated) = ("SCULPTOR_TEST_FP_TRACKED", "SCULPTOR_TEST_FP_UNRELATED");
+        let keys = [tracked];
+        std::env::remove_var(tracked);
+        let unset = fingerprint(b"conf", &keys);
+        std::env::set_var(tracked, "");
+        let empty = fingerprint(b"conf", &keys);
+        std::env::set_var(tracked, "release");
+        let release = fingerprint(b"conf", &keys);
+        std::env::set_var(unrelated, "noise");
+        let with_noise = fingerprint(b"conf", &keys);
+        std::env::set_var(tracked, "debug");
+        let debug = fingerprint(b"conf", &keys);
+        std::env::remove_var(tracked);
+        std::env::remove_var(unrelated);
+        assert_ne!(unset, empty);
+        assert_ne!(empty, release);
+        assert_eq!(release, with_noise);
+        assert_ne!(release, debug);
+        assert_ne!(unset, fingerprint(b"other", &keys));
+    }
+
+    #[test]
+    fn snap_reader_matches_snap() {
+        let data = vec![7u8; 100_000];
+        for capacity in [0, 1, 4096, SNAP_BUFFER_CAPACITY] {
+            let digest = snap_reader_with_capacity(&data[..], capacity).unwrap();
+            assert_eq!(digest, data.snap());
+        }
+        assert_eq!(snap_reader(&data[..]).unwrap(), data.snap());
+    }
+
+    #[test]
+    fn snap_lines_per_line() {
+        let digests: Vec<String> = snap_lines(&b"{\"a\":1}\n{\"b\":2}\r\n{\"c\":3}"[..])
+            .collect::<io::Result<_>>()
+            .unwrap();
+        let expected = [br#"{"a":1}"#, br#"{"b":2}"#, br#"{"c":3}"#].map(|line| line.snap());
+        assert_eq!(digests, expected);
+    }
+
+    #[test]
+    fn verify_file_detects_tampering() {
+        let path = PathBuf::from("test_sha_snap_verify_file.bin");
+        fs::write(&path, b"release").unwrap();
+        let expected = b"release".snap();
+        let matching = verify_file(&path, &expected.to_uppercase());
+        fs::write(&path, b"re1ease").unwrap();
+        let tampered = verify_file(&path, &expected);
+        fs::remove_file(&path).unwrap();
+        assert!(matching.unwrap());
+        assert!(!tampered.unwrap());
+        let missing = verify_file(&path, &expected);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(feature = "infer")]
+    #[test]
+    fn snap_reader_typed_sniffs_png() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.resize(3 * SNIFF_LEN, 7);
+        let (digest, mime) = snap_reader_typed(&png[..]).unwrap();
+        assert_eq!(digest, png.snap());
+        assert_eq!(mime.as_deref(), Some("image/png"));
+        let (_, unknown) = snap_reader_typed(&b"plain"[..]).unwrap();
+        assert_eq!(unknown, None);
+    }
+
+    #[test]
+    fn verify_prefixed_picks_algorithm() {
+        let sha256 = "sha256:2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824";
+        let sha512 = format!("sha512:{}", "hello".snap());
+        assert!(verify_prefixed(b"hello", sha256).unwrap());
+        assert!(verify_prefixed(b"hello", &sha512).unwrap());
+        assert!(!verify_prefixed(b"hullo", sha256).unwrap());
+        assert!(!verify_prefixed(b"hello", "sha256:2cf24d").unwrap());
+        let unknown = verify_prefixed(b"hello", "md5:5d41402abc4b2a76b9719d911017c592");
+        assert_eq!(unknown.unwrap_err().kind(), io::ErrorKind::Unsupported);
+        let bare = verify_prefixed(b"hello", &"hello".snap());
+        assert_eq!(bare.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn snap_channel_matches_snap() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let sender = std::thread::spawn(move || {
+            for chunk in ["ab", "", "cde", "f"] {
+                tx.send(chunk.as_bytes().to_vec()).unwrap();
+            }
+        });
+        let digest = snap_channel(rx);
+        sender.join().unwrap();
+        assert_eq!(digest, "abcdef".snap());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn snap_bytes_stream_matches_snap() {
+        use bytes::Bytes;
+        use std::{
+            collections::VecDeque,
+            pin::Pin,
+            task::{Context, Poll},
+        };
+        struct Chunks(VecDeque<Result<Bytes, io::Error>>);
+        impl futures_core::Stream for Chunks {
+            type Item = Result<Bytes, io::Error>;
+            fn poll_next(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+            ) -> Poll<Option<Self::Item>> {
+                Poll::Ready(self.0.pop_front())
+            }
+        }
+        let chunks = ["ab", "", "cde", "f"].map(|chunk| Ok(Bytes::from_static(chunk.as_bytes())));
+        let digest = snap_bytes_stream(Chunks(chunks.into())).await;
+        let failing = Chunks([Ok(Bytes::new()), Err(io::Error::other("reset"))].into());
+        assert_eq!(digest.unwrap(), "abcdef".snap());
+        assert_eq!(
+            snap_bytes_stream(failing).await.unwrap_err().to_string(),
+            "reset"
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn snap_files_parallel_keeps_order() {
+        let dir = PathBuf::from("test_sha_snap_parallel");
+        fs::create_dir_all(&dir).unwrap();
+        let mut paths: Vec<PathBuf> = (0..8).map(|i| dir.join(format!("{}.bin", i))).collect();
+        for (i, path) in paths.iter().enumerate() {
+            fs::write(path, vec![i as u8; 1000 * i]).unwrap();
+        }
+        paths.insert(3, dir.join("missing.bin"));
+        let results = snap_files_parallel(&paths);
+        let serial: Vec<_> = paths.iter().map(|path| snap_file(path).ok()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(results.len(), paths.len());
+        for ((result, path), serial) in results.into_iter().zip(&paths).zip(serial) {
+            match result {
+                Ok((hashed, digest)) => {
+                    assert_eq!(&hashed, path);
+                    assert_eq!(Some(digest), serial);
+                }
+                Err(_) => assert!(serial.is_none()),
+            }
+        }
+    }
+
+    #[test]
+    fn readers_equal_short_circuits() {
+        struct Endless;
+        impl Read for Endless {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                buf.fill(1);
+                Ok(buf.len())
+            }
+        }
+        let data = vec![1u8; SNAP_BUFFER_CAPACITY * 2 + 5];
+        assert!(readers_equal(&data[..], &data[..]).unwrap());
+        assert!(!readers_equal(&data[..], &data[..data.len() - 1]).unwrap());
+        assert!(readers_equal(&b""[..], &b""[..]).unwrap());
+        // would never finish without stopping at the first differing chunk
+        assert!(!readers_equal(&[0u8][..], Endless).unwrap());
+    }
+
+    #[test]
+    fn snap_spans_frames_ranges() {
+        let data = b"header|index-a|body|index-b";
+        let digest = snap_spans(data, &[(7, 14), (20, 27)]).unwrap();
+        assert_eq!(digest, frame(&[b"index-a", b"index-b"]).snap());
+        assert_eq!(digest, snap_spans(data, &[(7, 14), (20, 27)]).unwrap());
+        assert_eq!(snap_slice(&data[7..14]), b"index-a".snap());
+        let out_of_bounds = snap_spans(data, &[(20, 28)]).unwrap_err();
+        assert_eq!(out_of_bounds.kind(), io::ErrorKind::InvalidInput);
+        assert!(snap_spans(data, &[(5, 4)]).is_err());
+    }
+
+    #[test]
+    fn hashing_writer_tees() {
+        let data = vec![3u8; 100_000];
+        let mut writer = HashingWriter::new(Vec::new());
+        io::copy(&mut &data[..], &mut writer).unwrap();
+        let (copied, digest) = writer.into_parts();
+        assert_eq!(copied, data);
+        assert_eq!(digest, data.snap());
+    }
+
+    #[cfg(feature = "serde_snap")]
+    #[test]
+    fn snap_serialized_float_policy() {
+        let floats = [0.1, 1.0 / 3.0, 1e100, -0.0];
+        assert_eq!(
+            snap_serialized(&floats).unwrap(),
+            "[0.1,0.3333333333333333,1e100,-0.0]".snap()
+        );
+        // pinned, so a change in float formatting can't slip through unnoticed
+        let pinned = "c1ebd2811266c9afe176520c16094502f67e0187c0d64e95807d4ba6311cd2db\
+                      6336ca6f90d8b2bd53a60ae00a916ea5354305a671ce834f1a474ee6c0b7b805";
+        assert_eq!(snap_serialized(&floats).unwrap(), pinned);
+        assert_eq!(snap_serialized(&f64::NAN).unwrap(), "null".snap());
+        assert_eq!(snap_serialized(&f64::INFINITY).unwrap(), "null".snap());
+    }
+
+    #[cfg(feature = "serde_snap")]
+    #[test]
+    fn snap_jcs_matches_rfc_8785() {
+        // the example of RFC 8785 §3.2.2
+        #[allow(clippy::excessive_precision)]
+        let value = serde_json::json!({
+            "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+            "string": "\u{20ac}$\u{f}\nA'B\"\\\\\"/",
+            "literals": [null, true, false],
+        });
+        let canonical = r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#;
+        assert_eq!(jcs(&value), canonical);
+        assert_eq!(snap_jcs(&value).unwrap(), canonical.snap());
+        // keys sort by UTF-16 code units, where U+1F600 (a surrogate pair) precedes U+FB33
+        let keys =
+            serde_json::json!({"\u{fb33}": 1, "\u{1f600}": 2, "1": 100, "10": 1e21, "": 1e-7});
+        let sorted = "{\"\":1e-7,\"1\":100,\"10\":1e+21,\"\u{1f600}\":2,\"\u{fb33}\":1}";
+        assert_eq!(jcs(&keys), sorted);
+    }
+
+    #[cfg(feature = "serde_snap")]
+    #[test]
+    fn snap_records_depends_on_order() {
+        let forward = snap_records([("created", 1), ("deleted", 2)]).unwrap();
+        let backward = snap_records([("deleted", 2), ("created", 1)]).unwrap();
+        assert_ne!(forward, backward);
+        let framed = frame(&[br#"["created",1]"#, br#"["deleted",2]"#]);
+        assert_eq!(forward, framed.snap());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn snap_canonical_ignores_field_order() {
+        #[derive(serde::Serialize)]
+        struct Forward {
+            alpha: u32,
+            beta: Vec<&'static str>,
+        }
+        #[derive(serde::Serialize)]
+        struct Backward {
+            beta: Vec<&'static str>,
+            alpha: u32,
+        }
+        let forward = Forward {
+            alpha: 1,
+            beta: vec!["x"],
+        };
+        let backward = Backward {
+            beta: vec!["x"],
+            alpha: 1,
+        };
+        assert_eq!(
+            snap_canonical(&forward).unwrap(),
+            snap_canonical(&backward).unwrap()
+        );
+        #[derive(serde::Serialize)]
+        struct Short {
+            b: u8,
+            a: u8,
+        }
+        let encoded = canonical_bytes(&Short { b: 1, a: 2 }).unwrap();
+        assert_eq!(encoded, [0xa2, 0x61, b'a', 0x02, 0x61, b'b', 0x01]);
+        let changed = Backward {
+            beta: vec!["y"],
+            alpha: 1,
+        };
+        assert_ne!(
+            snap_canonical(&forward).unwrap(),
+            snap_canonical(&changed).unwrap()
+        );
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn canonical_bytes_of_equal_values() {
+        use std::collections::{BTreeMap, HashMap};
+        #[derive(serde::Serialize)]
+        struct Limits {
+            soft: u64,
+            hard: u64,
+        }
+        let hashed: HashMap<_, _> = (0..16).map(|i| (format!("k{}", i), i as u64)).collect();
+        let sorted: BTreeMap<_, _> = hashed.clone().into_iter().collect();
+        assert_eq!(
+            canonical_bytes(&hashed).unwrap(),
+            canonical_bytes(&sorted).unwrap()
+        );
+        let limits = Limits { soft: 1, hard: 2 };
+        let as_map = BTreeMap::from([("hard", 2u64), ("soft", 1)]);
+        let bytes = canonical_bytes(&limits).unwrap();
+        assert_eq!(bytes, canonical_bytes(&as_map).unwrap());
+        assert_eq!(snap_canonical(&limits).unwrap(), bytes.snap());
+    }
+
+    #[test]
+    fn snap_path_dispatches() {
+        let root = PathBuf::from("test_sha_snap_snap_path");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        let file = snap_path(&root.join("a.txt"));
+        let dir = snap_path(&root);
+        let merkle = build_merkle(&root);
+        let missing = snap_path(&root.join("missing"));
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(file.unwrap(), "a".snap());
+        assert_eq!(dir.unwrap(), merkle.unwrap().digest.to_hex());
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn merkle_rehashes_only_ancestors() {
+        let root = PathBuf::from("test_sha_snap_merkle");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub").join("b.txt"), "b").unwrap();
+        fs::write(root.join("sub").join("c.txt"), "c").unwrap();
+        let before = build_merkle(&root).unwrap();
+        fs::write(root.join("sub").join("b.txt"), "b2").unwrap();
+        let after = build_merkle(&root).unwrap();
+        let mut rehashed = before.clone();
+        rehashed.rehash(&root.join("sub").join("b.txt")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let [a0, sub0] = &before.children[..] else {
+            panic!()
+        };
+        let [a1, sub1] = &after.children[..] else {
+            panic!()
+        };
+        assert_ne!(before.digest, after.digest);
+        assert_eq!(a0.digest, a1.digest);
+        assert_ne!(sub0.digest, sub1.digest);
+        assert_ne!(sub0.children[0].digest, sub1.children[0].digest);
+        assert_eq!(sub0.children[1].digest, sub1.children[1].digest);
+        assert_eq!(rehashed, after);
+        assert_eq!(before.diff(&after), [Path::new("sub").join("b.txt")]);
+    }
+
+    #[test]
+    fn diff_dirs_classifies_changes() {
+        let (a, b) = (
+            PathBuf::from("test_sha_snap_diff_a"),
+            PathBuf::from("test_sha_snap_diff_b"),
+        );
+        for root in [&a, &b] {
+            let _ = fs::remove_dir_all(root);
+            fs::create_dir_all(root.join("sub")).unwrap();
+            fs::write(root.join("same.txt"), "same").unwrap();
+            fs::write(root.join("sub").join("edit.txt"), "old").unwrap();
+        }
+        fs::write(b.join("sub").join("edit.txt"), "new").unwrap();
+        fs::write(b.join("sub").join("new.txt"), "new").unwrap();
+        let diff = diff_dirs(&a, &b);
+        fs::remove_dir_all(&a).unwrap();
+        fs::remove_dir_all(&b).unwrap();
+        assert_eq!(
+            diff.unwrap(),
+            DirDiff {
+                added: vec![Path::new("sub").join("new.txt")],
+                removed: vec![],
+                changed: vec![Path::new("sub").join("edit.txt")],
+            }
+        );
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let root = PathBuf::from("test_sha_snap_manifest");
+        let manifest = PathBuf::from("test_sha_snap_manifest.SHA512SUMS");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub").join("b.txt"), "b").unwrap();
+        write_manifest(&root, &manifest).unwrap();
+        let text = fs::read_to_string(&manifest).unwrap();
+        let clean = verify_manifest(&root, &manifest);
+        fs::write(root.join("sub").join("b.txt"), "tampered").unwrap();
+        let tampered = verify_manifest(&root, &manifest);
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(&manifest).unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "{}  a.txt\n{}  sub/b.txt\n",
+                snap_slice(b"a"),
+                snap_slice(b"b")
+            )
+        );
+        assert_eq!(clean.unwrap(), []);
+        assert_eq!(
+            tampered.unwrap(),
+            [Mismatch::Changed(PathBuf::from("sub/b.txt"))]
+        );
+    }
+
+    #[test]
+    fn merkle_progress_counts_files() {
+        let root = PathBuf::from("test_sha_snap_merkle_progress");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.txt"), "b").unwrap();
+        fs::write(root.join("sub/c.txt"), "c").unwrap();
+        let mut calls = Vec::new();
+        let tree = build_merkle_progress(&root, |path, index, total| {
+            calls.push((path.to_path_buf(), index, total))
+        });
+        let plain = build_merkle(&root);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(tree.unwrap(), plain.unwrap());
+        assert_eq!(
+            calls,
+            [
+                (root.join("a.txt"), 1, 3),
+                (root.join("sub/b.txt"), 2, 3),
+                (root.join("sub/c.txt"), 3, 3),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn merkle_symlink_loop() {
+        let root = PathBuf::from("test_sha_snap_merkle_loop");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink("..", root.join("sub/up")).unwrap();
+        // a link to a sibling directory isn't a loop
+        std::os::unix::fs::symlink("sub", root.join("alias")).unwrap();
+        let strict = build_merkle(&root);
+        let lenient = build_merkle_with(&root, WalkPolicy::SkipUnreadable);
+        let path = snap_path(&root);
+        let progress = build_merkle_progress(&root, |_, _, _| {});
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(strict.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(path.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(progress.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let (tree, mut skipped) = lenient.unwrap();
+        skipped.sort();
+        assert_eq!(skipped, [root.join("alias/up"), root.join("sub/up")]);
+        assert_eq!(tree.children.len(), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn duplicates_grouped_by_content() {
+        let root = PathBuf::from("test_sha_snap_duplicates");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "same").unwrap();
+        fs::write(root.join("sub/b.txt"), "same").unwrap();
+        fs::write(root.join("unique.txt"), "unique").unwrap();
+        std::os::unix::fs::symlink("missing", root.join("broken")).unwrap();
+        std::os::unix::fs::symlink("..", root.join("sub/up")).unwrap();
+        let duplicates = find_duplicates(&root);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            duplicates.unwrap(),
+            [[root.join("a.txt"), root.join("sub/b.txt")]]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn merkle_walk_policy() {
+        let root = PathBuf::from("test_sha_snap_merkle_policy");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        // a dangling symlink can't be opened, even by root
+        std::os::unix::fs::symlink("missing", root.join("broken")).unwrap();
+        let strict = build_merkle_with(&root, WalkPolicy::FailFast);
+        let lenient = build_merkle_with(&root, WalkPolicy::SkipUnreadable);
+        fs::remove_file(root.join("broken")).unwrap();
+        let clean = build_merkle(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(strict.is_err());
+        let (tree, skipped) = lenient.unwrap();
+        assert_eq!(skipped, [root.join("broken")]);
+        assert_eq!(tree.digest, clean.digest);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn merkle_rehash_keeps_policy() {
+        let root = PathBuf::from("test_sha_snap_merkle_rehash_policy");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink("missing", root.join("broken")).unwrap();
+        let (tree, _) = build_merkle_with(&root, WalkPolicy::SkipUnreadable).unwrap();
+        // a new entry makes the root's listing stale, so the whole root is re-read
+        fs::write(root.join("b.txt"), "b").unwrap();
+        let strict = tree.clone().rehash(&root.join("b.txt"));
+        let mut lenient = tree.clone();
+        let skipped = lenient.rehash_with(&root.join("b.txt"), WalkPolicy::SkipUnreadable);
+        let (rebuilt, _) = build_merkle_with(&root, WalkPolicy::SkipUnreadable).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(strict.is_err());
+        assert_eq!(skipped.unwrap(), [root.join("broken")]);
+        assert_eq!(lenient, rebuilt);
+    }
+}
@@ -0,0 +1,32 @@
+//! Uniquely named temp files, for writers that stage content beside its target and
+//! rename it into place
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Tells apart the temp files created within this process, across threads
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Creates a new, empty `<dir>/<prefix>.<pid>.<n>.tmp` file, unique among processes by
+/// the pid and among threads by the counter
+///
+/// The file is opened with `create_new`, so a file left over by an earlier process with
+/// the same pid is skipped rather than clobbered. It is not removed automatically.
+pub(crate) fn create_unique(dir: &Path, prefix: &str) -> io::Result<(fs::File, PathBuf)> {
+    loop {
+        let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("{}.{}.{}.tmp", prefix, std::process::id(), n));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => Err(e)?,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{FileIO, SerdeStr};
+use std::io;
+
+/// Semantic checks beyond what deserialization enforces, e.g. a port in range
+pub trait Validate {
+    /// Every problem found, as messages for the user; `Ok` if there are none
+    fn validate(&self) -> Result<(), Vec<String>>;
+}
+
+impl<T> FileIO<T>
+where
+    T: SerdeStr + Validate,
+{
+    /// Loads the file and validates it, failing with `InvalidData` listing every problem
+    pub fn load_validated(&self) -> io::Result<T> {
+        let conf = self.load()?;
+        if let Err(problems) = conf.validate() {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is invalid: {}",
+                    self.path.display(),
+                    problems.join("; ")
+                ),
+            ))?;
+        }
+        Ok(conf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::{fs, path::PathBuf};
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct Conf {
+        pub name: String,
+        pub port: u16,
+    }
+    impl SerdeStr for Conf {
+        fn de_from_str(string: &str) -> Result<Self, io::Error> {
+            Ok(serde_json::from_str(string)?)
+        }
+        fn ser_to_string(&self) -> Result<String, io::Error> {
+            Ok(serde_json::to_string(self)?)
+        }
+    }
+    impl Validate for Conf {
+        fn validate(&self) -> Result<(), Vec<String>> {
+            let mut problems = Vec::new();
+            if self.name.is_empty() {
+                problems.push("name must not be empty".to_string());
+            }
+            if self.port < 1024 {
+                problems.push(format!("port {} is privileged", self.port));
+            }
+            problems.is_empty().then_some(()).ok_or(problems)
+        }
+    }
+
+    #[test]
+    fn load_validated_collects_problems() {
+        let path = PathBuf::from("test_validate_collects_problems.json");
+        let file_io = FileIO::<Conf>::new(path.clone());
+        fs::write(&path, r#"{"name":"app","port":8080}"#).unwrap();
+        let valid = file_io.load_validated();
+        fs::write(&path, r#"{"name":"","port":80}"#).unwrap();
+        let invalid = file_io.load_validated();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(valid.unwrap().port, 8080);
+        let invalid = invalid.unwrap_err();
+        assert_eq!(invalid.kind(), io::ErrorKind::InvalidData);
+        assert!(invalid
+            .to_string()
+            .ends_with("is invalid: name must not be empty; port 80 is privileged"));
+    }
+}
@@ -0,0 +1,538 @@
+use crate::{FileIO, SerdeStr};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Quiet period used to coalesce a burst of file system events into a single reload
+pub const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Keeps a watch alive; dropping it stops the watcher and its reload thread
+pub struct WatchGuard {
+    _watcher: RecommendedWatcher,
+}
+
+/// Watches `dir` and hands every debounced burst of events to `on_batch` on a worker thread
+pub(crate) fn watch_debounced(
+    dir: &Path,
+    mode: RecursiveMode,
+    mut on_batch: impl FnMut(Vec<Event>) + Send + 'static,
+) -> io::Result<WatchGuard> {
+    let (tx, rx) = mpsc::channel::<Event>();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) => {
+            let _ = tx.send(event);
+        }
+        Err(e) => log::warn!("file watcher error: {}", e),
+    })
+    .map_err(io::Error::other)?;
+    watcher.watch(dir, mode).map_err(io::Error::other)?;
+    // the thread ends once the watcher, and with it the sender, is dropped
+    thread::spawn(move || {
+        while let Ok(first) = rx.recv() {
+            let mut batch = vec![first];
+            while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+                batch.push(event);
+            }
+            on_batch(batch);
+        }
+    });
+    Ok(WatchGuard { _watcher: watcher })
+}
+
+/// A change to an entry of a watched directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigEvent {
+    Created(PathBuf),
+    Modified(PathBuf),
+    Removed(PathBuf),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Created,
+    Modified,
+    Removed,
+}
+
+fn changes(event: Event) -> Vec<(Change, PathBuf)> {
+    let change = match event.kind {
+        EventKind::Access(_) => return Vec::new(),
+        EventKind::Create(_) => Change::Created,
+        EventKind::Remove(_) => Change::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Change::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Change::Created,
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            let mut paths = event.paths.into_iter();
+            let from = paths.next().expect("two paths");
+            let to = paths.next().expect("two paths");
+            return vec![(Change::Removed, from), (Change::Created, to)];
+        }
+        _ => Change::Modified,
+    };
+    event.paths.into_iter().map(|path| (change, path)).collect()
+}
+
+/// Folds a burst of events into at most one net event per path, in order of first appearance
+fn coalesce(events: Vec<Event>) -> Vec<ConfigEvent> {
+    let mut seen: Vec<(PathBuf, Change, Change)> = Vec::new();
+    for (change, path) in events.into_iter().flat_map(changes) {
+        match seen.iter_mut().find(|(p, _, _)| *p == path) {
+            Some((_, _, last)) => *last = change,
+            None => seen.push((path, change, change)),
+        }
+    }
+    seen.into_iter()
+        .filter_map(|(path, first, last)| match (first, last) {
+            // appeared and vanished again within the burst
+            (Change::Created, Change::Removed) => None,
+            (Change::Created, _) => Some(ConfigEvent::Created(path)),
+            (_, Change::Removed) => Some(ConfigEvent::Removed(path)),
+            // including removed and recreated
+            _ => Some(ConfigEvent::Modified(path)),
+        })
+        .collect()
+}
+
+/// Reports every created, modified or removed entry below `dir`, debounced per burst
+pub fn watch_dir(
+    dir: &Path,
+    mut on_event: impl FnMut(ConfigEvent) + Send + 'static,
+) -> io::Result<WatchGuard> {
+    std::fs::create_dir_all(dir)?;
+    watch_debounced(dir, RecursiveMode::Recursive, move |events| {
+        for event in coalesce(events) {
+            on_event(event);
+        }
+    })
+}
+
+fn touches(event: &Event, target: &Path) -> bool {
+    !matches!(event.kind, EventKind::Access(_)) && event.paths.iter().any(|p| p == target)
+}
+
+impl<T> FileIO<T>
+where
+    T: SerdeStr + Send + 'static,
+{
+    fn watch_target(&self) -> io::Result<(PathBuf, PathBuf)> {
+        self.ensure_parent()?;
+        let target = std::path::absolute(&self.path)?;
+        let parent = target
+            .parent()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The path has no parent directory",
+                )
+            })?
+            .to_path_buf();
+        Ok((target, parent))
+    }
+    /// Calls `on_change` with the reloaded content whenever the file changes
+    ///
+    /// The parent directory is watched so that editors saving by rename are noticed,
+    /// and events arriving within [`DEBOUNCE`] of each other trigger a single reload.
+    pub fn watch(
+        &self,
+        mut on_change: impl FnMut(io::Result<T>) + Send + 'static,
+    ) -> io::Result<WatchGuard> {
+        let (target, parent) = self.watch_target()?;
+        let file_io = self.clone();
+        watch_debounced(&parent, RecursiveMode::NonRecursive, move |events| {
+            if events.iter().any(|event| touches(event, &target)) {
+                on_change(file_io.load());
+            }
+        })
+    }
+    /// [`FileIO::watch`] that first calls `on_change` with the current content, on the
+    /// calling thread before returning
+    ///
+    /// The watcher is already running by then, and reloads are held back until the initial
+    /// call is done, so no change slips between the first load and the first event.
+    pub fn watch_with_initial(
+        &self,
+        on_change: impl FnMut(io::Result<T>) + Send + 'static,
+    ) -> io::Result<WatchGuard> {
+        let on_change = Arc::new(Mutex::new(on_change));
+        let mut initial = on_change.lock().unwrap_or_else(|e| e.into_inner());
+        let shared = on_change.clone();
+        let guard =
+            self.watch(move |res| (shared.lock().unwrap_or_else(|e| e.into_inner()))(res))?;
+        initial(self.load());
+        Ok(guard)
+    }
+    /// Calls `on_good` only with reloads that parse, so a broken edit leaves the caller
+    /// on its last good value; failures are logged as warnings
+    pub fn watch_valid(&self, on_good: impl FnMut(T) + Send + 'static) -> io::Result<WatchGuard> {
+        let path = self.path.clone();
+        self.watch_valid_or(on_good, move |e| {
+            log::warn!("keeping previous {}: {}", path.display(), e)
+        })
+    }
+    /// [`FileIO::watch_valid`] reporting failed reloads to `on_error` instead of the log
+    pub fn watch_valid_or(
+        &self,
+        mut on_good: impl FnMut(T) + Send + 'static,
+        mut on_error: impl FnMut(io::Error) + Send + 'static,
+    ) -> io::Result<WatchGuard> {
+        self.watch(move |res| match res {
+            Ok(conf) => on_good(conf),
+            Err(e) => on_error(e),
+        })
+    }
+    /// [`FileIO::watch`] that skips the reload when the file's [`snap`](crate::ShaSnap::snap)
+    /// digest is unchanged since the last one, e.g. after an editor rewrote it identically
+    #[cfg(feature = "sha_snap")]
+    pub fn watch_distinct(
+        &self,
+        mut on_change: impl FnMut(io::Result<T>) + Send + 'static,
+    ) -> io::Result<WatchGuard> {
+        use crate::ShaSnap;
+        let (target, parent) = self.watch_target()?;
+        let file_io = self.clone();
+        let digest = |target: &Path| std::fs::read(target).ok().map(|bytes| bytes.snap_digest());
+        let mut last = digest(&target);
+        watch_debounced(&parent, RecursiveMode::NonRecursive, move |events| {
+            if !events.iter().any(|event| touches(event, &target)) {
+                return;
+            }
+            let current = digest(&target);
+            if current != last {
+                last = current;
+                on_change(file_io.load());
+            }
+        })
+    }
+    /// Like `tail -f` for a file of one record per line, such as NDJSON: calls `on_record`
+    /// with each line appended from now on, parsed as `T`
+    ///
+    /// A line written in pieces is held back until its newline arrives, and blank lines are
+    /// skipped. A file that shrinks is taken as truncated or rotated and followed from its
+    /// start again.
+    pub fn follow(
+        &self,
+        mut on_record: impl FnMut(io::Result<T>) + Send + 'static,
+    ) -> io::Result<WatchGuard> {
+        let (target, parent) = self.watch_target()?;
+        let mut offset = match std::fs::metadata(&target) {
+            Ok(meta) => meta.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => Err(e)?,
+        };
+        let mut partial = Vec::new();
+        watch_debounced(&parent, RecursiveMode::NonRecursive, move |events| {
+            if !events.iter().any(|event| touches(event, &target)) {
+                return;
+            }
+            match read_appended(&target, &mut offset, &mut partial) {
+                Ok(lines) => lines.into_iter().for_each(&mut on_record),
+                Err(e) => on_record(Err(e)),
+            }
+        })
+    }
+}
+
+/// Reads what was appended to `path` past `offset` and parses the lines it completes,
+/// keeping an unfinished last line in `partial`
+fn read_appended<T: SerdeStr>(
+    path: &Path,
+    offset: &mut u64,
+    partial: &mut Vec<u8>,
+) -> io::Result<Vec<io::Result<T>>> {
+    use std::io::{Read, Seek};
+    let mut file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        // removed, e.g. mid-rotation; the new file is read from its start
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            *offset = 0;
+            partial.clear();
+            return Ok(Vec::new());
+        }
+        Err(e) => Err(e)?,
+    };
+    if file.metadata()?.len() < *offset {
+        *offset = 0;
+        partial.clear();
+    }
+    file.seek(io::SeekFrom::Start(*offset))?;
+    *offset += file.read_to_end(partial)? as u64;
+    let Some(end) = partial.iter().rposition(|&b| b == b'\n') else {
+        return Ok(Vec::new());
+    };
+    let rest = partial.split_off(end + 1);
+    let complete = std::mem::replace(partial, rest);
+    let records = complete
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .map(|line| match std::str::from_utf8(line) {
+            Ok(line) => T::de_from_str(line),
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        })
+        .collect();
+    Ok(records)
+}
+
+#[cfg(feature = "tokio")]
+mod stream {
+    use super::*;
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use tokio::sync::mpsc as async_mpsc;
+
+    /// A [`Stream`](futures_core::Stream) of reloads; dropping it stops the watcher
+    pub struct WatchStream<T> {
+        rx: async_mpsc::UnboundedReceiver<io::Result<T>>,
+        _guard: WatchGuard,
+    }
+
+    impl<T> WatchStream<T> {
+        pub async fn next(&mut self) -> Option<io::Result<T>> {
+            self.rx.recv().await
+        }
+    }
+
+    impl<T> futures_core::Stream for WatchStream<T> {
+        type Item = io::Result<T>;
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.rx.poll_recv(cx)
+        }
+    }
+
+    impl<T> FileIO<T>
+    where
+        T: SerdeStr + Send + 'static,
+    {
+        /// Async flavor of [`FileIO::watch`], yielding one item per debounced reload
+        pub fn watch_stream(&self) -> io::Result<WatchStream<T>> {
+            let (tx, rx) = async_mpsc::unbounded_channel();
+            let guard = self.watch(move |res| {
+                let _ = tx.send(res);
+            })?;
+            Ok(WatchStream { rx, _guard: guard })
+        }
+        /// Resolves with the content once, on the next debounced change, e.g. to await a
+        /// config change at a single decision point; the watcher stops right after
+        ///
+        /// The watcher starts when the future is first polled, so earlier changes are missed.
+        pub async fn next_reload(&self) -> io::Result<T> {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            let mut tx = Some(tx);
+            let _guard = self.watch(move |res| {
+                if let Some(tx) = tx.take() {
+                    let _ = tx.send(res);
+                }
+            })?;
+            rx.await.map_err(io::Error::other)?
+        }
+    }
+}
+#[cfg(feature = "tokio")]
+pub use stream::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::fs;
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    struct Conf {
+        pub name: String,
+    }
+    impl SerdeStr for Conf {
+        fn de_from_str(string: &str) -> Result<Self, io::Error> {
+            Ok(serde_json::from_str(string)?)
+        }
+        fn ser_to_string(&self) -> Result<String, io::Error> {
+            Ok(serde_json::to_string(self)?)
+        }
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn watch_config_dir_reports_created() {
+        crate::project_info::hermetic_root();
+        use crate::{AppAuthor, ProjectInfo};
+        struct WatchApp;
+        impl AppAuthor for WatchApp {
+            fn app_name() -> &'static str {
+                "sculptor-test-watch-dir"
+            }
+            fn author() -> &'static str {
+                "sculptor"
+            }
+        }
+        let (tx, rx) = mpsc::channel();
+        let guard = WatchApp::watch_config_dir(move |event| {
+            let _ = tx.send(event);
+        })
+        .unwrap();
+        thread::sleep(DEBOUNCE);
+        let plugin = WatchApp::config_dir().join("plugin.toml");
+        fs::write(&plugin, "").unwrap();
+        let event = rx.recv_timeout(Duration::from_secs(5));
+        drop(guard);
+        fs::remove_dir_all(WatchApp::config_dir()).unwrap();
+        assert_eq!(event.unwrap(), ConfigEvent::Created(plugin));
+    }
+
+    #[test]
+    fn watch_reloads_on_change() {
+        let path = PathBuf::from("test_watch_reloads_on_change.json");
+        fs::write(&path, r#"{"name":"old"}"#).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let guard = FileIO::<Conf>::new(path.clone())
+            .watch(move |res| {
+                let _ = tx.send(res.map(|conf| conf.name));
+            })
+            .unwrap();
+        thread::sleep(DEBOUNCE);
+        fs::write(&path, r#"{"name":"new"}"#).unwrap();
+        let name = rx.recv_timeout(Duration::from_secs(5));
+        drop(guard);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(name.unwrap().unwrap(), "new");
+    }
+
+    #[test]
+    fn follow_emits_appended_records() {
+        use std::io::Write;
+        let path = PathBuf::from("test_watch_follow.ndjson");
+        fs::write(&path, "{\"name\":\"before\"}\n").unwrap();
+        let (tx, rx) = mpsc::channel();
+        let guard = FileIO::<Conf>::new(path.clone())
+            .follow(move |res| {
+                let _ = tx.send(res.map(|conf| conf.name));
+            })
+            .unwrap();
+        thread::sleep(DEBOUNCE);
+        let mut log = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        log.write_all(b"{\"name\":\"first\"}\n{\"name\":").unwrap();
+        log.flush().unwrap();
+        thread::sleep(DEBOUNCE * 3);
+        log.write_all(b"\"second\"}\n").unwrap();
+        drop(log);
+        let names: Vec<_> = (0..2)
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)))
+            .collect();
+        let extra = rx.recv_timeout(DEBOUNCE * 3);
+        drop(guard);
+        fs::remove_file(&path).unwrap();
+        let names: Vec<_> = names
+            .into_iter()
+            .map(|name| name.unwrap().unwrap())
+            .collect();
+        assert_eq!(names, ["first", "second"]);
+        assert!(extra.is_err());
+    }
+
+    #[test]
+    fn watch_with_initial_fires_at_startup() {
+        let path = PathBuf::from("test_watch_with_initial_fires_at_startup.json");
+        fs::write(&path, r#"{"name":"old"}"#).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let guard = FileIO::<Conf>::new(path.clone())
+            .watch_with_initial(move |res| {
+                let _ = tx.send(res.map(|conf| conf.name));
+            })
+            .unwrap();
+        let initial = rx.try_recv();
+        thread::sleep(DEBOUNCE);
+        fs::write(&path, r#"{"name":"new"}"#).unwrap();
+        let name = rx.recv_timeout(Duration::from_secs(5));
+        drop(guard);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(initial.unwrap().unwrap(), "old");
+        assert_eq!(name.unwrap().unwrap(), "new");
+    }
+
+    #[test]
+    fn watch_valid_skips_invalid_edit() {
+        let path = PathBuf::from("test_watch_valid_skips_invalid_edit.json");
+        fs::write(&path, r#"{"name":"old"}"#).unwrap();
+        let (good_tx, good_rx) = mpsc::channel();
+        let (bad_tx, bad_rx) = mpsc::channel();
+        let guard = FileIO::<Conf>::new(path.clone())
+            .watch_valid_or(
+                move |conf| {
+                    let _ = good_tx.send(conf.name);
+                },
+                move |e| {
+                    let _ = bad_tx.send(e.kind());
+                },
+            )
+            .unwrap();
+        thread::sleep(DEBOUNCE);
+        fs::write(&path, r#"{"name":"#).unwrap();
+        let bad = bad_rx.recv_timeout(Duration::from_secs(5));
+        let good = good_rx.try_recv();
+        drop(guard);
+        fs::remove_file(&path).unwrap();
+        assert!(bad.is_ok());
+        assert!(good.is_err());
+    }
+
+    #[cfg(feature = "sha_snap")]
+    #[test]
+    fn watch_distinct_skips_identical_rewrite() {
+        let path = PathBuf::from("test_watch_distinct_skips_identical_rewrite.json");
+        fs::write(&path, r#"{"name":"old"}"#).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let guard = FileIO::<Conf>::new(path.clone())
+            .watch_distinct(move |res| {
+                let _ = tx.send(res.map(|conf| conf.name));
+            })
+            .unwrap();
+        thread::sleep(DEBOUNCE);
+        fs::write(&path, r#"{"name":"old"}"#).unwrap();
+        let spurious = rx.recv_timeout(DEBOUNCE * 5);
+        fs::write(&path, r#"{"name":"new"}"#).unwrap();
+        let name = rx.recv_timeout(Duration::from_secs(5));
+        drop(guard);
+        fs::remove_file(&path).unwrap();
+        assert!(spurious.is_err());
+        assert_eq!(name.unwrap().unwrap(), "new");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn watch_stream_yields_one_reload() {
+        let path = PathBuf::from("test_watch_stream_yields_one_reload.json");
+        fs::write(&path, r#"{"name":"old"}"#).unwrap();
+        let mut stream = FileIO::<Conf>::new(path.clone()).watch_stream().unwrap();
+        tokio::time::sleep(DEBOUNCE).await;
+        fs::write(&path, r#"{"name":"new"}"#).unwrap();
+        let first = tokio::time::timeout(Duration::from_secs(5), stream.next()).await;
+        let second = tokio::time::timeout(DEBOUNCE * 5, stream.next()).await;
+        drop(stream);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(first.unwrap().unwrap().unwrap().name, "new");
+        assert!(second.is_err());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn next_reload_resolves_on_change() {
+        let path = PathBuf::from("test_watch_next_reload.json");
+        fs::write(&path, r#"{"name":"old"}"#).unwrap();
+        let file_io = FileIO::<Conf>::new(path.clone());
+        let reload = file_io.next_reload();
+        tokio::pin!(reload);
+        let before = tokio::time::timeout(DEBOUNCE * 3, &mut reload).await;
+        fs::write(&path, r#"{"name":"new"}"#).unwrap();
+        let after = tokio::time::timeout(Duration::from_secs(5), &mut reload).await;
+        fs::remove_file(&path).unwrap();
+        assert!(before.is_err());
+        assert_eq!(after.unwrap().unwrap().name, "new");
+    }
+}
@@ -0,0 +1,27 @@
+//! Runs in its own process, as the override is process-wide
+
+use sculptor::{AppAuthor, ProjectInfo};
+use std::path::PathBuf;
+
+struct OverrideApp;
+impl AppAuthor for OverrideApp {
+    fn app_name() -> &'static str {
+        "sculptor-test-override"
+    }
+    fn author() -> &'static str {
+        "sculptor"
+    }
+}
+
+#[test]
+fn override_root_redirects_dirs() {
+    let root = PathBuf::from("fake-root");
+    sculptor::set_override_root(root.clone());
+    let config = OverrideApp::config_dir();
+    let cache = OverrideApp::cache_dir();
+    sculptor::clear_override_root();
+    assert!(config.starts_with(root.join("config")));
+    assert!(config.ends_with(OverrideApp::project_path()));
+    assert!(cache.starts_with(root.join("cache")));
+    assert!(!OverrideApp::config_dir().starts_with(&root));
+}